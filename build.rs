@@ -1,108 +1,153 @@
 // following https://doc.rust-lang.org/cargo/reference/build-scripts.html
 use std::env;
-//use std::io::Write;         //needed for debugging
-//use std::path::PathBuf;     //needed for one approach
-//use std::fs;                //needed for one approach
 
-fn main() {
-    // This file (build.rs) needs to be in the package root.
-    // It is called before the linker and used to arrange for the linker to find the
-    // proper memory.x file for the MCU. The memory.x files are assumed to be in a
-    // directory memoryMaps/xxx/  where xxx is replaced by an one of
-
-    let mcus = [
-        "STM32F042",
-        "STM32F030XC",
-        "STM32F100",
-        "STM32F101",
-        "STM32F103",
-        "STM32F303XC",
-        "STM32F401",
-        "STM32F411",
-        "STM32F722",
-        "STM32H742",
-        "STM32L0X2",
-        "STM32L100",
-        "STM32L151",
-        "STM32L4X2",
-        "LM3S6965",
-        "GD32VF103CB",
-        "GD32VF103C8",
-        "GD32VF103_EXTRA",
-    ];
-
-    // For example,   memoryMaps/STM32F401/memory.x
-    // Note that the MCU string must be in upper case because it is also used to
-    // find the  CARGO_FEATURE_xxx environment variable (eg CARGO_FEATURE_STM32F401)
-    // which is in upper case.
-
-    // There are two possible appraches: one is to copy the appropriate memory.x file into the OUT_DIR
-    // where compiled pieces are placed for linking, then add that dir to the linker search path.
-    // (Adding to path may be redundant, the OUT_DIR is probably already in the search path.)
-    // The second approach is to simply add the location of the appropriate memory.x file to
-    // the search path. This is not only quicker, but has the advantage that the memory.x file
-    // can be MCU specific. In the copy appraoch the OUT_DIR is only MCU triple specific, so there
-    // could be conflicts (in the unlikely situation) where two mcu's have the same triple and
-    // different memory layouts.
-
-    // The memoryNote.txt file is just to record some debugging information
-    //let mut df = std::fs::File::create("memoryNote.txt").unwrap();
-
-    // It is assumed that only one MCU feature will be specified. If there are more then
-    // only the first is found (but actual code may be a mess if cargo really lets you do that).
-
-    let pre = "CARGO_FEATURE_".to_owned();
-
-    // For debugging. Write all CARGO_FEATURE_  mcu variables to file
-    //for m in &mcus {
-    //   match env::var_os(pre.clone() + m) {
-    //      None    => df.write(format!("{}{} is not set\n", pre, m).as_bytes()).unwrap(),
-    //      Some(x) => df.write(format!("{}{} is {:?}\n", pre, m, x).as_bytes()).unwrap()
-    //      };
-    //   };
-
-    // For debugging. Write all env variables to file
-    //df.write(format!("env::vars() gives\n").as_bytes()).unwrap();
-    //for (key, value) in env::vars() {
-    //     df.write(format!("   {:?}: {:?}\n", key, value).as_bytes()).unwrap();
-    //     };
-
-    // Compare mcus elements against CARGO_FEATURE_* env variables to determine directory of
-    // memory.x file to use.
-    // If there is no MCU feature identified then the usual default is that memory.x is
-    // searched for in the package root.
+// This file (build.rs) needs to be in the package root.
+// It is called before the linker and used to arrange for the linker to find the
+// proper memory.x file for the MCU. The memory.x files are in a directory memoryMaps/xxx/
+// for each MCU feature in Cargo.toml, eg  memoryMaps/STM32F401/memory.x  for feature stm32f401.
+
+// (MCU feature, HAL feature it selects in Cargo.toml, memoryMaps directory)
+// Every MCU feature in Cargo.toml needs an entry here.
+const MCUS: &[(&str, &str, &str)] = &[
+    ("stm32f030xc", "stm32f0xx", "STM32F030XC"),
+    ("stm32f042", "stm32f0xx", "STM32F042"),
+    ("stm32f100", "stm32f1xx", "STM32F100"),
+    ("stm32f101", "stm32f1xx", "STM32F101"),
+    ("stm32f103", "stm32f1xx", "STM32F103"),
+    ("stm32f303xc", "stm32f3xx", "STM32F303XC"),
+    ("stm32f401", "stm32f4xx", "STM32F401"),
+    ("stm32f405", "stm32f4xx", "STM32F405"),
+    ("stm32f411", "stm32f4xx", "STM32F411"),
+    ("stm32f722", "stm32f7xx", "STM32F722"),
+    ("stm32h742", "stm32h7xx", "STM32H742"),
+    ("stm32l0x2", "stm32l0xx", "STM32L0X2"),
+    ("stm32l100", "stm32l1xx", "STM32L100"),
+    ("stm32l151", "stm32l1xx", "STM32L151"),
+    ("stm32l422", "stm32l4xx", "STM32L422"),
+    ("stm32l471", "stm32l4xx", "STM32L471"),
+    ("stm32l486", "stm32l4xx", "STM32L486"),
+    ("stm32wle5", "stm32wlxx", "STM32WLE5"),
+    ("gd32vf103cb", "gd32vf103xx", "GD32VF103CB"),
+    ("gd32vf103c8", "gd32vf103xx", "GD32VF103C8"),
+    ("nrf52840", "nrf52840", "NRF52840"),
+    ("rp2040", "rp2040", "RP2040"),
+    ("lm3s6965", "lm3s6965", "LM3S6965"),
+];
+
+// (board feature, HAL feature of its MCU), see src/board.rs. Every board feature in Cargo.toml
+// needs an entry here.
+const BOARDS: &[(&str, &str)] = &[
+    ("board-bluepill", "stm32f1xx"),
+    ("board-discovery-stm32f303", "stm32f3xx"),
+    ("board-blackpill-stm32f401", "stm32f4xx"),
+    ("board-blackpill-stm32f411", "stm32f4xx"),
+    ("board-nucleo-f401", "stm32f4xx"),
+    ("board-nucleo-f411", "stm32f4xx"),
+    ("board-heltec-lora-node151", "stm32l1xx"),
+    ("board-discovery-stm32l100", "stm32l1xx"),
+];
+
+// true if the feature is enabled, eg CARGO_FEATURE_STM32F401 is set for feature stm32f401
+fn enabled(feature: &str) -> bool {
+    let var = "CARGO_FEATURE_".to_owned() + &feature.to_uppercase().replace('-', "_");
+    env::var_os(var).is_some()
+}
 
-    let mut indir: String = "".to_string();
-    let d = "memoryMaps/".to_owned();
-    for m in &mcus {
-        let v = env::var_os(pre.clone() + m);
-        if v.is_some() {
-            indir = d + m;
-            break;
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // defmt needs its own linker script as well as link.x (see .cargo/config)
+    if enabled("log-defmt") {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    };
+
+    // Hopping within a packet is an SX127x feature, the other radios would silently not hop.
+    if enabled("fhss") {
+        if let Some(radio) = ["sx126x", "stm32wlxx"].iter().find(|f| enabled(f)) {
+            panic!(
+                "\n\nfeature fhss needs an SX127x radio, it cannot be used with {}\n\n",
+                radio
+            );
         };
-    }
-
-    // Adding an empty search path causes problems compiling the crate, so skip if memory.x not found.
-    // This allows 'cargo build  --features $MCU ' to work
-    // but do not expect to compile examples. (There will be a 'cannot find linker script memory.x' error.)
-
-    if !indir.is_empty() {
-        //df.write(format!("in mcu found condition.\n").as_bytes()).unwrap();
-        let infile = indir.clone() + "/memory.x";
-
-        // one approach
-        //let outdir  = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
-        //let outfile = &PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("memory.x");
-        //fs::copy(&infile, outfile).unwrap();  // Copy memory.x to OUT_DIR. Possibly should handle error.
-        //println!("cargo:rustc-link-search={}", outdir.display());
-
-        // other approach
-        println!("cargo:rustc-link-search={}", indir);
-
-        println!("cargo:rerun-if-changed=build.rs");
-        println!("cargo:rerun-if-changed={}", infile);
-    } else {
-        //df.write(format!("mcu NOT found condition.\n").as_bytes()).unwrap();
-        println!();
-    }
+    };
+
+    // Checked first, as two boards usually also means two MCU features.
+    let boards: Vec<&str> = BOARDS
+        .iter()
+        .filter(|(board, _)| enabled(board))
+        .map(|(board, _)| *board)
+        .collect();
+    if boards.len() > 1 {
+        panic!(
+            "\n\nonly one board feature can be used, found: {}\n\n",
+            boards.join(", ")
+        );
+    };
+
+    let selected: Vec<_> = MCUS.iter().filter(|(mcu, _, _)| enabled(mcu)).collect();
+
+    // The HAL feature is selected by the MCU feature, so  --features $MCU  is enough. Specifying
+    // a HAL feature as well (--features $HAL,$MCU) is fine but it needs an MCU feature,
+    // as the hal crates and the memory map depend on the specific MCU.
+    let (mcu, hal, dir) = match selected.as_slice() {
+        [one] => **one,
+        [] => {
+            let mut hals: Vec<&str> = MCUS.iter().map(|(_, hal, _)| *hal).collect();
+            hals.dedup();
+            if let Some(hal) = hals.into_iter().find(|hal| enabled(hal)) {
+                let mcus: Vec<&str> = MCUS
+                    .iter()
+                    .filter(|(_, h, _)| *h == hal)
+                    .map(|(mcu, _, _)| *mcu)
+                    .collect();
+                panic!(
+                    "\n\nfeature {} needs an MCU feature, one of: {}\n\n",
+                    hal,
+                    mcus.join(", ")
+                );
+            };
+            // No MCU, eg cargo doc or cargo tree. There is no memory map, so do not expect to
+            // link the binaries (there will be a 'cannot find linker script memory.x' error).
+            return;
+        }
+        several => {
+            let mcus: Vec<&str> = several.iter().map(|(mcu, _, _)| *mcu).collect();
+            panic!(
+                "\n\nonly one MCU feature can be used, found: {}\n\n",
+                mcus.join(", ")
+            );
+        }
+    };
+
+    // Cargo.toml has  mcu = [hal, ...]  so this only fails if an entry above is wrong.
+    if !enabled(hal) {
+        panic!("\n\nMCU feature {} should select feature {}\n\n", mcu, hal);
+    };
+    // eg --features stm32f1xx,stm32f401 would give two setup() functions
+    if let Some((_, other, _)) = MCUS.iter().find(|(_, h, _)| *h != hal && enabled(h)) {
+        panic!(
+            "\n\nfeature {} cannot be used with MCU feature {} (which uses {})\n\n",
+            other, mcu, hal
+        );
+    };
+
+    // Cargo.toml has  board = [mcu]  so, as for the MCU, this only fails if an entry above is wrong.
+    if let Some((board, board_hal)) = BOARDS.iter().find(|(board, _)| enabled(board)) {
+        if *board_hal != hal {
+            panic!(
+                "\n\nboard feature {} is for {} but MCU feature {} uses {}\n\n",
+                board, board_hal, mcu, hal
+            );
+        };
+    };
+
+    // Adding the directory to the linker search path, rather than copying memory.x into OUT_DIR,
+    // keeps the map MCU specific. OUT_DIR is only specific to the target triple, so two MCUs with
+    // the same triple and different memory could conflict.
+    let infile = format!("memoryMaps/{}/memory.x", dir);
+    if !std::path::Path::new(&infile).exists() {
+        panic!("\n\nMCU feature {} has no memory map {}\n\n", mcu, infile);
+    };
+    println!("cargo:rustc-link-search=memoryMaps/{}", dir);
+    println!("cargo:rerun-if-changed={}", infile);
 }
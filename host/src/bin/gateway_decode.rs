@@ -0,0 +1,41 @@
+//! Read gateway_spi output and print each packet decoded, with RSSI and SNR.
+//!
+//!  gateway_decode /dev/ttyUSB0 [baud]
+//!  gateway_decode saved_output.txt
+//!  gateway_decode -            (stdin)
+
+use std::env;
+use std::process;
+
+use lora_gps_host::frame::{describe, parse_line};
+use lora_gps_host::input::{for_each_line, open, DEFAULT_BAUD};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <serial device | file | -> [baud]", args[0]);
+        process::exit(2);
+    };
+    let baud = match args.get(2) {
+        Some(b) => b.parse().unwrap_or_else(|_| {
+            eprintln!("baud must be a number, not {}", b);
+            process::exit(2);
+        }),
+        None => DEFAULT_BAUD,
+    };
+
+    let mut input = open(&args[1], baud).unwrap_or_else(|e| {
+        eprintln!("cannot open {}: {}", args[1], e);
+        process::exit(1);
+    });
+
+    let result = for_each_line(&mut *input, |line| {
+        if let Some(frame) = parse_line(line) {
+            println!("{}", describe(&frame));
+        };
+    });
+    if let Err(e) = result {
+        eprintln!("error reading {}: {}", args[1], e);
+        process::exit(1);
+    };
+}
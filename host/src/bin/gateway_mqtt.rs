@@ -0,0 +1,86 @@
+//! Read gateway_spi output and publish each decoded position, alert and telemetry packet to an
+//! MQTT broker, with a topic per sender (see src/publish.rs).
+//!
+//!  gateway_mqtt /dev/ttyUSB0 [--broker host[:port]] [--qos 0|1|2] [--prefix lora_gps] [--baud 9600]
+//!  gateway_mqtt saved_output.txt --broker localhost
+
+use std::env;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lora_gps_host::frame::{describe, parse_line};
+use lora_gps_host::input::{for_each_line, open, DEFAULT_BAUD};
+use lora_gps_host::publish::{qos, Config, Publisher, DEFAULT_PORT};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "usage: {} <serial device | file | -> [--broker host[:port]] [--qos 0|1|2] [--prefix topic] [--baud n]",
+        program
+    );
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        usage(&args[0]);
+    };
+    let source = &args[1];
+    let mut config = Config::default();
+    let mut baud = DEFAULT_BAUD;
+
+    let mut rest = args[2..].iter();
+    while let Some(flag) = rest.next() {
+        let value = rest.next().unwrap_or_else(|| usage(&args[0]));
+        match flag.as_str() {
+            "--broker" => {
+                let mut parts = value.splitn(2, ':');
+                config.host = parts.next().unwrap_or_default().to_string();
+                config.port = match parts.next() {
+                    Some(p) => p.parse().unwrap_or_else(|_| usage(&args[0])),
+                    None => DEFAULT_PORT,
+                };
+            }
+            "--qos" => {
+                config.qos = value
+                    .parse()
+                    .ok()
+                    .and_then(qos)
+                    .unwrap_or_else(|| usage(&args[0]))
+            }
+            "--prefix" => config.prefix = value.clone(),
+            "--baud" => baud = value.parse().unwrap_or_else(|_| usage(&args[0])),
+            _ => usage(&args[0]),
+        };
+    }
+
+    let mut input = open(source, baud).unwrap_or_else(|e| {
+        eprintln!("cannot open {}: {}", source, e);
+        process::exit(1);
+    });
+    let publisher = Publisher::connect(&config);
+
+    let result = for_each_line(&mut *input, |line| {
+        if let Some(frame) = parse_line(line) {
+            println!("{}", describe(&frame));
+            if let Err(e) = publisher.publish(&frame, now()) {
+                eprintln!("not published: {}", e);
+            };
+        };
+    });
+    if let Err(e) = result {
+        eprintln!("error reading {}: {}", source, e);
+        process::exit(1);
+    };
+
+    // give the connection thread time to send what is queued (reading a file ends quickly)
+    thread::sleep(Duration::from_secs(2));
+}
@@ -0,0 +1,77 @@
+//! Read gateway_spi output and write a track for each sender as GPX, KML or GeoJSON
+//! (chosen by the output file extension), with times, RSSI, SNR and battery as extensions.
+//!
+//!  track_export /dev/ttyUSB0 tracks.gpx [baud]
+//!  track_export saved_output.txt tracks.kml
+//!  track_export - tracks.geojson            (stdin)
+//!
+//! Times are when each line is read, so are only meaningful when reading a device (or stdin) live.
+//! When reading a device the output is rewritten after each new point, until the program is stopped.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lora_gps_host::export::{export, Format};
+use lora_gps_host::frame::parse_line;
+use lora_gps_host::input::{for_each_line, is_serial, open, DEFAULT_BAUD};
+use lora_gps_host::track::Tracks;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn write(path: &str, tracks: &Tracks, format: Format) {
+    if let Err(e) = fs::write(path, export(tracks, format)) {
+        eprintln!("cannot write {}: {}", path, e);
+        process::exit(1);
+    };
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!(
+            "usage: {} <serial device | file | -> <output .gpx | .kml | .geojson> [baud]",
+            args[0]
+        );
+        process::exit(2);
+    };
+    let (source, output) = (&args[1], &args[2]);
+    let format = Format::from_path(output).unwrap_or_else(|| {
+        eprintln!("output must end in .gpx, .kml or .geojson, not {}", output);
+        process::exit(2);
+    });
+    let baud = match args.get(3) {
+        Some(b) => b.parse().unwrap_or_else(|_| {
+            eprintln!("baud must be a number, not {}", b);
+            process::exit(2);
+        }),
+        None => DEFAULT_BAUD,
+    };
+
+    let mut input = open(source, baud).unwrap_or_else(|e| {
+        eprintln!("cannot open {}: {}", source, e);
+        process::exit(1);
+    });
+    let live = is_serial(source);
+
+    let mut tracks = Tracks::new();
+    let result = for_each_line(&mut *input, |line| {
+        if let Some(frame) = parse_line(line) {
+            if tracks.add(&frame, now()) && live {
+                write(output, &tracks, format);
+            };
+        };
+    });
+    if let Err(e) = result {
+        eprintln!("error reading {}: {}", source, e);
+        process::exit(1);
+    };
+
+    write(output, &tracks, format);
+}
@@ -0,0 +1,172 @@
+//! Tracks written as GPX, KML or GeoJSON. RSSI, SNR and battery are extensions:
+//!  GPX <extensions>, KML <ExtendedData> arrays, and GeoJSON feature properties.
+
+use serde_json::json;
+
+use crate::track::Tracks;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Gpx,
+    Kml,
+    GeoJson,
+}
+
+impl Format {
+    // from the output file extension
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = path.rsplit('.').next()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gpx" => Some(Format::Gpx),
+            "kml" => Some(Format::Kml),
+            "geojson" | "json" => Some(Format::GeoJson),
+            _ => None,
+        }
+    }
+}
+
+pub fn export(tracks: &Tracks, format: Format) -> String {
+    match format {
+        Format::Gpx => gpx(tracks),
+        Format::Kml => kml(tracks),
+        Format::GeoJson => geojson(tracks),
+    }
+}
+
+// days since 1970-01-01 to (year, month, day), from Howard Hinnant's civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+// ISO 8601 UTC, eg 2021-06-01T12:34:56Z
+pub fn iso8601(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    let s = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y,
+        m,
+        d,
+        s / 3600,
+        s / 60 % 60,
+        s % 60
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|x| x.to_string()).unwrap_or_default()
+}
+
+pub fn gpx(tracks: &Tracks) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"lora_gps\" xmlns=\"http://www.topografix.com/GPX/1/1\" \
+         xmlns:lora=\"https://github.com/pdgilbert/LoRaGPS-rust\">\n",
+    );
+    for (id, points) in &tracks.senders {
+        out += &format!("  <trk>\n    <name>{}</name>\n    <trkseg>\n", escape(id));
+        for p in points {
+            out += &format!(
+                "      <trkpt lat=\"{:.6}\" lon=\"{:.6}\">\n        <time>{}</time>\n        \
+                 <extensions><lora:rssi>{}</lora:rssi>",
+                p.position.lat,
+                p.position.lon,
+                iso8601(p.time),
+                p.rssi
+            );
+            if let Some(snr) = p.snr {
+                out += &format!("<lora:snr>{}</lora:snr>", snr);
+            };
+            if let Some(b) = p.battery {
+                out += &format!("<lora:battery>{}</lora:battery>", b);
+            };
+            out += "</extensions>\n      </trkpt>\n";
+        }
+        out += "    </trkseg>\n  </trk>\n";
+    }
+    out += "</gpx>\n";
+    out
+}
+
+fn kml_array(name: &str, values: impl Iterator<Item = String>) -> String {
+    let mut out = format!("        <gx:SimpleArrayData name=\"{}\">\n", name);
+    for v in values {
+        out += &format!("          <gx:value>{}</gx:value>\n", v);
+    }
+    out + "        </gx:SimpleArrayData>\n"
+}
+
+// one gx:Track per sender, so time and the extension arrays line up with the coordinates
+pub fn kml(tracks: &Tracks) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n\
+         <Document>\n",
+    );
+    for (id, points) in &tracks.senders {
+        out += &format!(
+            "  <Placemark>\n    <name>{}</name>\n    <gx:Track>\n",
+            escape(id)
+        );
+        for p in points {
+            out += &format!("      <when>{}</when>\n", iso8601(p.time));
+        }
+        for p in points {
+            out += &format!(
+                "      <gx:coord>{:.6} {:.6} 0</gx:coord>\n",
+                p.position.lon, p.position.lat
+            );
+        }
+        out += "      <ExtendedData>\n       <SchemaData>\n";
+        out += &kml_array("rssi", points.iter().map(|p| p.rssi.to_string()));
+        out += &kml_array("snr", points.iter().map(|p| opt(p.snr)));
+        out += &kml_array("battery", points.iter().map(|p| opt(p.battery)));
+        out += "       </SchemaData>\n      </ExtendedData>\n    </gx:Track>\n  </Placemark>\n";
+    }
+    out += "</Document>\n</kml>\n";
+    out
+}
+
+// a LineString feature per sender, with per point times and extensions as property arrays
+pub fn geojson(tracks: &Tracks) -> String {
+    let features: Vec<_> = tracks
+        .senders
+        .iter()
+        .map(|(id, points)| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": points.iter().map(|p| [p.position.lon, p.position.lat]).collect::<Vec<_>>(),
+                },
+                "properties": {
+                    "sender": id,
+                    "times": points.iter().map(|p| iso8601(p.time)).collect::<Vec<_>>(),
+                    "rssi": points.iter().map(|p| p.rssi).collect::<Vec<_>>(),
+                    "snr": points.iter().map(|p| p.snr).collect::<Vec<_>>(),
+                    "battery": points.iter().map(|p| p.battery).collect::<Vec<_>>(),
+                },
+            })
+        })
+        .collect();
+
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+    // cannot fail, only strings and numbers
+    serde_json::to_string_pretty(&collection).unwrap() + "\n"
+}
@@ -0,0 +1,65 @@
+//! Lines of JSON written by the gateway, one per received packet, eg
+//!
+//!  {"rssi":-87,"snr":7,"data":"A50003FF002A06423431312D32..."}
+
+use serde::Deserialize;
+
+use crate::packet::{decode, Kind};
+
+#[derive(Deserialize)]
+struct Line {
+    rssi: i16,
+    snr: Option<i16>,
+    data: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub rssi: i16,
+    pub snr: Option<i16>,
+    pub data: Vec<u8>, // the packet as received
+}
+
+// None for lines that are not gateway output (eg noise while the usart starts up)
+pub fn parse_line(line: &str) -> Option<Frame> {
+    let l: Line = serde_json::from_str(line.trim()).ok()?;
+    Some(Frame {
+        rssi: l.rssi,
+        snr: l.snr,
+        data: from_hex(&l.data)?,
+    })
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| match c {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok(),
+            _ => None, // odd length
+        })
+        .collect()
+}
+
+// One line description of a frame, in the same form as receive_spi prints, plus signal strength.
+pub fn describe(frame: &Frame) -> String {
+    let signal = match frame.snr {
+        Some(snr) => format!("rssi {} snr {}", frame.rssi, snr),
+        None => format!("rssi {}", frame.rssi),
+    };
+    match decode(&frame.data) {
+        Some((header, payload)) => format!(
+            "{} #{} via {:?} {}{}  ({})",
+            String::from_utf8_lossy(&header.source),
+            header.seq,
+            header.path,
+            match header.kind {
+                Kind::Alert => "ALERT ",
+                Kind::Status => "STATUS ",
+                _ => "",
+            },
+            String::from_utf8_lossy(payload),
+            signal
+        ),
+        None => format!("{}  ({})", String::from_utf8_lossy(&frame.data), signal),
+    }
+}
@@ -0,0 +1,43 @@
+//! Gateway output read from a serial device, a file of saved output, or stdin ("-").
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::time::Duration;
+
+pub const DEFAULT_BAUD: u32 = 9600; // as set up for the usart in setup()
+
+// serial device names on linux, macOS and windows
+pub fn is_serial(path: &str) -> bool {
+    path.starts_with("/dev/tty") || path.starts_with("/dev/cu.") || path.starts_with("COM")
+}
+
+pub fn open(path: &str, baud: u32) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    };
+    if is_serial(path) {
+        let port = serialport::new(path, baud)
+            .timeout(Duration::from_secs(1))
+            .open()?;
+        return Ok(Box::new(BufReader::new(port)));
+    };
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+// Call f with each line until the end of input. Serial read timeouts (no packet received for a
+// while) are not the end, so a serial device is read until the program is stopped.
+pub fn for_each_line<F: FnMut(&str)>(input: &mut dyn BufRead, mut f: F) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        match input.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {
+                f(&line);
+                line.clear();
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => (),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => line.clear(), // not utf8, eg noise
+            Err(e) => return Err(e),
+        };
+    }
+}
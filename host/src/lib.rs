@@ -0,0 +1,72 @@
+//! PC side of the gateway_spi firmware (see src/gateway.rs).
+//!  The packet codec, APRS reports and position parsing are shared with the firmware by including
+//!  its source, since the firmware crate itself only builds for the MCU targets.
+
+#[path = "../../src/aprs.rs"]
+pub mod aprs;
+#[path = "../../src/geodesy.rs"]
+pub mod geodesy;
+#[path = "../../src/nmea.rs"]
+pub mod nmea;
+#[path = "../../src/packet.rs"]
+pub mod packet;
+
+pub mod export;
+pub mod frame;
+pub mod input;
+pub mod publish;
+pub mod track;
+
+// Firmware modules without hardware dependencies, compiled here only so their tests run on the PC.
+// fix needs ubx (for NAV-PVT), ubx and pmtk need gateway, and hopping needs prng. Most of those,
+// and the fsk and hopping traits (for the radio drivers), are only used by the firmware.
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/fix.rs"]
+mod fix;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/fsk.rs"]
+mod fsk;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/gateway.rs"]
+mod gateway;
+#[cfg(test)]
+#[path = "../../src/geofence.rs"]
+mod geofence;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/hopping.rs"]
+mod hopping;
+#[cfg(test)]
+#[path = "../../src/lorawan.rs"]
+mod lorawan;
+#[cfg(test)]
+#[path = "../../src/mesh.rs"]
+mod mesh;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/pmtk.rs"]
+mod pmtk;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/prng.rs"]
+mod prng;
+#[cfg(test)]
+#[path = "../../src/relay.rs"]
+mod relay;
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/ubx.rs"]
+mod ubx;
+
+// logln! of the firmware (see src/log.rs), printed by the tests. Defined after the modules above,
+// so they use it as the firmware does, with  use crate::logln.
+#[cfg(test)]
+#[macro_export]
+macro_rules! logln {
+    ($($arg:tt)*) => {
+        println!($($arg)*)
+    };
+}
@@ -0,0 +1,172 @@
+//! Publish decoded packets to an MQTT broker, for dashboards (eg Grafana, Node-RED).
+//!
+//!  topic  <prefix>/<sender>/position    {"lat":45.39,"lon":-75.67,"battery":3.71,...}
+//!         <prefix>/<sender>/alert       {"text":"EXIT 01 4523.74241,N 07540.61255,W 03:17:37Z",...}
+//!         <prefix>/<sender>/status      {"text":"no fix since 03:17:37Z",...}
+//!         <prefix>/<sender>/telemetry   {"text":"...",...}   other data packets
+//!
+//!  Every message also has rssi, snr, time, and (for packets with a header) seq and path. The time
+//!  is that of the GPS fix for positions and alerts that include it, otherwise when the packet was
+//!  read. battery is null unless the sender measures it (eg  "Bat 3.71V"  in an APRS comment).
+//!  Positions can also be LoRa-APRS reports. Beacons, and plain text that is not a position
+//!  report, are not published.
+//!
+//!  The connection is kept by a thread, which reconnects after RECONNECT_DELAY if the broker
+//!  goes away. Messages published meanwhile are queued, up to QUEUE_LEN. The session is persistent
+//!  (not clean), so QoS 1 and 2 messages in flight when the connection drops are sent again.
+
+use std::thread;
+use std::time::Duration;
+
+use rumqttc::{Client, ClientError, MqttOptions, QoS};
+use serde_json::{json, Value};
+
+use crate::export::iso8601;
+use crate::frame::Frame;
+use crate::nmea::parse_report_time;
+use crate::packet::{decode, Kind};
+use crate::track::{fix_time, parse, parse_plain, Report};
+
+pub const DEFAULT_PORT: u16 = 1883;
+pub const QUEUE_LEN: usize = 100;
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+pub const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+pub fn qos(level: u8) -> Option<QoS> {
+    match level {
+        0 => Some(QoS::AtMostOnce),
+        1 => Some(QoS::AtLeastOnce),
+        2 => Some(QoS::ExactlyOnce),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub prefix: String,
+    pub qos: QoS,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: String::from("localhost"),
+            port: DEFAULT_PORT,
+            client_id: String::from("lora_gps_gateway"),
+            prefix: String::from("lora_gps"),
+            qos: QoS::AtLeastOnce,
+        }
+    }
+}
+
+// MQTT wildcards and level separators are not allowed in a topic level
+fn topic_level(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '/' | '+' | '#' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn position(report: &Report) -> Value {
+    json!({ "lat": report.position.lat, "lon": report.position.lon, "battery": report.battery })
+}
+
+// Topic (under the prefix) and JSON message for a frame read at time read, or None if it is not
+// published.
+pub fn message(frame: &Frame, read: u64) -> Option<(String, Value)> {
+    let (sender, kind, mut msg, fix) = match decode(&frame.data) {
+        Some((header, payload)) => {
+            let (kind, mut msg, fix) = match header.kind {
+                Kind::Beacon => return None,
+                Kind::Alert => (
+                    "alert",
+                    json!({ "text": String::from_utf8_lossy(payload) }),
+                    parse_report_time(payload),
+                ),
+                // the time in the text is that of the last fix, not of the message
+                Kind::Status => (
+                    "status",
+                    json!({ "text": String::from_utf8_lossy(payload) }),
+                    None,
+                ),
+                Kind::Data => match parse(payload) {
+                    Some(r) => ("position", position(&r), r.time),
+                    None => (
+                        "telemetry",
+                        json!({ "text": String::from_utf8_lossy(payload) }),
+                        None,
+                    ),
+                },
+            };
+            msg["seq"] = json!(header.seq);
+            msg["path"] = json!(&header.path[..]);
+            (
+                String::from_utf8_lossy(&header.source).into_owned(),
+                kind,
+                msg,
+                fix,
+            )
+        }
+        None => {
+            let (id, r) = parse_plain(&frame.data)?;
+            (id, "position", position(&r), r.time)
+        }
+    };
+
+    msg["rssi"] = json!(frame.rssi);
+    msg["snr"] = json!(frame.snr);
+    msg["time"] = json!(iso8601(fix_time(read, fix)));
+    Some((format!("{}/{}", topic_level(&sender), kind), msg))
+}
+
+pub struct Publisher {
+    client: Client,
+    prefix: String,
+    qos: QoS,
+}
+
+impl Publisher {
+    // Start the connection thread. This does not wait for the broker, so works (queueing
+    // messages) when the broker is not yet running.
+    pub fn connect(config: &Config) -> Self {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE).set_clean_session(false);
+        let (client, mut connection) = Client::new(options, QUEUE_LEN);
+
+        let broker = format!("{}:{}", config.host, config.port);
+        thread::spawn(move || {
+            // iter() ends when the Publisher (client) is dropped, otherwise errors are followed
+            // by a reconnect on the next call
+            for event in connection.iter() {
+                if let Err(e) = event {
+                    eprintln!("mqtt {}: {}, reconnecting", broker, e);
+                    thread::sleep(RECONNECT_DELAY);
+                };
+            }
+        });
+
+        Publisher {
+            client,
+            prefix: config.prefix.clone(),
+            qos: config.qos,
+        }
+    }
+
+    // Queue the message for frame, if it has one. Returns Ok(false) if there is nothing to
+    // publish, and an error if the queue is full (the broker has been away for a while).
+    pub fn publish(&self, frame: &Frame, read: u64) -> Result<bool, ClientError> {
+        let (topic, msg) = match message(frame, read) {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+        let topic = format!("{}/{}", self.prefix, topic);
+        self.client
+            .try_publish(topic, self.qos, false, msg.to_string())?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,121 @@
+//! Per sender tracks built from decoded gateway frames.
+
+use std::collections::BTreeMap;
+
+use crate::aprs;
+use crate::frame::Frame;
+use crate::geodesy::Position;
+use crate::nmea::{parse_position, parse_report, parse_report_time};
+use crate::packet::{decode, Kind};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub time: u64, // seconds since 1970, of the GPS fix (see fix_time)
+    pub position: Position,
+    pub rssi: i16,
+    pub snr: Option<i16>,
+    pub battery: Option<f64>, // volts
+}
+
+// Position report, with the time of the fix (UTC seconds of day) and battery voltage if the sender
+// includes them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Report {
+    pub position: Position,
+    pub time: Option<u32>,
+    pub battery: Option<f64>,
+}
+
+// Battery voltage from a word such as  3.71V  after the position, as in the APRS comment
+// "Bat 3.71V"  (see battery_comment in src/aprs.rs). None if the sender does not measure it.
+pub fn battery(comment: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(comment).ok()?;
+    s.split_whitespace()
+        .find_map(|w| w.strip_suffix('V')?.parse().ok())
+}
+
+// Time (seconds since 1970) of a fix at UTC seconds of day fix, in a frame read at time read. The
+// date is that of the read time, or the day before or after if that is closer (a fix just before
+// midnight read just after). Without a fix time the read time is used.
+pub fn fix_time(read: u64, fix: Option<u32>) -> u64 {
+    let fix = match fix {
+        Some(t) => t as u64,
+        None => return read,
+    };
+    let t = read - read % 86_400 + fix;
+    if t > read + 43_200 {
+        t - 86_400
+    } else if t + 43_200 < read {
+        t + 86_400
+    } else {
+        t
+    }
+}
+
+// Report in a payload from send_gps,  "4523.74241,N 07540.61255,W 03:17:37Z"  with any tags or
+// battery voltage after the time.
+pub fn parse(payload: &[u8]) -> Option<Report> {
+    Some(Report {
+        position: parse_position(payload)?,
+        time: parse_report_time(payload),
+        battery: battery(payload),
+    })
+}
+
+// Sender id and report in a frame without a packet header: a LoRa-APRS position report, or plain
+// text  "id lat,N lon,W"  from older senders.
+pub fn parse_plain(data: &[u8]) -> Option<(String, Report)> {
+    if let Some(r) = aprs::decode(data) {
+        let report = Report {
+            position: r.position,
+            time: r.time,
+            battery: battery(r.comment),
+        };
+        return Some((String::from_utf8_lossy(r.source).into_owned(), report));
+    };
+    let (id, _) = parse_report(data)?;
+    let report = parse(&data[id.len() + 1..])?;
+    Some((String::from_utf8_lossy(id).into_owned(), report))
+}
+
+// sender id and position report in a frame, whether it has a packet header or not
+fn report(frame: &Frame) -> Option<(String, Report)> {
+    match decode(&frame.data) {
+        Some((header, payload)) => {
+            if header.kind != Kind::Data {
+                return None;
+            };
+            let id = String::from_utf8_lossy(&header.source).into_owned();
+            Some((id, parse(payload)?))
+        }
+        None => parse_plain(&frame.data),
+    }
+}
+
+#[derive(Default)]
+pub struct Tracks {
+    pub senders: BTreeMap<String, Vec<Point>>, // points in the order received
+}
+
+impl Tracks {
+    pub fn new() -> Self {
+        Tracks::default()
+    }
+
+    // add the position in frame (read at time read), if it has one. Returns true if a point was
+    // added.
+    pub fn add(&mut self, frame: &Frame, read: u64) -> bool {
+        let (id, report) = match report(frame) {
+            Some(v) => v,
+            None => return false,
+        };
+        self.senders.entry(id).or_default().push(Point {
+            time: fix_time(read, report.time),
+            position: report.position,
+            rssi: frame.rssi,
+            snr: frame.snr,
+            battery: report.battery,
+        });
+        true
+    }
+}
@@ -0,0 +1,214 @@
+// Publish through a minimal MQTT 3.1.1 broker stand-in (enough of mosquitto for the publisher:
+// CONNECT, PUBLISH at QoS 0 and 1, PINGREQ), listening on a local port.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use rumqttc::QoS;
+use serde_json::Value;
+
+use lora_gps_host::aprs;
+use lora_gps_host::frame::Frame;
+use lora_gps_host::geodesy::Position;
+use lora_gps_host::packet::{Header, Kind};
+use lora_gps_host::publish::{message, Config, Publisher};
+
+// fixed header type and the rest of the packet
+fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).ok()?;
+    let kind = byte[0];
+
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        stream.read_exact(&mut byte).ok()?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            break;
+        };
+    }
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).ok()?;
+    Some((kind, body))
+}
+
+// Serve one connection, sending received (topic, payload) on tx. If drop_after_connack the
+// connection is closed straight after accepting it, to make the client reconnect. A client that
+// asks for a persistent session is told the session is present when it reconnects.
+fn serve(
+    mut stream: TcpStream,
+    tx: &Sender<(String, Vec<u8>)>,
+    drop_after_connack: bool,
+    reconnect: bool,
+) {
+    while let Some((kind, body)) = read_packet(&mut stream) {
+        match kind >> 4 {
+            1 => {
+                // body is protocol name "MQTT" (2 + 4 bytes), level, flags, ...
+                let clean = body[7] & 0x02 != 0;
+                let present = (reconnect && !clean) as u8;
+                stream.write_all(&[0x20, 0x02, present, 0x00]).unwrap();
+                if drop_after_connack {
+                    return;
+                };
+            }
+            3 => {
+                let qos = (kind >> 1) & 0x03;
+                let n = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let topic = String::from_utf8(body[2..2 + n].to_vec()).unwrap();
+                let mut rest = &body[2 + n..];
+                if qos > 0 {
+                    stream.write_all(&[0x40, 0x02, rest[0], rest[1]]).unwrap();
+                    rest = &rest[2..];
+                };
+                tx.send((topic, rest.to_vec())).unwrap();
+            }
+            12 => stream.write_all(&[0xD0, 0x00]).unwrap(),
+            14 => return, // DISCONNECT
+            _ => (),
+        };
+    }
+}
+
+fn broker(drop_first: bool) -> (u16, Receiver<(String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let mut first = true;
+        for stream in listener.incoming() {
+            serve(stream.unwrap(), &tx, drop_first && first, !first);
+            first = false;
+        }
+    });
+    (port, rx)
+}
+
+fn position_frame() -> Frame {
+    let mut data = heapless::Vec::new();
+    let mut header = Header::new(Kind::Data, b"B411-2", 42);
+    header.path.push(7).unwrap();
+    assert!(header.encode(b"4523.74241,N 07540.61255,W 11:59:30Z", &mut data));
+    Frame {
+        rssi: -87,
+        snr: Some(7),
+        data: data.to_vec(),
+    }
+}
+
+fn config(port: u16, qos: QoS, id: &str) -> Config {
+    Config {
+        host: String::from("127.0.0.1"),
+        port,
+        client_id: String::from(id),
+        qos,
+        ..Config::default()
+    }
+}
+
+fn check_position(topic: &str, payload: &[u8]) {
+    assert_eq!(topic, "lora_gps/B411-2/position");
+    let msg: Value = serde_json::from_slice(payload).unwrap();
+    assert!((msg["lat"].as_f64().unwrap() - 45.395707).abs() < 1e-5);
+    assert!((msg["lon"].as_f64().unwrap() + 75.676876).abs() < 1e-5);
+    assert_eq!(msg["rssi"], -87);
+    assert_eq!(msg["snr"], 7);
+    assert_eq!(msg["seq"], 42);
+    assert_eq!(msg["path"], serde_json::json!([7]));
+    assert_eq!(msg["time"], "2021-06-01T11:59:30Z"); // of the fix, not TIME
+    assert_eq!(msg["battery"], Value::Null);
+}
+
+const TIME: u64 = 1_622_548_800; // 2021-06-01T12:00:00Z
+
+#[test]
+fn publishes_position_at_qos_0_and_1() {
+    for (qos, id) in [(QoS::AtMostOnce, "t0"), (QoS::AtLeastOnce, "t1")].iter() {
+        let (port, rx) = broker(false);
+        let publisher = Publisher::connect(&config(port, *qos, id));
+        assert!(publisher.publish(&position_frame(), TIME).unwrap());
+
+        let (topic, payload) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        check_position(&topic, &payload);
+    }
+}
+
+#[test]
+fn reconnects_when_broker_drops_connection() {
+    let (port, rx) = broker(true);
+    let publisher = Publisher::connect(&config(port, QoS::AtLeastOnce, "reconnect"));
+    assert!(publisher.publish(&position_frame(), TIME).unwrap());
+
+    let (topic, payload) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    check_position(&topic, &payload);
+}
+
+#[test]
+fn beacons_are_not_published() {
+    let mut data = heapless::Vec::new();
+    assert!(Header::new(Kind::Beacon, &[3], 1).encode(&[3, 1, 200], &mut data));
+    let (port, _rx) = broker(false);
+    let publisher = Publisher::connect(&config(port, QoS::AtMostOnce, "beacon"));
+    let frame = Frame {
+        rssi: -90,
+        snr: None,
+        data: data.to_vec(),
+    };
+    assert!(!publisher.publish(&frame, TIME).unwrap());
+}
+
+#[test]
+fn fix_time_is_dated_from_read_time() {
+    let frame = |data: &[u8]| Frame {
+        rssi: -90,
+        snr: None,
+        data: data.to_vec(),
+    };
+    let time = |data: &[u8], read: u64| message(&frame(data), read).unwrap().1["time"].clone();
+
+    let report = b"B411-2 4523.74241,N 07540.61255,W 23:59:58Z 2D";
+    assert_eq!(time(report, TIME), "2021-06-01T23:59:58Z");
+    // read just after midnight, the fix was the day before
+    assert_eq!(time(report, TIME + 12 * 3600 + 5), "2021-06-01T23:59:58Z");
+    // and a fix just after midnight read just before is the next day
+    let report = b"B411-2 4523.74241,N 07540.61255,W 00:00:01Z";
+    assert_eq!(time(report, TIME + 12 * 3600 - 1), "2021-06-02T00:00:01Z");
+    // older senders do not send the fix time
+    assert_eq!(
+        time(b"B411-2 4523.74241,N 07540.61255,W", TIME),
+        "2021-06-01T12:00:00Z"
+    );
+}
+
+#[test]
+fn aprs_report_with_time_and_battery() {
+    let mut data = heapless::Vec::new();
+    let position = Position::new(45.395707, -75.676876);
+    let comment = aprs::battery_comment(3710);
+    for format in [aprs::Format::Compressed, aprs::Format::Uncompressed].iter() {
+        assert!(aprs::encode(
+            &mut data,
+            b"VE3XYZ-9",
+            position,
+            aprs::CAR,
+            *format,
+            Some(11 * 3600 + 59 * 60 + 30),
+            comment.as_bytes(),
+        ));
+        let frame = Frame {
+            rssi: -87,
+            snr: Some(7),
+            data: data.to_vec(),
+        };
+        let (topic, msg) = message(&frame, TIME).unwrap();
+        assert_eq!(topic, "VE3XYZ-9/position");
+        assert!((msg["lat"].as_f64().unwrap() - 45.395707).abs() < 1e-3);
+        assert!((msg["lon"].as_f64().unwrap() + 75.676876).abs() < 1e-3);
+        assert_eq!(msg["battery"], 3.71);
+        assert_eq!(msg["time"], "2021-06-01T11:59:30Z");
+    }
+}
@@ -0,0 +1,6 @@
+MEMORY
+{
+  /* NRF52840 with no SoftDevice or bootloader */
+  FLASH : ORIGIN = 0x00000000, LENGTH = 1024K
+  RAM : ORIGIN = 0x20000000, LENGTH = 256K
+}
@@ -0,0 +1,17 @@
+MEMORY
+{
+  /* RP2040 with 2M of flash (Pico), the first 256 bytes are the second stage bootloader */
+  BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
+  FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+  RAM : ORIGIN = 0x20000000, LENGTH = 256K
+}
+
+/* BOOT2_FIRMWARE is in src/lora_spi_gps_usart.rs */
+EXTERN(BOOT2_FIRMWARE)
+
+SECTIONS {
+  .boot2 ORIGIN(BOOT2) :
+  {
+    KEEP(*(.boot2));
+  } > BOOT2
+} INSERT BEFORE .text;
@@ -0,0 +1,7 @@
+MEMORY
+{
+  /* Define memory regions.  STM32F405RG */
+  /* the 64K CCM RAM at 0x10000000 is not used */
+  FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 1024K
+  RAM (rwx)  : ORIGIN = 0x20000000, LENGTH = 128K
+}
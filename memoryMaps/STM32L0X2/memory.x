@@ -1,5 +1,5 @@
 MEMORY
-{   /* see https://github.com/stm32-rs/stm32l0xx-hal/blob/master/  */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 16K
-  RAM : ORIGIN = 0x20000000, LENGTH = 2K
+{   /* STM32L072KZ, as the mcu-STM32L072KZTx feature of stm32l0xx-hal  */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 192K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
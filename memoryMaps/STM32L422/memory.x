@@ -0,0 +1,7 @@
+MEMORY
+{
+  /* STM32L422, see https://github.com/stm32-rs/stm32l4xx-hal/blob/master/memory.x */
+  /* SRAM2 (8K) follows SRAM1 (32K) so they are used as one region */
+  FLASH : ORIGIN = 0x8000000, LENGTH = 128K
+  RAM : ORIGIN = 0x20000000, LENGTH = 40K
+}
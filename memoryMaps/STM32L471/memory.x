@@ -0,0 +1,7 @@
+MEMORY
+{
+  /* STM32L471xG, see https://github.com/stm32-rs/stm32l4xx-hal/blob/master/memory.x */
+  /* SRAM2 (32K) follows SRAM1 (96K) so they are used as one region */
+  FLASH : ORIGIN = 0x8000000, LENGTH = 1M
+  RAM : ORIGIN = 0x20000000, LENGTH = 128K
+}
@@ -1,6 +0,0 @@
-MEMORY
-{
-  /* see https://github.com/stm32-rs/stm32l4xx-hal/blob/master/memory.x */
-  FLASH : ORIGIN = 0x8000000, LENGTH = 256K 
-  RAM : ORIGIN = 0x20000000, LENGTH = 64K
-}
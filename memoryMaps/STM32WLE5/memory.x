@@ -0,0 +1,6 @@
+MEMORY
+{
+  /* STM32WLE5JC, eg Seeed LoRa-E5. SRAM2 (32K) follows SRAM1 (32K) so they are used as one region */
+  FLASH : ORIGIN = 0x8000000, LENGTH = 256K
+  RAM : ORIGIN = 0x20000000, LENGTH = 64K
+}
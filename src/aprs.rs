@@ -0,0 +1,364 @@
+//! APRS position reports, as used by LoRa-APRS trackers and iGates.
+//!
+//!  <\xff\x01CALL-9>APZLGP,WIDE1-1:!4523.74N/07540.61W>comment          uncompressed
+//!  <\xff\x01CALL-9>APZLGP,WIDE1-1:!/5L!!<*e7>  !comment                compressed (base 91)
+//!  <\xff\x01CALL-9>APZLGP,WIDE1-1:/031737h/5L!!<*e7>  !comment         with the fix time (UTC)
+//!
+//!  The 3 byte prefix marks LoRa-APRS packets, the rest is the usual TNC2 text form.
+//!  The decoder also accepts reports without the prefix, and with a day/hour/minute timestamp.
+//!  Course, speed and altitude are not encoded.
+
+use core::fmt::{self, Write};
+
+use heapless::{String, Vec};
+
+use crate::geodesy::Position;
+use crate::nmea::{parse_coordinate, parse_time};
+use crate::packet::MAX_PACKET;
+
+pub const PREFIX: [u8; 3] = [b'<', 0xFF, 0x01];
+
+// APZ is the experimental software range
+pub const DESTINATION: &str = "APZLGP";
+pub const PATH: &str = "WIDE1-1";
+
+// longest comment iGates are expected to pass on after a position
+pub const MAX_COMMENT: usize = 43;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Symbol {
+    pub table: u8, // '/' primary, '\\' alternate
+    pub code: u8,
+}
+
+pub const CAR: Symbol = Symbol {
+    table: b'/',
+    code: b'>',
+};
+pub const JOGGER: Symbol = Symbol {
+    table: b'/',
+    code: b'[',
+};
+pub const BIKE: Symbol = Symbol {
+    table: b'/',
+    code: b'b',
+};
+pub const BALLOON: Symbol = Symbol {
+    table: b'/',
+    code: b'O',
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Uncompressed, // about 18m resolution, readable
+    Compressed,   // better resolution in fewer bytes
+}
+
+struct Writer<'a>(&'a mut Vec<u8, MAX_PACKET>);
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0
+            .extend_from_slice(s.as_bytes())
+            .map_err(|_| fmt::Error)
+    }
+}
+
+// ddmm.hh (lat) or dddmm.hh (lon) plus hemisphere
+fn write_uncompressed(
+    w: &mut Writer,
+    value: f64,
+    digits: usize,
+    hemi: (char, char),
+) -> fmt::Result {
+    let a = if value < 0.0 { -value } else { value };
+    // hundredths of minutes, rounded, so 59.999 minutes does not print as 60.00
+    let total = (a * 6000.0 + 0.5) as u32;
+    let h = if value < 0.0 { hemi.1 } else { hemi.0 };
+    write!(
+        w,
+        "{:0w$}{:02}.{:02}{}",
+        total / 6000,
+        total % 6000 / 100,
+        total % 100,
+        h,
+        w = digits
+    )
+}
+
+fn write_base91(w: &mut Writer, mut v: u32) -> fmt::Result {
+    let mut digits = [0u8; 4];
+    for d in digits.iter_mut().rev() {
+        *d = (v % 91) as u8 + 33;
+        v /= 91;
+    }
+    // cannot fail, all digits are printable ASCII
+    w.write_str(core::str::from_utf8(&digits).unwrap())
+}
+
+fn base91(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0u32, |v, d| match d {
+        33..=123 => Some(v * 91 + (*d - 33) as u32),
+        _ => None,
+    })
+}
+
+// Encode a position report from source (callsign-SSID) into out, with the time of the fix (UTC
+// seconds of day) if there is one. The comment (eg battery voltage, see battery_comment) is
+// truncated to MAX_COMMENT. Returns false if it does not fit.
+pub fn encode(
+    out: &mut Vec<u8, MAX_PACKET>,
+    source: &[u8],
+    position: Position,
+    symbol: Symbol,
+    format: Format,
+    time: Option<u32>,
+    comment: &[u8],
+) -> bool {
+    out.clear();
+    if out.extend_from_slice(&PREFIX).is_err() || out.extend_from_slice(source).is_err() {
+        return false;
+    };
+
+    let mut w = Writer(out);
+    let ok = write!(w, ">{},{}:", DESTINATION, PATH).is_ok()
+        && match time {
+            None => w.write_char('!').is_ok(),
+            Some(t) => write!(w, "/{:02}{:02}{:02}h", t / 3600 % 24, t / 60 % 60, t % 60).is_ok(),
+        }
+        && match format {
+            Format::Uncompressed => {
+                write_uncompressed(&mut w, position.lat, 2, ('N', 'S')).is_ok()
+                    && w.write_char(symbol.table as char).is_ok()
+                    && write_uncompressed(&mut w, position.lon, 3, ('E', 'W')).is_ok()
+                    && w.write_char(symbol.code as char).is_ok()
+            }
+            Format::Compressed => {
+                let y = (380_926.0 * (90.0 - position.lat)) as u32;
+                let x = (190_463.0 * (180.0 + position.lon)) as u32;
+                // no course/speed (c is space, so s and T are ignored)
+                w.write_char(symbol.table as char).is_ok()
+                    && write_base91(&mut w, y).is_ok()
+                    && write_base91(&mut w, x).is_ok()
+                    && w.write_char(symbol.code as char).is_ok()
+                    && w.write_str("  !").is_ok()
+            }
+        };
+
+    ok && out
+        .extend_from_slice(&comment[..comment.len().min(MAX_COMMENT)])
+        .is_ok()
+}
+
+// comment giving battery voltage, eg  "Bat 3.71V"
+pub fn battery_comment(millivolts: u16) -> String<16> {
+    let mut s = String::new();
+    // cannot fail, at most 14 characters
+    write!(
+        s,
+        "Bat {}.{:02}V",
+        millivolts / 1000,
+        millivolts % 1000 / 10
+    )
+    .unwrap();
+    s
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Report<'a> {
+    pub source: &'a [u8],
+    pub position: Position,
+    pub symbol: Symbol,
+    pub time: Option<u32>, // UTC seconds of day, if the report has a UTC timestamp
+    pub comment: &'a [u8],
+}
+
+// hhmmssh (UTC), or ddhhmmz (UTC, the day is dropped). Local ddhhmm/ times are not used.
+fn decode_timestamp(t: &[u8]) -> Option<u32> {
+    match t.get(6)? {
+        b'h' => parse_time(&t[..6]),
+        b'z' => parse_time(&[t[2], t[3], t[4], t[5], b'0', b'0']),
+        _ => None,
+    }
+}
+
+// ddmm.hhN with APRS position ambiguity (trailing digits replaced by spaces) treated as zeros
+fn decode_uncompressed(field: &[u8], hemisphere: u8) -> Option<f64> {
+    let mut digits = [0u8; 8];
+    let digits = digits.get_mut(..field.len())?;
+    for (d, f) in digits.iter_mut().zip(field) {
+        *d = if *f == b' ' { b'0' } else { *f };
+    }
+    parse_coordinate(digits, hemisphere)
+}
+
+// Position report in buf (with or without the LoRa-APRS prefix). None for other packets,
+// including APRS packets that are not positions (messages, status, ...).
+pub fn decode(buf: &[u8]) -> Option<Report<'_>> {
+    let buf = buf.strip_prefix(&PREFIX[..]).unwrap_or(buf);
+
+    let gt = buf.iter().position(|b| *b == b'>')?;
+    let colon = buf.iter().position(|b| *b == b':')?;
+    if gt == 0 || colon < gt {
+        return None;
+    };
+    let source = &buf[..gt];
+    let info = &buf[colon + 1..];
+
+    let (body, time) = match info.first()? {
+        b'!' | b'=' => (&info[1..], None),
+        b'/' | b'@' => (info.get(8..)?, decode_timestamp(&info[1..8])),
+        _ => return None,
+    };
+
+    if body.first()?.is_ascii_digit() {
+        // 4523.74N/07540.61W>
+        if body.len() < 19 {
+            return None;
+        };
+        Some(Report {
+            source,
+            position: Position::new(
+                decode_uncompressed(&body[0..7], body[7])?,
+                decode_uncompressed(&body[9..17], body[17])?,
+            ),
+            symbol: Symbol {
+                table: body[8],
+                code: body[18],
+            },
+            time,
+            comment: &body[19..],
+        })
+    } else {
+        // /YYYYXXXX>csT
+        if body.len() < 13 {
+            return None;
+        };
+        let y = base91(&body[1..5])? as f64;
+        let x = base91(&body[5..9])? as f64;
+        Some(Report {
+            source,
+            position: Position::new(90.0 - y / 380_926.0, -180.0 + x / 190_463.0),
+            symbol: Symbol {
+                table: body[0],
+                code: body[9],
+            },
+            time,
+            comment: &body[13..],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HERE: Position = Position {
+        lat: 45.395707,
+        lon: -75.676876,
+    };
+
+    fn round_trip(position: Position, format: Format, time: Option<u32>, tolerance: f64) {
+        let mut out = Vec::new();
+        assert!(encode(
+            &mut out,
+            b"VE3XYZ-9",
+            position,
+            BIKE,
+            format,
+            time,
+            b"Bat 3.71V"
+        ));
+        assert!(out.starts_with(&PREFIX));
+
+        let r = decode(&out).unwrap();
+        assert_eq!(r.source, b"VE3XYZ-9");
+        assert_eq!(r.symbol, BIKE);
+        assert_eq!(r.time, time);
+        assert_eq!(r.comment, b"Bat 3.71V");
+        assert!((r.position.lat - position.lat).abs() < tolerance);
+        assert!((r.position.lon - position.lon).abs() < tolerance);
+    }
+
+    #[test]
+    fn round_trips() {
+        let south_east = Position::new(-33.856784, 151.215297);
+        for p in [HERE, south_east].iter() {
+            // hundredths of minutes, and about 1m compressed
+            round_trip(*p, Format::Uncompressed, None, 0.01 / 60.0);
+            round_trip(*p, Format::Compressed, None, 1e-5);
+            round_trip(*p, Format::Compressed, Some(3 * 3600 + 17 * 60 + 37), 1e-5);
+        }
+    }
+
+    #[test]
+    fn uncompressed_text() {
+        let mut out = Vec::new();
+        let t = Some(3 * 3600 + 17 * 60 + 37);
+        assert!(encode(
+            &mut out,
+            b"VE3XYZ-9",
+            HERE,
+            CAR,
+            Format::Uncompressed,
+            t,
+            b""
+        ));
+        assert_eq!(
+            &out[3..],
+            b"VE3XYZ-9>APZLGP,WIDE1-1:/031737h4523.74N/07540.61W>"
+        );
+    }
+
+    #[test]
+    fn other_reports() {
+        // without the prefix, messaging and a day/hour/minute timestamp
+        let r = decode(b"VE3XYZ-9>APRS,WIDE1-1:@300317z4523.74N/07540.61W>hello").unwrap();
+        assert_eq!(r.time, Some(3 * 3600 + 17 * 60));
+        assert_eq!(r.comment, b"hello");
+        // local time is not used
+        let r = decode(b"VE3XYZ-9>APRS:/300317/4523.74N/07540.61W>").unwrap();
+        assert_eq!(r.time, None);
+        // position ambiguity
+        let r = decode(b"VE3XYZ-9>APRS:!4523.  N/07540.  W>").unwrap();
+        assert!((r.position.lat - 45.383333).abs() < 1e-5);
+
+        // not positions
+        assert_eq!(decode(b"VE3XYZ-9>APRS::VE3ABC   :hello"), None);
+        assert_eq!(decode(b">APRS:!4523.74N/07540.61W>"), None);
+        assert_eq!(decode(b"VE3XYZ-9>APRS:!4523.74N/07540.61W"), None); // too short
+        assert_eq!(decode(b"4523.74241,N 07540.61255,W 03:17:37Z"), None);
+    }
+
+    #[test]
+    fn limits() {
+        let mut out = Vec::new();
+        let long = [b'x'; 60];
+        assert!(encode(
+            &mut out,
+            b"VE3XYZ-9",
+            HERE,
+            CAR,
+            Format::Compressed,
+            None,
+            &long
+        ));
+        assert_eq!(decode(&out).unwrap().comment.len(), MAX_COMMENT);
+        assert!(!encode(
+            &mut out,
+            &[b'A'; MAX_PACKET],
+            HERE,
+            CAR,
+            Format::Compressed,
+            None,
+            b""
+        ));
+    }
+
+    #[test]
+    fn battery() {
+        assert_eq!(battery_comment(3712).as_str(), "Bat 3.71V");
+        assert_eq!(battery_comment(12_050).as_str(), "Bat 12.05V");
+        assert_eq!(battery_comment(0).as_str(), "Bat 0.00V");
+    }
+}
@@ -0,0 +1,67 @@
+//! The few things that differ between the Cortex-M MCUs (stm32 hals) and RISC-V (gd32vf103xx hal),
+//! so the rest of the crate and the binaries do not use the cortex_m or riscv crates directly.
+//!
+//!   use lora_gps::arch::entry;     in place of  use cortex_m_rt::entry;
+//!   arch::free(|cs| ...)            in place of  cortex_m::interrupt::free(|cs| ...)
+//!
+//!  Semihosting is only available on Cortex-M, so on RISC-V debug builds also use panic_halt and
+//!  logln! needs feature log-uart (or log-defmt) to print anything (see src/log.rs).
+
+#[cfg(not(feature = "gd32vf103xx"))]
+pub use cortex_m::interrupt::{free, CriticalSection, Mutex};
+#[cfg(not(feature = "gd32vf103xx"))]
+pub use cortex_m_rt::entry;
+
+#[cfg(feature = "gd32vf103xx")]
+pub use riscv::interrupt::{free, CriticalSection, Mutex};
+#[cfg(feature = "gd32vf103xx")]
+pub use riscv_rt::entry;
+
+// Reset the MCU.
+#[cfg(not(feature = "gd32vf103xx"))]
+pub fn reset() -> ! {
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+// The Bumblebee core of the GD32VF103 has no SCB, it resets the MCU on the key written to MSFTRST
+// in its timer unit (as SysTimer_SoftwareReset in the Nuclei SDK).
+#[cfg(feature = "gd32vf103xx")]
+const MSFTRST: *mut u32 = 0xD100_0FF0 as *mut u32;
+#[cfg(feature = "gd32vf103xx")]
+const MSFTRST_KEY: u32 = 0x8000_0A5F;
+
+#[cfg(feature = "gd32vf103xx")]
+pub fn reset() -> ! {
+    unsafe { core::ptr::write_volatile(MSFTRST, MSFTRST_KEY) };
+    loop {
+        riscv::asm::nop();
+    }
+}
+
+// Busy wait for at least n core clock cycles, for when there is no timer (eg after setup() failed).
+#[cfg(not(feature = "gd32vf103xx"))]
+pub fn delay_cycles(n: u32) {
+    cortex_m::asm::delay(n)
+}
+
+// each nop takes at least a cycle
+#[cfg(feature = "gd32vf103xx")]
+pub fn delay_cycles(n: u32) {
+    for _ in 0..n {
+        riscv::asm::nop();
+    }
+}
+
+// Mutex for sharing a bus (eg i2c for a display and adcs) with shared_bus, using the critical
+// section of this MCU. shared_bus only provides one for Cortex-M.
+pub struct BusMutex<T>(Mutex<T>);
+
+impl<T> shared_bus::BusMutex<T> for BusMutex<T> {
+    fn create(v: T) -> Self {
+        BusMutex(Mutex::new(v))
+    }
+
+    fn lock<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        free(|cs| f(self.0.borrow(cs)))
+    }
+}
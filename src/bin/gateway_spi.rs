@@ -0,0 +1,124 @@
+//! Gateway. Receive packets with LoRa using crate radio_sx127x (on SPI) and forward each one, with
+//! RSSI and SNR, as a line of JSON on the usart (the one used for GPS in the other binaries) to a PC.
+//! Unlike receive_spi this does not need semihosting, so runs without a debugger attached.
+//! See host/ for the PC side.
+//!  Using  sck, miso, mosi, cs, reset and D00, D01. Not yet using  D02, D03
+//!  For pin connections see the setup() sections in src/lora_spi_gps_usart.rs.
+//! Tested using an RFM95 style radio.
+
+#![no_std]
+#![no_main]
+
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
+use panic_semihosting as _;
+
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
+use panic_halt as _;
+
+use lora_gps::arch::entry;
+
+use embedded_hal::delay::blocking::DelayMs;
+
+// trait needs to be in scope to find  methods start_receive and check_receive.
+use radio::Receive;
+
+use radio_sx127x::device::PacketInfo;
+
+use heapless::String;
+
+use lora_gps::gateway::{json_line, write_all, MAX_LINE};
+use lora_gps::lora_spi_gps_usart::{setup, Feed, LED};
+use lora_gps::supervisor::{Recovery, Supervisor};
+
+#[cfg(feature = "hopping")]
+use lora_gps::hopping::Follower;
+#[cfg(feature = "fhss")]
+use lora_gps::lora_spi_gps_usart::fhss_delay;
+#[cfg(any(feature = "hopping", feature = "fhss"))]
+use lora_gps::lora_spi_gps_usart::CONFIG_HOP;
+#[cfg(feature = "hopping")]
+use lora_gps::packet::decode;
+
+const POLL_MS: u32 = 10; // between checks for a received packet
+
+#[entry]
+fn main() -> ! {
+    // usart tx (9600 bps) goes to the PC, eg through a USB serial adapter
+    let (mut lora, mut tx, _rx, _i2c, mut led, mut wdog) = setup(); //delay is available in lora.delay_ms()
+    led.off();
+
+    let mut supervisor = Supervisor::new();
+
+    // with feature hopping, listen on the senders' channels (see src/hopping.rs)
+    #[cfg(feature = "hopping")]
+    let mut follower = Follower::new(CONFIG_HOP);
+    #[cfg(feature = "hopping")]
+    let started = follower.listen(&mut lora);
+    #[cfg(not(feature = "hopping"))]
+    let started = lora.start_receive();
+    let _ = supervisor.check(&mut lora, started); // failures are handled in the loop
+
+    let mut buff = [0u8; 1024];
+    let mut info = PacketInfo::default();
+    let mut line: String<MAX_LINE> = String::new();
+
+    loop {
+        let poll = match lora.check_receive(false) {
+            Ok(true) => lora.get_received(&mut info, &mut buff).map(Some),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
+
+        match supervisor.check(&mut lora, poll) {
+            Ok(Some(n)) => {
+                if json_line(&mut line, info.rssi, info.snr, &buff[..n]) {
+                    // nothing useful to do if the usart fails, the next packet will try again
+                    let _ = write_all(&mut tx, line.as_bytes());
+                };
+                led.on();
+                let _ = lora.delay_ms(20u32);
+                led.off();
+
+                #[cfg(feature = "hopping")]
+                if follower
+                    .received(decode(&buff[..n]).map(|(h, _)| h.seq))
+                    .is_some()
+                {
+                    let started = follower.listen(&mut lora);
+                    let _ = supervisor.check(&mut lora, started);
+                };
+            }
+
+            Ok(None) => {
+                #[cfg(feature = "hopping")]
+                if follower.elapsed(POLL_MS).is_some() {
+                    let started = follower.listen(&mut lora);
+                    let _ = supervisor.check(&mut lora, started);
+                };
+            }
+
+            // Reinit returns the radio to CONFIG_RADIO, so with hopping it is moved back
+            Err(Recovery::Reinitialised) => {
+                #[cfg(feature = "hopping")]
+                let started = follower.listen(&mut lora);
+                #[cfg(not(feature = "hopping"))]
+                let started = lora.start_receive();
+                let _ = supervisor.check(&mut lora, started);
+            }
+
+            Err(Recovery::Retry) => (), // eg transient timeout or CRC errors, keep listening
+        };
+
+        // progress is the radio working, whether or not anything was received
+        if supervisor.healthy() {
+            wdog.feed();
+        };
+
+        // with feature fhss the radio may be receiving a packet that hops
+        #[cfg(feature = "fhss")]
+        let _ = fhss_delay(&mut lora, &CONFIG_HOP, POLL_MS);
+        #[cfg(not(feature = "fhss"))]
+        let _ = lora.delay_ms(POLL_MS);
+    }
+}
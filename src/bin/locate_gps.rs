@@ -0,0 +1,240 @@
+//! Handheld receiver for walking towards trackers.
+//! Receive position reports with LoRa using crate radio_sx127x (on SPI), read own position from GPS on usart,
+//! and display distance and direction to the last reported position of each sender on an ssd1306 oled (i2c).
+//!  Using  sck, miso, mosi, cs, reset and D00, D01. Not yet using  D02, D03
+//!  For pin connections see the setup() sections in src/lora_spi_gps_usart.rs.
+//! Tested using an RFM95 style radio.
+
+#![no_std]
+#![no_main]
+
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
+use panic_semihosting as _;
+
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
+use panic_halt as _;
+
+use lora_gps::arch::entry;
+
+use embedded_hal::delay::blocking::DelayMs;
+
+// trait needs to be in scope to find  methods start_receive and check_receive.
+use radio::Receive;
+
+use radio_sx127x::device::PacketInfo;
+
+use heapless::{String, Vec};
+
+//use embedded_hal::serial::Read;
+use old_e_h::serial::Read;
+
+use core::fmt::Write;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_8X13, MonoTextStyle, MonoTextStyleBuilder}, //FONT_6X10
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use ssd1306::{mode::BufferedGraphicsMode, prelude::*, I2CDisplayInterface, Ssd1306};
+
+use lora_gps::geodesy::{compass_point, distance, initial_bearing, Position};
+use lora_gps::lora_spi_gps_usart::{setup, Feed, LED};
+use lora_gps::nmea::{parse_position, parse_report, parse_rmc_position};
+use lora_gps::packet::{decode, Kind};
+
+// number of senders remembered. Only the first three fit on the display.
+const MAX_SENDERS: usize = 8;
+
+struct Sender {
+    id: Vec<u8, 16>,
+    position: Position,
+}
+
+fn to_str(x: &[u8]) -> &str {
+    match core::str::from_utf8(x) {
+        Ok(str) => &str,
+        Err(_error) => "?",
+    }
+}
+
+// remember the last reported position of each sender
+fn update(senders: &mut Vec<Sender, MAX_SENDERS>, id: &[u8], position: Position) {
+    let id = &id[..id.len().min(16)];
+
+    match senders.iter_mut().find(|s| &s.id[..] == id) {
+        Some(s) => s.position = position,
+        None => {
+            let mut v = Vec::new();
+            v.extend_from_slice(id).unwrap(); // cannot fail, id is truncated to capacity
+            // if the table is full the new sender is ignored
+            let _ = senders.push(Sender { id: v, position });
+        }
+    };
+}
+
+fn display<S>(
+    here: Option<Position>,
+    senders: &[Sender],
+    text_style: MonoTextStyle<BinaryColor>,
+    disp: &mut Ssd1306<impl WriteOnlyDataCommand, S, BufferedGraphicsMode<S>>,
+) -> ()
+where
+    S: DisplaySize,
+{
+    let mut lines: [String<32>; 4] = [
+        heapless::String::new(),
+        heapless::String::new(),
+        heapless::String::new(),
+        heapless::String::new(),
+    ];
+
+    match here {
+        None => write!(lines[0], "no GPS fix").unwrap(),
+        // 16 characters, eg " 45.379  -75.677"
+        Some(p) => write!(lines[0], "{:7.3} {:8.3}", p.lat, p.lon).unwrap(),
+    };
+
+    // display is 16 characters wide with FONT_8X13:  id(5) distance(6) compass point(3)
+    for (i, s) in senders.iter().take(3).enumerate() {
+        let id = to_str(&s.id[..s.id.len().min(5)]);
+        match here {
+            None => write!(lines[i + 1], "{:5} ?", id).unwrap(),
+            Some(p) => {
+                let d = distance(p, s.position);
+                let b = compass_point(initial_bearing(p, s.position));
+                if d < 10_000.0 {
+                    write!(lines[i + 1], "{:5}{:>6}m {}", id, d as u32, b).unwrap()
+                } else {
+                    write!(lines[i + 1], "{:5}{:>5}km {}", id, (d / 1000.0) as u32, b).unwrap()
+                }
+            }
+        };
+    }
+
+    disp.clear();
+    for i in 0..lines.len() {
+        // shift down by 10 so first line is on display
+        Text::new(&lines[i], Point::new(0, i as i32 * 16 + 10), text_style)
+            .draw(&mut *disp)
+            .unwrap();
+    }
+    disp.flush().unwrap();
+    ()
+}
+
+#[entry]
+fn main() -> ! {
+    let (mut lora, _tx_gps, mut rx_gps, i2c, mut led, mut wdog) = setup(); //delay is available in lora.delay_ms()
+    led.off();
+
+    let interface = I2CDisplayInterface::new(i2c);
+
+    // set display size 128x32 or 128x64 and Font6x8 or Font8x16
+    let mut disp = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
+        .into_buffered_graphics_mode();
+    disp.init().unwrap();
+
+    let text_style = MonoTextStyleBuilder::new()
+        .font(&FONT_8X13) //.font(&FONT_6X10)
+        .text_color(BinaryColor::On)
+        .build();
+
+    Text::with_baseline(
+        "Display initialized ...",
+        Point::zero(),
+        text_style,
+        Baseline::Top,
+    )
+    .draw(&mut disp)
+    .unwrap();
+
+    disp.flush().unwrap();
+
+    lora.start_receive().unwrap(); // should handle error
+
+    let mut buff = [0u8; 1024];
+    let mut n: usize;
+    let mut info = PacketInfo::default();
+
+    let mut buffer: Vec<u8, 80> = Vec::new(); // gps line, up to 80  u8 elements on stack
+    let mut good = false; // true while capturing a line
+
+    let mut here: Option<Position> = None;
+    let mut senders: Vec<Sender, MAX_SENDERS> = Vec::new();
+
+    loop {
+        let mut changed = false;
+
+        // Read whatever the gps has sent without blocking, so packets are not missed while
+        // waiting for a gps line. At 9600 bps a byte arrives about every millisecond.
+        loop {
+            match rx_gps.read() {
+                Ok(byte) => {
+                    if byte == 36 {
+                        //  $ is 36. start of a line
+                        buffer.clear();
+                        good = true; //start capturing line
+                    };
+
+                    if good {
+                        // \r is 13. Drop the line if the buffer is full.
+                        if buffer.push(byte).is_err() || byte == 13 {
+                            if let Some(p) = parse_rmc_position(&buffer) {
+                                here = Some(p);
+                                changed = true;
+                            };
+                            buffer.clear();
+                            good = false;
+                        };
+                    };
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(_)) => {
+                    // overrun or framing error, the line is lost
+                    buffer.clear();
+                    good = false;
+                    break;
+                }
+            };
+        }
+
+        match lora.check_receive(false) {
+            Ok(v) if v => {
+                wdog.feed(); // progress, the radio working (the gps is optional here)
+                n = lora.get_received(&mut info, &mut buff).unwrap();
+                match decode(&buff[..n]) {
+                    Some((h, payload)) => {
+                        if h.kind == Kind::Data {
+                            if let Some(position) = parse_position(payload) {
+                                update(&mut senders, &h.source, position);
+                                changed = true;
+                            };
+                        };
+                    }
+                    // plain text from older senders
+                    None => {
+                        if let Some((id, position)) = parse_report(&buff[..n]) {
+                            update(&mut senders, id, position);
+                            changed = true;
+                        };
+                    }
+                };
+                led.on();
+                let _ = lora.delay_ms(2u32);
+                led.off();
+            }
+
+            Ok(_v) => wdog.feed(),
+
+            Err(_err) => (), // transient timeout or CRC errors, keep listening
+        };
+
+        if changed {
+            display(here, &senders, text_style, &mut disp);
+        };
+
+        let _ = lora.delay_ms(1u32);
+    }
+}
@@ -0,0 +1,130 @@
+//! Mesh node. Beacons its route to the base station, keeps a neighbour table and forwards
+//! packets (including those heard from trackers) towards the base with LoRa using crate radio_sx127x (on SPI).
+//! The node with MESH_ADDR equal to BASE_ADDR is the base and prints delivered packets with logln!.
+//!  Using  sck, miso, mosi, cs, reset and D00, D01. Not yet using  D02, D03
+//!  For pin connections see the setup() sections in src/lora_spi_gps_usart.rs.
+//! Tested using an RFM95 style radio.
+
+#![no_std]
+#![no_main]
+
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
+use panic_semihosting as _;
+
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
+use panic_halt as _;
+
+use lora_gps::arch::entry;
+
+use embedded_hal::delay::blocking::DelayMs;
+
+// trait needs to be in scope to find  method start_receive.
+use radio::Receive;
+
+use heapless::Vec;
+
+use lora_gps::lbt::ListenBeforeTalk;
+use lora_gps::log::drain;
+use lora_gps::logln;
+use lora_gps::lora_spi_gps_usart::{setup, Fed, Feed, CONFIG_LBT, LED};
+use lora_gps::mesh::Mesh;
+use lora_gps::packet::{decode, parse_addr, MAX_PACKET};
+use lora_gps::supervisor::{last_resort, Recovery, Supervisor};
+
+// The mesh sends beacons and forwards packets on FREQUENCY, without hopping, and nodes do not
+// follow hopping senders.
+#[cfg(feature = "hopping")]
+compile_error!("feature hopping cannot be used with mesh_spi");
+
+// between logs of the listen before talk counters
+const STATS_MS: u32 = 60_000;
+
+fn to_str(x: &[u8]) -> &str {
+    match core::str::from_utf8(x) {
+        Ok(str) => &str,
+        Err(_error) => "problem converting u8 to str ",
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // set these with
+    // MESH_ADDR=3 BASE_ADDR=1 cargo build ...
+    // Every node needs a different MESH_ADDR (0 to 254) and the same BASE_ADDR.
+    let addr =
+        parse_addr(option_env!("MESH_ADDR").unwrap_or("1")).expect("MESH_ADDR should be 0 to 254");
+    let base =
+        parse_addr(option_env!("BASE_ADDR").unwrap_or("1")).expect("BASE_ADDR should be 0 to 254");
+
+    let (mut lora, mut tx, _rx, _i2c, mut led, mut wdog) = setup(); //delay is available in lora.delay_ms()
+    led.off();
+
+    let mut mesh = Mesh::new(addr, base);
+    let mut lbt = ListenBeforeTalk::new(CONFIG_LBT, &[addr]);
+
+    let mut supervisor = Supervisor::new();
+    let started = lora.start_receive();
+    let _ = supervisor.check(&mut lora, started); // failures are handled in the loop
+
+    let mut buff = [0u8; 1024];
+    let mut delivered: Vec<u8, MAX_PACKET> = Vec::new();
+
+    // milliseconds since start, counted from the loop delay (so only approximate)
+    let mut now_ms: u32 = 0;
+
+    logln!("mesh node {} base {}", addr, base);
+
+    loop {
+        drain(&mut tx); // log output, with feature log-uart
+
+        let polled = mesh.poll(
+            &mut Fed::with_lbt(&mut lora, &mut wdog, &mut lbt),
+            now_ms,
+            &mut buff,
+            &mut delivered,
+        );
+        match supervisor.check(&mut lora, polled) {
+            Ok(true) => {
+                if let Some((h, payload)) = decode(&delivered) {
+                    logln!(
+                        "{} #{} via {:?} {}",
+                        to_str(&h.source),
+                        h.seq,
+                        &h.path[..],
+                        to_str(payload)
+                    );
+                };
+                led.on();
+                let _ = lora.delay_ms(20u32);
+                led.off();
+            }
+
+            Ok(false) => (),
+
+            Err(Recovery::Reinitialised) => {
+                let started = lora.start_receive();
+                let _ = supervisor.check(&mut lora, started);
+            }
+
+            Err(Recovery::Retry) => (),
+        };
+
+        // progress is the radio working, whether or not anything was received
+        if supervisor.healthy() {
+            wdog.feed();
+        };
+
+        match lora.delay_ms(10u32) {
+            Ok(b) => b, // b is ()
+            Err(_err) => {
+                logln!("Error returned from lora.try_delay_ms().");
+                last_resort(); // resets the MCU, panic! would only halt in release builds
+            }
+        };
+        now_ms = now_ms.wrapping_add(10);
+        if now_ms % STATS_MS == 0 {
+            logln!("lbt {:?}", lbt.counters());
+        };
+    }
+}
@@ -8,15 +8,15 @@
 #![no_std]
 #![no_main]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 
-use cortex_m::prelude::_embedded_hal_adc_OneShot;
-use cortex_m_rt::entry;
-use cortex_m_semihosting::*;
+use lora_gps::arch::{entry, BusMutex};
+use old_e_h::adc::OneShot;
 
 use embedded_hal::delay::blocking::DelayMs;
 use radio::Transmit;
@@ -39,7 +39,13 @@ use embedded_graphics::{
 
 use ssd1306::{mode::BufferedGraphicsMode, prelude::*, I2CDisplayInterface, Ssd1306};
 
-use lora_gps::lora_spi_gps_usart::{setup, LED};
+use lora_gps::lora_spi_gps_usart::{delay_fed, setup, Feed, LED};
+use lora_gps::supervisor::last_resort;
+use lora_gps::{log, logln};
+
+// log-uart writes to the usart, which here is the GPS usart
+#[cfg(feature = "log-uart")]
+compile_error!("feature log-uart cannot be used with monitor_gps, use log-defmt");
 
 fn display<S>(
     bat_mv: i16,
@@ -88,12 +94,12 @@ fn main() -> ! {
     // or  cargo:rustc-env=SENDER_ID="whatever"
     let id = option_env!("SENDER_ID").expect("").as_bytes();
 
-    let (mut lora, _tx_gps, mut rx_gps, i2c, mut led) = setup(); //  lora (delay is available in lora)
+    let (mut lora, _tx_gps, mut rx_gps, i2c, mut led, mut wdog) = setup(); //  lora (delay is available in lora)
     led.off();
 
     // i2c oled and ads setup
 
-    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let manager = shared_bus::BusManager::<BusMutex<_>, _>::new(i2c);
     let interface = I2CDisplayInterface::new(manager.acquire());
 
     // set display size 128x32 or 128x64 and Font6x8 or Font8x16
@@ -161,7 +167,9 @@ fn main() -> ! {
         if good {
             //push byte into buffer then transmit if error/buffer full or end of line. \r is 13, \n is 10
             if buffer.push(byte).is_err() || byte == 13 {
-                //hprintln!("{:?}", &buffer).unwrap();
+                wdog.feed(); // progress, a line from the GPS
+
+                //logln!("{:?}", &buffer);
 
                 buf2.clear();
                 // put id in first
@@ -188,8 +196,8 @@ fn main() -> ! {
                         buf2.push(*v).unwrap();
                     }
 
-                    //hprintln!("{:?}", &buf2).unwrap();
-                    hprint!(".").unwrap(); // print "."  on transmit of $GPRMC message (but not others)
+                    //logln!("{:?}", &buf2);
+                    log!("."); // print "."  on transmit of $GPRMC message (but not others)
                     led.on(); // double blink on transmit of decoded message, one here and one below.
                     let _ = lora.delay_ms(2u32);
                     led.off();
@@ -211,14 +219,14 @@ fn main() -> ! {
                         led.off();
                     }
                     Err(_err) => {
-                        hprintln!("Error returned from lora.start_transmit().").unwrap();
+                        logln!("Error returned from lora.start_transmit().");
                         //panic!("should reset in release mode.");
                     }
                 };
 
-                // Note hprintln! requires semihosting. If hprintln! (thus also match section below) are
-                // removed then this example works on battery power with no computer attached.
-                // (tested only on blackpill with stm32f411 )
+                // Note logln! uses semihosting in debug builds (see src/log.rs), which needs a computer
+                // attached. Release builds, or builds with feature log-defmt or log-uart, work on
+                // battery power with no computer attached. (tested only on blackpill with stm32f411 )
 
                 // The first transmission often return false and prints "x", but works after that.
                 // If this continually returns "TX not complete" then the radio should probably be reset,
@@ -227,12 +235,12 @@ fn main() -> ! {
                 match lora.check_transmit() {
                     Ok(b) => {
                         if !b {
-                            hprint!("x").unwrap();
+                            log!("x");
                             // if multible times then panic!("should reset in release mode.");
                         }
                     }
                     Err(_err) => {
-                        hprintln!("Error returned from lora.check_transmit().").unwrap();
+                        logln!("Error returned from lora.check_transmit().");
                         //panic!("should reset in release mode.");
                     }
                 };
@@ -291,11 +299,11 @@ fn main() -> ! {
 
                 buffer.clear();
                 good = false;
-                match lora.delay_ms(5000u32) {
+                match delay_fed(&mut lora, &mut wdog, 5000u32) {
                     Ok(b) => b, // b is ()
                     Err(_err) => {
-                        hprintln!("Error returned from lora.try_delay_ms().").unwrap();
-                        panic!("should reset in release mode.");
+                        logln!("Error returned from lora.try_delay_ms().");
+                        last_resort(); // resets the MCU, panic! would only halt in release builds
                     }
                 };
             };
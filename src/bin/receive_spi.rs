@@ -1,4 +1,4 @@
-//! Receive message with LoRa using crate radio_sx127x (on SPI) and print on semihost.
+//! Receive message with LoRa using crate radio_sx127x (on SPI) and print with logln! (see src/log.rs).
 //!  Using  sck, miso, mosi, cs, reset and D00, D01. Not yet using  D02, D03
 //!  For pin connections see the setup() sections in src/lora_spi_gps_usart.rs.
 //! Tested using an RFM95 style radio.
@@ -6,15 +6,15 @@
 #![no_std]
 #![no_main]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 //use panic_reset;
 
-use cortex_m_rt::entry;
-use cortex_m_semihosting::*;
+use lora_gps::arch::entry;
 
 use embedded_hal::delay::blocking::DelayMs;
 
@@ -23,7 +23,28 @@ use radio::Receive;
 
 use radio_sx127x::device::PacketInfo;
 
-use lora_gps::lora_spi_gps_usart::{setup, LED};
+use lora_gps::aprs;
+use lora_gps::log::drain;
+use lora_gps::logln;
+use lora_gps::lora_spi_gps_usart::{setup, Feed, LED};
+use lora_gps::packet::{decode, Kind};
+use lora_gps::supervisor::{last_resort, Recovery, Supervisor};
+
+#[cfg(feature = "hopping")]
+use lora_gps::hopping::Follower;
+#[cfg(feature = "fhss")]
+use lora_gps::lora_spi_gps_usart::fhss_delay;
+#[cfg(any(feature = "hopping", feature = "fhss"))]
+use lora_gps::lora_spi_gps_usart::CONFIG_HOP;
+
+// With feature fsk, receive in FSK (see src/fsk.rs) from send_spi built with fsk. The hopping
+// channels are LoRa channels.
+#[cfg(feature = "fsk")]
+use lora_gps::lora_spi_gps_usart::set_fsk;
+#[cfg(all(feature = "fsk", any(feature = "hopping", feature = "fhss")))]
+compile_error!("feature fsk cannot be used with hopping or fhss");
+
+const POLL_MS: u32 = 100; // between checks for a received packet
 
 fn to_str(x: &[u8]) -> &str {
     match core::str::from_utf8(x) {
@@ -34,42 +55,129 @@ fn to_str(x: &[u8]) -> &str {
 
 #[entry]
 fn main() -> ! {
-    let (mut lora, _rx, _tx, _i2c, mut led) = setup(); //delay is available in lora.delay_ms()
+    let (mut lora, mut tx, _rx, _i2c, mut led, mut wdog) = setup(); //delay is available in lora.delay_ms()
     led.off();
 
-    lora.start_receive().unwrap(); // should handle error
+    let mut supervisor = Supervisor::new();
+
+    #[cfg(feature = "fsk")]
+    set_fsk(&mut lora, &mut supervisor);
+
+    // with feature hopping, listen on the senders' channels (see src/hopping.rs)
+    #[cfg(feature = "hopping")]
+    let mut follower = Follower::new(CONFIG_HOP);
+    #[cfg(feature = "hopping")]
+    let started = follower.listen(&mut lora);
+    #[cfg(not(feature = "hopping"))]
+    let started = lora.start_receive();
+    let _ = supervisor.check(&mut lora, started); // failures are handled in the loop
 
     let mut buff = [0u8; 1024];
-    let mut n: usize;
     let mut info = PacketInfo::default();
 
     loop {
-        let poll = lora.check_receive(false);
+        drain(&mut tx); // log output, with feature log-uart
+
         // false (the restart option) specifies whether transient timeout or CRC errors should be
         // internally handled (returning Ok(false) or passed back to the caller as errors.
+        // They are passed back, so the supervisor counts them.
+        let poll = match lora.check_receive(false) {
+            Ok(true) => lora.get_received(&mut info, &mut buff).map(Some),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
 
-        match poll {
-            Ok(v) if v => {
-                n = lora.get_received(&mut info, &mut buff).unwrap();
-                //hprintln!("RX complete ({:?}, length: {})", info, n).unwrap();
-                //hprintln!("{:?}", &buff[..n]).unwrap();
+        match supervisor.check(&mut lora, poll) {
+            Ok(Some(n)) => {
+                #[cfg(feature = "hopping")]
+                let seq = decode(&buff[..n]).map(|(h, _)| h.seq);
+                //logln!("RX complete ({:?}, length: {})", info, n);
+                //logln!("{:?}", &buff[..n]);
                 // for some reason the next prints twice?
-                hprintln!("{}", to_str(&buff[..n])).unwrap();
+                // packets with a header first, as the payload could look like an APRS report
+                match decode(&buff[..n]) {
+                    // mesh beacons are not of interest here
+                    Some((h, _)) if h.kind == Kind::Beacon => (),
+                    // path shows the relays that delivered the packet, [] if received directly
+                    Some((h, payload)) => {
+                        let alert = match h.kind {
+                            Kind::Alert => "ALERT ",
+                            Kind::Status => "STATUS ", // eg no fix since 03:17:37Z
+                            _ => "",
+                        };
+                        logln!(
+                            "{} #{} via {:?} {}{}",
+                            to_str(&h.source),
+                            h.seq,
+                            &h.path[..],
+                            alert,
+                            to_str(payload)
+                        )
+                    }
+                    None => match aprs::decode(&buff[..n]) {
+                        // from send_gps with feature aprs, or other LoRa-APRS trackers
+                        Some(r) => logln!(
+                            "APRS {} {:.5} {:.5} {}",
+                            to_str(r.source),
+                            r.position.lat,
+                            r.position.lon,
+                            to_str(r.comment)
+                        ),
+                        // plain text from send_spi or older senders
+                        None => logln!("{}", to_str(&buff[..n])),
+                    },
+                };
                 led.on();
                 let _ = lora.delay_ms(20u32);
                 led.off();
+
+                #[cfg(feature = "hopping")]
+                if follower.received(seq).is_some() {
+                    let started = follower.listen(&mut lora);
+                    let _ = supervisor.check(&mut lora, started);
+                };
             }
 
-            Ok(_v) => (), // hprint!(".").unwrap(),   // print "." if nothing received
+            Ok(None) => {
+                // log!("."),   // print "." if nothing received
+                #[cfg(feature = "hopping")]
+                if follower.elapsed(POLL_MS).is_some() {
+                    let started = follower.listen(&mut lora);
+                    let _ = supervisor.check(&mut lora, started);
+                };
+            }
 
-            Err(err) => hprintln!("poll error {:?} ", err).unwrap(),
+            // Reinit returns the radio to CONFIG_RADIO, so with hopping it is moved back, and with
+            // fsk switched again
+            Err(Recovery::Reinitialised) => {
+                #[cfg(feature = "fsk")]
+                set_fsk(&mut lora, &mut supervisor);
+                #[cfg(feature = "hopping")]
+                let started = follower.listen(&mut lora);
+                #[cfg(not(feature = "hopping"))]
+                let started = lora.start_receive();
+                let _ = supervisor.check(&mut lora, started);
+            }
+
+            Err(Recovery::Retry) => (),
         };
 
-        match lora.delay_ms(100u32) {
+        // progress is the radio working, whether or not anything was received
+        if supervisor.healthy() {
+            wdog.feed();
+        };
+
+        // with feature fhss the radio may be receiving a packet that hops
+        #[cfg(feature = "fhss")]
+        let waited = fhss_delay(&mut lora, &CONFIG_HOP, POLL_MS);
+        #[cfg(not(feature = "fhss"))]
+        let waited = lora.delay_ms(POLL_MS);
+
+        match waited {
             Ok(b) => b, // b is ()
             Err(_err) => {
-                hprintln!("Error returned from lora.try_delay_ms().").unwrap();
-                panic!("should reset in release mode.");
+                logln!("Error returned from lora.try_delay_ms().");
+                last_resort(); // resets the MCU, panic! would only halt in release builds
             }
         };
     }
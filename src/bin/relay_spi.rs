@@ -0,0 +1,188 @@
+//! Store-and-forward relay to extend range. Receive packets with LoRa using crate radio_sx127x (on SPI),
+//! drop duplicates and packets with no hops left, then retransmit after a random delay with the relay
+//! address added to the packet path.
+//!  Using  sck, miso, mosi, cs, reset and D00, D01. Not yet using  D02, D03
+//!  For pin connections see the setup() sections in src/lora_spi_gps_usart.rs.
+//! Tested using an RFM95 style radio.
+
+#![no_std]
+#![no_main]
+
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
+use panic_semihosting as _;
+
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
+use panic_halt as _;
+
+use lora_gps::arch::entry;
+
+use embedded_hal::delay::blocking::DelayMs;
+
+// trait needs to be in scope to find  methods start_receive and check_receive.
+use radio::Receive;
+
+use radio_sx127x::device::PacketInfo;
+
+use heapless::Vec;
+
+use lora_gps::lbt::ListenBeforeTalk;
+use lora_gps::log::drain;
+use lora_gps::lora_spi_gps_usart::{
+    delay_fed, setup, transmit, wait_transmit, Feed, CONFIG_LBT, LED,
+};
+use lora_gps::packet::{parse_addr, MAX_PACKET};
+use lora_gps::prng::XorShift32;
+use lora_gps::relay::Relay;
+
+#[cfg(feature = "hopping")]
+use lora_gps::hopping::Follower;
+#[cfg(feature = "fhss")]
+use lora_gps::lora_spi_gps_usart::fhss_delay;
+#[cfg(any(feature = "hopping", feature = "fhss"))]
+use lora_gps::lora_spi_gps_usart::CONFIG_HOP;
+#[cfg(feature = "hopping")]
+use lora_gps::packet::decode;
+
+use lora_gps::supervisor::{last_resort, Recovery, Supervisor};
+use lora_gps::{log, logln};
+
+const POLL_MS: u32 = 10; // between checks for a received packet
+
+// Random delay before retransmitting, so relays that heard the same packet do not all
+// transmit at once (and the sender has finished).
+const RELAY_DELAY_MIN_MS: u32 = 100;
+const RELAY_DELAY_MAX_MS: u32 = 1000;
+
+// between logs of the listen before talk counters
+const STATS_MS: u32 = 60_000;
+
+#[entry]
+fn main() -> ! {
+    // set this with
+    // RELAY_ADDR=7 cargo build ...
+    // Each relay needs a different address (0 to 254) for the path trace.
+    let addr = parse_addr(option_env!("RELAY_ADDR").unwrap_or("1"))
+        .expect("RELAY_ADDR should be 0 to 254");
+
+    let (mut lora, mut tx, _rx, _i2c, mut led, mut wdog) = setup(); //delay is available in lora.delay_ms()
+    led.off();
+
+    let mut relay = Relay::new(addr);
+    let mut rng = XorShift32::new(addr as u32);
+    let mut lbt = ListenBeforeTalk::new(CONFIG_LBT, &[addr]);
+
+    let mut supervisor = Supervisor::new();
+
+    // with feature hopping, listen on the senders' channels (see src/hopping.rs)
+    #[cfg(feature = "hopping")]
+    let mut follower = Follower::new(CONFIG_HOP);
+    #[cfg(feature = "hopping")]
+    let started = follower.listen(&mut lora);
+    #[cfg(not(feature = "hopping"))]
+    let started = lora.start_receive();
+    let _ = supervisor.check(&mut lora, started); // failures are handled in the loop
+
+    let mut buff = [0u8; 1024];
+    let mut info = PacketInfo::default();
+    let mut out: Vec<u8, MAX_PACKET> = Vec::new();
+
+    logln!("relay {} listening", addr);
+
+    // milliseconds since the counters were logged, counted from the loop delay (so only approximate)
+    let mut since_stats: u32 = 0;
+
+    loop {
+        drain(&mut tx); // log output, with feature log-uart
+
+        let poll = match lora.check_receive(false) {
+            Ok(true) => lora.get_received(&mut info, &mut buff).map(Some),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
+
+        match supervisor.check(&mut lora, poll) {
+            Ok(Some(n)) => {
+                rng.mix(&buff[..n]);
+
+                let relayed = relay.process(&buff[..n], &mut out);
+                if relayed {
+                    let wait = rng.between(RELAY_DELAY_MIN_MS, RELAY_DELAY_MAX_MS);
+                    let _ = delay_fed(&mut lora, &mut wdog, wait);
+                    // a packet dropped by listen before talk is logged with the lbt counters
+                    let dropped = lbt.counters().dropped;
+                    let sent = transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &out);
+                    if !sent
+                        && lbt.counters().dropped == dropped
+                        && !wait_transmit(&mut lora, &mut wdog, 2000)
+                    {
+                        logln!("relay transmit did not complete");
+                    };
+                    log!(">"); // print ">" for each relayed packet
+                };
+
+                // Back to receive after a relayed packet. With feature hopping the packet went on
+                // the channel of its sequence number, as the original, and the next one is on
+                // another.
+                #[cfg(feature = "hopping")]
+                let restart = follower
+                    .received(decode(&buff[..n]).map(|(h, _)| h.seq))
+                    .is_some()
+                    || relayed;
+                #[cfg(not(feature = "hopping"))]
+                let restart = relayed;
+                if restart {
+                    #[cfg(feature = "hopping")]
+                    let started = follower.listen(&mut lora);
+                    #[cfg(not(feature = "hopping"))]
+                    let started = lora.start_receive();
+                    let _ = supervisor.check(&mut lora, started);
+                };
+            }
+
+            Ok(None) => {
+                #[cfg(feature = "hopping")]
+                if follower.elapsed(POLL_MS).is_some() {
+                    let started = follower.listen(&mut lora);
+                    let _ = supervisor.check(&mut lora, started);
+                };
+            }
+
+            // Reinit returns the radio to CONFIG_RADIO, so with hopping it is moved back
+            Err(Recovery::Reinitialised) => {
+                #[cfg(feature = "hopping")]
+                let started = follower.listen(&mut lora);
+                #[cfg(not(feature = "hopping"))]
+                let started = lora.start_receive();
+                let _ = supervisor.check(&mut lora, started);
+            }
+
+            Err(Recovery::Retry) => (),
+        };
+
+        // progress is the radio working, whether or not anything was received
+        if supervisor.healthy() {
+            wdog.feed();
+        };
+
+        // with feature fhss the radio may be receiving a packet that hops
+        #[cfg(feature = "fhss")]
+        let waited = fhss_delay(&mut lora, &CONFIG_HOP, POLL_MS);
+        #[cfg(not(feature = "fhss"))]
+        let waited = lora.delay_ms(POLL_MS);
+
+        match waited {
+            Ok(b) => b, // b is ()
+            Err(_err) => {
+                logln!("Error returned from lora.try_delay_ms().");
+                last_resort(); // resets the MCU, panic! would only halt in release builds
+            }
+        };
+
+        since_stats += POLL_MS;
+        if since_stats >= STATS_MS {
+            logln!("lbt {:?}", lbt.counters());
+            since_stats = 0;
+        };
+    }
+}
@@ -6,16 +6,15 @@
 #![no_std]
 #![no_main]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 
-use cortex_m_rt::entry;
-use cortex_m_semihosting::*;
 use embedded_hal::delay::blocking::DelayMs;
-use radio::Transmit;
+use lora_gps::arch::entry;
 
 use heapless::Vec;
 use nb::block;
@@ -23,38 +22,331 @@ use nb::block;
 //use embedded_hal::serial::Read;
 use old_e_h::serial::Read;
 
-use lora_gps::lora_spi_gps_usart::{setup, LED};
+use lora_gps::fix::{FixState, FixTracker};
+use lora_gps::geodesy::Position;
+use lora_gps::geofence::{Crossing, Geofence, GeofenceMonitor};
+use lora_gps::lora_spi_gps_usart::{delay_fed, setup, Feed, LED};
+use lora_gps::nmea::{format_position, format_time};
+use lora_gps::packet::MAX_PACKET;
+use lora_gps::supervisor::last_resort;
+use lora_gps::{log, logln};
+
+#[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+use lora_gps::fix::no_fix_message;
+#[cfg(not(feature = "lorawan"))]
+use lora_gps::lbt::ListenBeforeTalk;
+#[cfg(not(feature = "lorawan"))]
+use lora_gps::lora_spi_gps_usart::{transmit, CONFIG_LBT};
+#[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+use lora_gps::packet::{Header, Kind};
+
+#[cfg(feature = "battery")]
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+#[cfg(any(feature = "aprs", feature = "battery"))]
+use lora_gps::aprs;
+#[cfg(feature = "battery")]
+use old_e_h::adc::OneShot;
+
+#[cfg(feature = "pmtk")]
+use heapless::String;
+#[cfg(feature = "pmtk")]
+use lora_gps::pmtk::{send, set_baud, set_output, set_update_rate, Ack, FIX_OUTPUT, MAX_COMMAND};
+#[cfg(feature = "ubx")]
+use lora_gps::ubx::{
+    cfg_msg, cfg_nav5, cfg_prt_uart, cfg_rate, cfg_rxm, command, wait_ack, Decoder, DynModel,
+    NavPvt, CFG_PRT, MAX_FRAME, NAV_PVT, PROTO_NMEA, PROTO_UBX,
+};
+#[cfg(any(feature = "ubx", feature = "pmtk"))]
+use lora_gps::{
+    gateway::write_all,
+    lora_spi_gps_usart::{set_gps_baud, GPS_BAUD},
+};
+#[cfg(any(feature = "ubx", feature = "pmtk"))]
+use old_e_h::serial::Write;
+
+#[cfg(feature = "lorawan")]
+use lora_gps::lora_spi_gps_usart::Fed;
+#[cfg(feature = "lorawan")]
+use lora_gps::lorawan::{lpp_gps, parse_hex, Credentials, Device, Region};
+#[cfg(feature = "lorawan")]
+use lora_gps::prng::XorShift32;
+
+// Geofences checked against every fix. Crossing a boundary sends an alert packet and,
+// while the tracker is outside all of them, reports are sent every FAST_REPORT_MS.
+// Replace these with your own area. An empty list disables geofencing.
+const GEOFENCES: &[Geofence] = &[
+    Geofence::Circle {
+        center: Position::new(45.3957, -75.6769),
+        radius_m: 500.0,
+    },
+    Geofence::Polygon(&[
+        Position::new(45.3900, -75.6850),
+        Position::new(45.3900, -75.6700),
+        Position::new(45.4000, -75.6700),
+        Position::new(45.4000, -75.6850),
+    ]),
+];
+
+const REPORT_MS: u32 = 5000; // delay between reports
+const FAST_REPORT_MS: u32 = 1000; // delay between reports while outside all geofences
+const ALERT_REPEATS: usize = 3; // alerts are sent several times as they are more important than reports
+#[cfg(not(feature = "lorawan"))]
+const STATS_REPORTS: u32 = 12; // reports between logs of the listen before talk counters
+
+// log-uart writes to the usart, which here is the GPS usart (and with ubx or pmtk carries commands)
+#[cfg(feature = "log-uart")]
+compile_error!("feature log-uart cannot be used with send_gps, use log-defmt");
+
+// With feature lorawan, positions are sent as Cayenne LPP uplinks on GPS_PORT and alerts as
+// confirmed uplinks on ALERT_PORT, rather than on the raw LoRa link. Raw NMEA lines are not sent.
+#[cfg(feature = "lorawan")]
+const LORAWAN_REGION: Region = Region::Us915 { subband: 2 };
+#[cfg(feature = "lorawan")]
+const GPS_PORT: u8 = 1;
+#[cfg(feature = "lorawan")]
+const ALERT_PORT: u8 = 2;
+
+// With feature aprs, positions are sent as LoRa-APRS reports from SENDER_ID (which must then be a
+// callsign-SSID) that iGates understand, and alerts as reports with eg "EXIT 01" as the comment.
+// Raw NMEA lines are not sent.
+#[cfg(all(feature = "aprs", feature = "lorawan"))]
+compile_error!("features aprs and lorawan cannot be used together");
+
+// With feature battery, the voltage on A0 of an ADS1015 on the i2c bus (ADDR pin to GND, as the
+// first ADC of monitor_gps) is added to each position report, eg "4523.74241,N 07540.61255,W
+// 03:17:37Z Bat 3.71V", and with feature aprs is the comment (see aprs::battery_comment).
+// Reports are sent without it if the ADC does not answer.
+#[cfg(feature = "battery")]
+const BATTERY_MV_PER_COUNT: u16 = 2; // at FullScaleRange::Within4_096V, calibrate for any divider
+#[cfg(all(feature = "battery", feature = "lorawan"))]
+compile_error!("feature battery is not sent in LoRaWAN uplinks, it cannot be used with lorawan");
+
+// Hopping picks the channel from the packet header, which APRS reports do not have. LoRaWAN has
+// its own channel plan (LORAWAN_REGION).
+#[cfg(all(feature = "hopping", any(feature = "aprs", feature = "lorawan")))]
+compile_error!("feature hopping cannot be used with aprs or lorawan");
+#[cfg(feature = "aprs")]
+const APRS_SYMBOL: aprs::Symbol = aprs::CAR;
+#[cfg(feature = "aprs")]
+const APRS_FORMAT: aprs::Format = aprs::Format::Compressed;
+
+// With feature ubx a u-blox GPS is set up at startup (see configure_ubx) to send only NAV-PVT, which
+// gives the fix and accuracy estimates in one message, rather than NMEA.
+#[cfg(feature = "ubx")]
+const UBX_MODEL: DynModel = DynModel::Automotive; // or Pedestrian, Airborne1g for a balloon, ...
+#[cfg(feature = "ubx")]
+const UBX_RATE_MS: u16 = 1000; // navigation solution (and NAV-PVT) period
+#[cfg(feature = "ubx")]
+const UBX_POWER_SAVE: bool = false; // cyclic tracking, less current but slower to get a fix
+
+// The GPS module (with feature ubx or pmtk) is switched from GPS_BAUD (as setup() configures the
+// usart) to the rate set with   GPS_BAUD=38400 cargo build --features ubx,...   and the usart
+// follows. At higher rates the GPS output takes less of each second. Without it, or on MCUs where
+// the usart rate cannot be changed (see set_gps_baud), the module stays at GPS_BAUD.
+#[cfg(any(feature = "ubx", feature = "pmtk"))]
+fn gps_baud() -> u32 {
+    let baud = option_env!("GPS_BAUD").map_or(GPS_BAUD, |b| {
+        b.parse().expect("GPS_BAUD should be a baud rate, eg 38400")
+    });
+    // checks the usart rate can be changed on this MCU, without changing it
+    if baud != GPS_BAUD && set_gps_baud(GPS_BAUD, GPS_BAUD) {
+        baud
+    } else {
+        GPS_BAUD
+    }
+}
+
+#[cfg(feature = "ubx")]
+fn configure_ubx<T, R>(tx: &mut T, rx: &mut R, wdog: &mut impl Feed)
+where
+    T: Write<u8>,
+    R: Read<u8>,
+{
+    let mut msg: Vec<u8, MAX_FRAME> = Vec::new();
+    let mut send = |msg: &[u8], what: &str| {
+        match command(tx, rx, msg, &mut || wdog.feed()) {
+            Some(true) => (),
+            Some(false) => logln!("u-blox GPS rejected {}", what),
+            None => logln!("no answer to {}, not a u-blox GPS?", what),
+        };
+    };
+
+    cfg_rate(&mut msg, UBX_RATE_MS);
+    send(&msg, "CFG-RATE");
+    cfg_nav5(&mut msg, UBX_MODEL);
+    send(&msg, "CFG-NAV5");
+    cfg_rxm(&mut msg, UBX_POWER_SAVE);
+    send(&msg, "CFG-RXM");
+    cfg_msg(&mut msg, NAV_PVT, 1);
+    send(&msg, "CFG-MSG");
+
+    // last, as it switches off NMEA output
+    let baud = gps_baud();
+    cfg_prt_uart(&mut msg, baud, PROTO_UBX | PROTO_NMEA, PROTO_UBX);
+    if baud == GPS_BAUD {
+        send(&msg, "CFG-PRT");
+        return;
+    };
+
+    // The module switches as soon as it has the message, so the acknowledgement is at the new rate.
+    if write_all(tx, &msg).is_err() {
+        logln!("CFG-PRT not sent");
+        return;
+    };
+    set_gps_baud(GPS_BAUD, baud);
+    match wait_ack(rx, CFG_PRT, &mut || wdog.feed()) {
+        Some(true) => logln!("GPS at {} bps", baud),
+        _ => {
+            logln!("no answer at {} bps, back to {}", baud, GPS_BAUD);
+            set_gps_baud(baud, GPS_BAUD);
+        }
+    };
+}
+
+// With feature pmtk an MTK based GPS (or Quectel L70, L76, L80, ...) is set up at startup to send only
+// the RMC, GGA and GSA sentences used for the fix (see src/fix.rs), every PMTK_RATE_MS.
+#[cfg(all(feature = "pmtk", feature = "ubx"))]
+compile_error!("features pmtk and ubx cannot be used together");
+#[cfg(feature = "pmtk")]
+const PMTK_RATE_MS: u16 = 1000;
+
+#[cfg(feature = "pmtk")]
+fn configure_pmtk<T, R>(tx: &mut T, rx: &mut R, wdog: &mut impl Feed)
+where
+    T: Write<u8>,
+    R: Read<u8>,
+{
+    let mut cmd: String<MAX_COMMAND> = String::new();
+    let mut feed = || wdog.feed();
+    let log_ack = |ack: Option<Ack>, what: &str| {
+        match ack {
+            Some(Ack::Success) => (),
+            Some(ack) => logln!("GPS answered {:?} to {}", ack, what),
+            None => logln!("no answer to {}, not an MTK GPS?", what),
+        };
+    };
+
+    // PMTK251 is not acknowledged, the module just switches. The next command checks it did.
+    let mut baud = gps_baud();
+    if baud != GPS_BAUD {
+        set_baud(&mut cmd, baud);
+        if write_all(tx, cmd.as_bytes()).is_ok() {
+            set_gps_baud(GPS_BAUD, baud);
+        } else {
+            baud = GPS_BAUD;
+        };
+    };
+
+    set_update_rate(&mut cmd, PMTK_RATE_MS);
+    let mut ack = send(tx, rx, &cmd, &mut feed);
+    if ack.is_none() && baud != GPS_BAUD {
+        logln!("no answer at {} bps, back to {}", baud, GPS_BAUD);
+        set_gps_baud(baud, GPS_BAUD);
+        ack = send(tx, rx, &cmd, &mut feed);
+    };
+    log_ack(ack, "PMTK220");
+    set_output(&mut cmd, &FIX_OUTPUT);
+    log_ack(send(tx, rx, &cmd, &mut feed), "PMTK314");
+}
+
+// set these with
+// LORAWAN_DEV_EUI=... LORAWAN_APP_EUI=... LORAWAN_APP_KEY=... cargo build --features lorawan,...   for OTAA
+// LORAWAN_DEV_ADDR=... LORAWAN_NWK_SKEY=... LORAWAN_APP_SKEY=... cargo build --features lorawan,... for ABP
+// in hex, as shown by the network server console.
+#[cfg(feature = "lorawan")]
+fn lorawan_credentials() -> Credentials {
+    match option_env!("LORAWAN_APP_KEY") {
+        Some(app_key) => Credentials::Otaa {
+            dev_eui: parse_hex(option_env!("LORAWAN_DEV_EUI").unwrap_or(""), true)
+                .expect("LORAWAN_DEV_EUI should be 16 hex digits"),
+            app_eui: parse_hex(option_env!("LORAWAN_APP_EUI").unwrap_or(""), true)
+                .expect("LORAWAN_APP_EUI should be 16 hex digits"),
+            app_key: parse_hex(app_key, false).expect("LORAWAN_APP_KEY should be 32 hex digits"),
+        },
+        None => Credentials::Abp {
+            dev_addr: parse_hex(option_env!("LORAWAN_DEV_ADDR").unwrap_or(""), true)
+                .expect("LORAWAN_DEV_ADDR should be 8 hex digits"),
+            nwk_skey: parse_hex(option_env!("LORAWAN_NWK_SKEY").unwrap_or(""), false)
+                .expect("LORAWAN_NWK_SKEY should be 32 hex digits"),
+            app_skey: parse_hex(option_env!("LORAWAN_APP_SKEY").unwrap_or(""), false)
+                .expect("LORAWAN_APP_SKEY should be 32 hex digits"),
+        },
+    }
+}
 
 #[entry]
 fn main() -> ! {
     // set this with
     // SENDER_ID="whatever" cargo build ...
     // or  cargo:rustc-env=SENDER_ID="whatever"
+    // With feature lorawan the network identifies the device, so id is not used.
+    #[cfg_attr(feature = "lorawan", allow(unused_variables))]
     let id = option_env!("SENDER_ID").expect("").as_bytes();
 
-    //hprintln!("id  {:?} length {:?}", id, id.len()).unwrap();
+    //logln!("id  {:?} length {:?}", id, id.len());
 
-    let (mut lora, _tx_gps, mut rx_gps, _i2c, mut led) = setup(); //  lora (delay is available in lora)
+    let (mut lora, _tx_gps, mut rx_gps, _i2c, mut led, mut wdog) = setup(); //  lora (delay is available in lora)
+
+    #[cfg(feature = "battery")]
+    let mut adc = Ads1x1x::new_ads1015(_i2c, SlaveAddr::default());
+    #[cfg(feature = "battery")]
+    if adc
+        .set_full_scale_range(FullScaleRange::Within4_096V)
+        .is_err()
+    {
+        logln!("no ADS1015 on i2c, reports are sent without the battery voltage");
+    };
     led.off();
 
     // byte buffer   Nov 2020 limit data.len() < 255 in radio_sx127x  .start_transmit
     let mut buffer: Vec<u8, 80> = Vec::new(); // up to 80  u8 elements on stack
     let mut buf2: Vec<u8, 80> = Vec::new(); // up to 80  u8 elements on stack
+    let mut alert: Vec<u8, 80> = Vec::new(); // up to 80  u8 elements on stack
+    let mut packet: Vec<u8, MAX_PACKET> = Vec::new(); // header and payload
+
+    // sequence number in the packet header, so relays and receivers can spot duplicates
+    #[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+    let mut seq: u16 = 0;
 
-    //hprintln!("buffer at {} of {}", buffer.len(), buffer.capacity()).unwrap();  //0 of 80
-    //hprintln!("buf2   at {} of {}",   buf2.len(),   buf2.capacity()).unwrap();  //0 of 80
+    // backoff when another sender is on the channel, seeded with id so senders do not back off in step
+    #[cfg(not(feature = "lorawan"))]
+    let mut lbt = ListenBeforeTalk::new(CONFIG_LBT, id);
+    #[cfg(not(feature = "lorawan"))]
+    let mut reports: u32 = 0;
+
+    let mut fences = GeofenceMonitor::new(GEOFENCES);
+    let mut tracker = FixTracker::new();
+
+    // the GPS usart transmit is only used for commands to the GPS
+    #[cfg(any(feature = "ubx", feature = "pmtk"))]
+    let mut tx_gps = _tx_gps;
+    #[cfg(feature = "ubx")]
+    configure_ubx(&mut tx_gps, &mut rx_gps, &mut wdog);
+    #[cfg(feature = "ubx")]
+    let mut ubx_decoder = Decoder::new();
+    #[cfg(feature = "pmtk")]
+    configure_pmtk(&mut tx_gps, &mut rx_gps, &mut wdog);
+
+    #[cfg(feature = "lorawan")]
+    let mut lorawan = Device::new(LORAWAN_REGION, lorawan_credentials());
+    // for the OTAA DevNonce, stirred with gps lines (which include the time)
+    #[cfg(feature = "lorawan")]
+    let mut rng = XorShift32::new(0);
+
+    //logln!("buffer at {} of {}", buffer.len(), buffer.capacity());  //0 of 80
+    //logln!("buf2   at {} of {}",   buf2.len(),   buf2.capacity());  //0 of 80
     buffer.clear();
     buf2.clear();
 
-    //hprintln!("going into write/read loop ^C to exit ...").unwrap();
+    //logln!("going into write/read loop ^C to exit ...");
 
     let e: u8 = b'x'; // replace char errors with "x"
     let mut good = false; // true while capturing a line
 
     //let mut size: usize;   // buffer size should not be needed
     //size = buffer.len();   //packet size
-    //hprintln!("read buffer {} of {}", size, buffer.capacity()).unwrap();
-    hprintln!("entering transmit loop").unwrap();
+    //logln!("read buffer {} of {}", size, buffer.capacity());
+    logln!("entering transmit loop");
 
     loop {
         let byte = match block!(rx_gps.read()) {
@@ -62,97 +354,250 @@ fn main() -> ! {
             Err(_error) => e,
         };
 
+        // fix state, from an RMC line or (with feature ubx) a NAV-PVT message
+        let mut state = None;
+
         if byte == 36 {
             //  $ is 36. start of a line
             buffer.clear();
             good = true; //start capturing line
         };
 
-        if good {
-            //push byte into buffer and transmit if error/buffer full or end of line. \r is 13, \n is 10
-            if buffer.push(byte).is_err() || byte == 13 {
-                //hprintln!("{:?}", &buffer).unwrap();
+        //push byte into buffer and check the line if error/buffer full or end of line. \r is 13, \n is 10
+        if good && (buffer.push(byte).is_err() || byte == 13) {
+            // progress, a line from the GPS. A loose GPS wire blocks in rx_gps.read() instead,
+            // and the watchdog resets the MCU.
+            wdog.feed();
 
-                buf2.clear();
-                // put id in first
-                for v in id.iter() {
-                    buf2.push(*v).unwrap();
-                }
-                buf2.push(b' ').unwrap();
-
-                // for message $GPRMC == [36, 71, 80, 82, 77, 67]
-                //    transmit GPS N and E in hundredths of degrees
-                // otherwise transmits the GPS message line
-
-                // if message id is $GPRMC but if buffer is too short then not valid data (V) orsomething was lost, so skip
-                //B411-2 $GPRMC,030052.00,V,,,,,,,300321,,,N*7A
-                //$GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66
-                if (buffer.len() > 45) && (&buffer[0..6] == [36, 71, 80, 82, 77, 67]) {
-                    // [19..31] is north/south.
-                    for v in buffer[19..31].iter() {
-                        buf2.push(*v).unwrap();
-                    }
-                    buf2.push(b' ').unwrap();
-                    // [32..45] is east/west
-                    for v in buffer[32..45].iter() {
-                        buf2.push(*v).unwrap();
-                    }
+            //logln!("{:?}", &buffer);
 
-                    //hprintln!("{:?}", &buf2).unwrap();
-                    hprint!(".").unwrap(); // print "."  on transmit of $GPRMC message (but not others)
-                    led.on(); // double blink on transmit of decoded message, one here and one below.
-                    let _ = lora.delay_ms(2u32);
-                    led.off();
-                    let _ = lora.delay_ms(300u32);
-                } else {
-                    for v in buffer[..].iter() {
-                        buf2.push(*v).unwrap();
-                    }
+            #[cfg(feature = "lorawan")]
+            rng.mix(&buffer);
+
+            // Only RMC lines (once per GPS epoch) are reported, GGA and GSA lines update the fix
+            // quality and other lines are ignored.
+            //B411-2 $GPRMC,030052.00,V,,,,,,,300321,,,N*7A
+            //$GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66
+            state = tracker.update(&buffer);
+            buffer.clear();
+            good = false;
+        };
+
+        #[cfg(feature = "ubx")]
+        if let Some(frame) = ubx_decoder.push(byte) {
+            wdog.feed(); // progress, a message from the GPS
+
+            #[cfg(feature = "lorawan")]
+            rng.mix(frame.payload);
+
+            if let Some(pvt) = NavPvt::parse(&frame) {
+                state = Some(tracker.update_pvt(&pvt));
+            };
+        };
+
+        let (fix, degraded) = match state {
+            None => continue,
+            Some(FixState::Good(p, _kind)) => (p, None),
+            Some(FixState::Degraded(p, d)) => (p, Some(d)),
+            Some(FixState::Stale { .. }) => {
+                log!("s"); // print "s" while the fix is briefly lost, nothing is sent
+                continue;
+            }
+            Some(FixState::NoFix { since }) => {
+                log!("n"); // print "n" for no fix
+
+                // a status packet replaces the raw NMEA lines sent when there was no fix
+                #[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+                if no_fix_message(&mut buf2, since) {
+                    Header::new(Kind::Status, id, seq).encode(&buf2, &mut packet);
+                    seq = seq.wrapping_add(1);
+                    transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &packet);
                 };
+                #[cfg(any(feature = "lorawan", feature = "aprs"))]
+                let _ = since; // status is not sent, there is no position to report
 
-                match lora.start_transmit(&buf2) {
-                    Ok(_b) => {
-                        led.on();
-                        let _ = lora.delay_ms(2u32);
-                        led.off();
-                    }
-                    Err(_err) => {
-                        hprintln!("Error returned from lora.start_transmit().").unwrap();
-                        //panic!("should reset in release mode.");
-                    }
+                if delay_fed(&mut lora, &mut wdog, REPORT_MS).is_err() {
+                    logln!("Error returned from lora.try_delay_ms().");
+                    last_resort(); // resets the MCU, panic! would only halt in release builds
                 };
+                continue;
+            }
+        };
+
+        // transmit GPS N and E and the time of the fix (UTC), "4523.74241,N 07540.61255,W 03:17:37Z"
+        // and for a degraded fix a tag for the reason, eg "4523.74241,N 07540.61255,W 03:17:37Z 2D"
+        // id goes in the packet header rather than the payload
+        let coords = format_position(fix);
+        let time = tracker.time();
+        buf2.clear();
+        buf2.extend_from_slice(coords.as_bytes()).unwrap();
+        if let Some(t) = time {
+            buf2.push(b' ').unwrap();
+            buf2.extend_from_slice(format_time(t).as_bytes()).unwrap();
+        };
+        // the tag and battery voltage that follow are also the APRS comment
+        #[cfg(feature = "aprs")]
+        let tail = buf2.len();
+        if let Some(d) = degraded {
+            buf2.push(b' ').unwrap();
+            buf2.extend_from_slice(d.tag()).unwrap();
+            log!("d"); // print "d" for a degraded fix
+        };
+        #[cfg(feature = "battery")]
+        if let Ok(v) = block!(adc.read(&mut AdcChannel::SingleA0)) {
+            let mv = v.max(0) as u16 * BATTERY_MV_PER_COUNT;
+            buf2.push(b' ').unwrap();
+            buf2.extend_from_slice(aprs::battery_comment(mv).as_bytes())
+                .unwrap();
+        };
 
-                // Note hprintln! requires semihosting. If hprintln! (thus also match section below) are
-                // removed then this example works on battery power with no computer attached.
-                // (tested only on blackpill with stm32f411 )
+        // Check the fix against the geofences and alert on any boundary crossing.
+        // Degraded fixes are not checked, as position errors could give false alerts.
+        if degraded.is_none() {
+            for c in fences.update(fix).iter() {
+                let (word, i): (&[u8], usize) = match *c {
+                    Crossing::Entered(i) => (b"ENTER", i),
+                    Crossing::Exited(i) => (b"EXIT", i),
+                };
+                // "EXIT 01 4523.74241,N 07540.61255,W 03:17:37Z"
+                alert.clear();
+                alert.extend_from_slice(word).unwrap();
+                alert.push(b' ').unwrap();
+                alert.push(b'0' + (i / 10) as u8).unwrap();
+                alert.push(b'0' + (i % 10) as u8).unwrap();
+                alert.push(b' ').unwrap();
+                alert.extend_from_slice(coords.as_bytes()).unwrap();
+                if let Some(t) = time {
+                    alert.push(b' ').unwrap();
+                    alert.extend_from_slice(format_time(t).as_bytes()).unwrap();
+                };
 
-                // The first transmission often return false and prints "x", but works after that.
-                // If this continually returns "TX not complete" then the radio should probably be reset,
-                //  but should avoid panic_reset after first transmission.
+                log!("!"); // print "!" on alert
 
-                match lora.check_transmit() {
-                    Ok(b) => {
-                        if !b {
-                            hprint!("x").unwrap();
-                            // if multible times then panic!("should reset in release mode.");
-                        }
-                    }
-                    Err(_err) => {
-                        hprintln!("Error returned from lora.check_transmit().").unwrap();
-                        //panic!("should reset in release mode.");
+                // a confirmed uplink is retried until acknowledged, up to ALERT_REPEATS times
+                #[cfg(feature = "lorawan")]
+                for _ in 0..ALERT_REPEATS {
+                    match lorawan.send(
+                        &mut Fed::new(&mut lora, &mut wdog),
+                        ALERT_PORT,
+                        &alert,
+                        true,
+                    ) {
+                        Ok(Some(d)) if d.ack => break,
+                        _ => (),
+                    };
+                }
+
+                #[cfg(feature = "aprs")]
+                if aprs::encode(
+                    &mut packet,
+                    id,
+                    fix,
+                    APRS_SYMBOL,
+                    APRS_FORMAT,
+                    time,
+                    &alert[..word.len() + 3],
+                ) {
+                    for _ in 0..ALERT_REPEATS {
+                        transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &packet);
+                        let _ = delay_fed(&mut lora, &mut wdog, 500u32);
                     }
                 };
 
-                buffer.clear();
-                good = false;
-                match lora.delay_ms(5000u32) {
-                    Ok(b) => b, // b is ()
-                    Err(_err) => {
-                        hprintln!("Error returned from lora.try_delay_ms().").unwrap();
-                        panic!("should reset in release mode.");
+                // repeats have the same sequence number, so relays forward only one
+                #[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+                {
+                    Header::new(Kind::Alert, id, seq).encode(&alert, &mut packet);
+                    seq = seq.wrapping_add(1);
+
+                    for _ in 0..ALERT_REPEATS {
+                        transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &packet);
+                        let _ = delay_fed(&mut lora, &mut wdog, 500u32);
                     }
+                }
+            }
+        };
+
+        //logln!("{:?}", &buf2);
+        log!("."); // print "."  on transmit of a position
+        led.on(); // double blink on transmit of decoded message, one here and one below.
+        let _ = lora.delay_ms(2u32);
+        led.off();
+        let _ = lora.delay_ms(300u32);
+
+        // Note logln! uses semihosting in debug builds (see src/log.rs), which needs a computer
+        // attached. Release builds, or builds with feature log-defmt or log-uart, work on
+        // battery power with no computer attached. (tested only on blackpill with stm32f411 )
+
+        #[cfg(feature = "lorawan")]
+        {
+            if !lorawan.is_joined() {
+                match lorawan.join(&mut Fed::new(&mut lora, &mut wdog), rng.next_u32() as u16) {
+                    Ok(true) => logln!("joined LoRaWAN network"),
+                    _ => log!("j"), // join failed, try again next fix
                 };
             };
+
+            // altitude is not in RMC, so sent as 0
+            packet.clear();
+            lpp_gps(&mut packet, 1, fix, 0.0);
+            match lorawan.send(
+                &mut Fed::new(&mut lora, &mut wdog),
+                GPS_PORT,
+                &packet,
+                false,
+            ) {
+                Ok(_) => {
+                    led.on();
+                    let _ = lora.delay_ms(2u32);
+                    led.off();
+                }
+                Err(_err) => logln!("Error returned from lorawan.send()."),
+            };
+        }
+
+        // the comment flags a degraded fix and gives the battery voltage
+        #[cfg(feature = "aprs")]
+        if aprs::encode(
+            &mut packet,
+            id,
+            fix,
+            APRS_SYMBOL,
+            APRS_FORMAT,
+            time,
+            buf2[tail..].strip_prefix(b" ").unwrap_or(&buf2[tail..]),
+        ) {
+            transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &packet);
+        };
+
+        // cannot overflow, buf2 is much smaller than MAX_PACKET
+        #[cfg(not(any(feature = "lorawan", feature = "aprs")))]
+        {
+            Header::new(Kind::Data, id, seq).encode(&buf2, &mut packet);
+            seq = seq.wrapping_add(1);
+
+            transmit(&mut lora, &mut led, &mut lbt, &mut wdog, &packet);
+        }
+
+        #[cfg(not(feature = "lorawan"))]
+        {
+            reports = reports.wrapping_add(1);
+            if reports % STATS_REPORTS == 0 {
+                logln!("lbt {:?}", lbt.counters());
+            };
+        }
+
+        let interval = if fences.is_outside_all() {
+            FAST_REPORT_MS
+        } else {
+            REPORT_MS
+        };
+        match delay_fed(&mut lora, &mut wdog, interval) {
+            Ok(b) => b, // b is ()
+            Err(_err) => {
+                logln!("Error returned from lora.try_delay_ms().");
+                last_resort(); // resets the MCU, panic! would only halt in release builds
+            }
         };
     }
 }
@@ -6,20 +6,28 @@
 #![no_std]
 #![no_main]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 //use panic_reset;
 
-use cortex_m_rt::entry;
-use cortex_m_semihosting::*;
 use embedded_hal::delay::blocking::DelayMs;
+use lora_gps::arch::entry;
 
 use radio::Transmit;
 
-use lora_gps::lora_spi_gps_usart::{setup, LED};
+use lora_gps::log::drain;
+use lora_gps::logln;
+use lora_gps::lora_spi_gps_usart::{delay_fed, setup, Feed, LED};
+use lora_gps::supervisor::{last_resort, Supervisor};
+
+#[cfg(feature = "fsk")]
+use lora_gps::lora_spi_gps_usart::set_fsk;
+#[cfg(feature = "fsk")]
+use lora_gps::supervisor::Recovery;
 
 #[entry]
 fn main() -> ! {
@@ -28,24 +36,24 @@ fn main() -> ! {
     // or  cargo:rustc-env=SENDER_ID="whatever"
     let id = option_env!("SENDER_ID").expect("Hello, LoRa!").as_bytes();
 
-    let (mut lora, _rx, _tx, _i2c, mut led) = setup(); //delay is available in lora
+    let (mut lora, mut tx, _rx, _i2c, mut led, mut wdog) = setup(); //delay is available in lora
     led.off();
 
     // print out configuration (for debugging)
 
     //    let v = lora.lora_get_config();
-    //    hprintln!("configuration {}", v).unwrap();
+    //    logln!("configuration {}", v);
 
-    //    hprintln!("chammel          {}", lora.get_chammel()).unwrap();
+    //    logln!("chammel          {}", lora.get_chammel());
 
-    //hprintln!("mode                  {}", lora.get_mode()).unwrap();
-    //hprintln!("mode                  {}", lora.read_register(Register::RegOpMode.addr())).unwrap();
-    //hprintln!("bandwidth          {:?}", lora.get_signal_bandwidth()).unwrap();
-    //hprintln!("coding_rate          {:?}",  lora.get_coding_rate_4()).unwrap();
-    //hprintln!("spreading_factor {:?}",  lora.get_spreading_factor()).unwrap();
-    //hprintln!("spreading_factor {:?}",
-    //hprintln!("invert_iq          {:?}",  lora.get_invert_iq()).unwrap();
-    //hprintln!("tx_power          {:?}",  lora.get_tx_power()).unwrap();
+    //logln!("mode                  {}", lora.get_mode());
+    //logln!("mode                  {}", lora.read_register(Register::RegOpMode.addr()));
+    //logln!("bandwidth          {:?}", lora.get_signal_bandwidth());
+    //logln!("coding_rate          {:?}",  lora.get_coding_rate_4());
+    //logln!("spreading_factor {:?}",  lora.get_spreading_factor());
+    //logln!("spreading_factor {:?}",
+    //logln!("invert_iq          {:?}",  lora.get_invert_iq());
+    //logln!("tx_power          {:?}",  lora.get_tx_power());
 
     // transmit something
 
@@ -67,38 +75,61 @@ fn main() -> ! {
     //        buffer[i + 1 + id.len()] = c as u8;
     //        }
 
+    // repeated errors re-initialise the radio, see src/supervisor.rs
+    let mut supervisor = Supervisor::new();
+
+    // with feature fsk, send in FSK (see src/fsk.rs) to receive_spi built with fsk
+    #[cfg(feature = "fsk")]
+    set_fsk(&mut lora, &mut supervisor);
+
     loop {
-        match lora.start_transmit(message) {
+        drain(&mut tx); // log output, with feature log-uart
+
+        let started = lora.start_transmit(message);
+        match supervisor.check(&mut lora, started) {
             Ok(_b) => {
                 led.on();
                 let _ = lora.delay_ms(2u32); // very short
                 led.off();
             }
-            Err(_err) => {
-                hprintln!("Error returned from lora.start_transmit().").unwrap();
-                //panic!("should reset in release mode.");
+            // Reinit returns the radio to LoRa, so with feature fsk it is switched again
+            #[cfg(feature = "fsk")]
+            Err(Recovery::Reinitialised) => {
+                logln!("Error returned from lora.start_transmit().");
+                set_fsk(&mut lora, &mut supervisor);
+            }
+            Err(_recovery) => {
+                logln!("Error returned from lora.start_transmit().");
             }
         };
 
-        match lora.check_transmit() {
+        let checked = lora.check_transmit();
+        match supervisor.check(&mut lora, checked) {
             Ok(b) => {
                 if b {
-                    hprintln!("TX complete").unwrap()
+                    logln!("TX complete");
+                    wdog.feed(); // progress, a message sent
                 } else {
-                    hprintln!("TX not complete").unwrap()
+                    logln!("TX not complete")
                 }
             }
 
-            Err(_err) => {
-                hprintln!("Error in lora.check_transmit(). Should return True or False.").unwrap()
+            #[cfg(feature = "fsk")]
+            Err(Recovery::Reinitialised) => {
+                logln!("Error in lora.check_transmit(). Should return True or False.");
+                set_fsk(&mut lora, &mut supervisor);
+            }
+            Err(_recovery) => {
+                logln!("Error in lora.check_transmit(). Should return True or False.")
             }
         };
 
-        match lora.delay_ms(5000u32) {
+        // longer than the watchdog allows without feeding
+        match delay_fed(&mut lora, &mut wdog, 5000u32) {
             Ok(b) => b, // b is ()
             Err(_err) => {
-                hprintln!("Error returned from lora.try_delay_ms().").unwrap();
-                panic!("should reset in release mode.");
+                logln!("Error returned from lora.try_delay_ms().");
+                last_resort(); // resets the MCU, panic! would only halt in release builds
             }
         };
     }
@@ -0,0 +1,410 @@
+//! Board definitions: the pins (and for some hals the peripherals) used for the radio, the GPS usart,
+//! the i2c bus and the on board led, selected with a board-* feature in Cargo.toml.
+//!
+//!  Each board is for one hal (MCU family), and the board feature selects its MCU feature, eg
+//!    cargo build --target thumbv7em-none-eabihf --features board-nucleo-f411 --bin send_gps
+//!  Without a board feature setup() uses the first board of its family, so  --features $HAL,$MCU  wires
+//!  as before. setup() gets each pin with  board_pin!(role, port, port, port),  which expands to the pin
+//!  already put in the mode the hal needs, and each peripheral with  board_periph!(role, p)  (for the
+//!  stm32f1xx hal also its constructor, eg  board_periph!(spi_new)). The other families keep the fixed
+//!  wiring in their setup().
+//!  The radio busy pin is the SX127x DIO0 (SX126x BUSY) and ready is DIO1.
+//!  A new board needs an entry here, a feature in Cargo.toml, and a line in BOARDS in build.rs.
+
+// stm32f1xx boards. Ports (gpioa, gpiob, gpioc).
+#[cfg(feature = "stm32f1xx")]
+#[macro_use]
+mod f1 {
+    // blue pill stm32f103 (the default, board-bluepill) with an RFM95 breakout
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $c:ident) => {
+            $a.pa5.into_alternate_push_pull(&mut $a.crl)
+        };
+        (miso, $a:ident, $b:ident, $c:ident) => {
+            $a.pa6.into_floating_input(&mut $a.crl)
+        };
+        (mosi, $a:ident, $b:ident, $c:ident) => {
+            $a.pa7.into_alternate_push_pull(&mut $a.crl)
+        };
+        (cs, $a:ident, $b:ident, $c:ident) => {
+            $a.pa1.into_push_pull_output(&mut $a.crl)
+        };
+        (busy, $a:ident, $b:ident, $c:ident) => {
+            $b.pb8.into_floating_input(&mut $b.crh)
+        };
+        (ready, $a:ident, $b:ident, $c:ident) => {
+            $b.pb9.into_floating_input(&mut $b.crh)
+        };
+        (reset, $a:ident, $b:ident, $c:ident) => {
+            $a.pa0.into_push_pull_output(&mut $a.crl)
+        };
+        (gps_tx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa2.into_alternate_push_pull(&mut $a.crl)
+        };
+        (gps_rx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa3
+        };
+        (scl, $a:ident, $b:ident, $c:ident) => {
+            $b.pb10.into_alternate_open_drain(&mut $b.crh)
+        };
+        (sda, $a:ident, $b:ident, $c:ident) => {
+            $b.pb11.into_alternate_open_drain(&mut $b.crh)
+        };
+        (led, $a:ident, $b:ident, $c:ident) => {
+            $c.pc13.into_push_pull_output(&mut $c.crh)
+        };
+    }
+
+    // The stm32f1xx hal has a constructor for each instance, so those are here too. The SPI1 and
+    // I2C1 constructors also take &mut afio.mapr, a board on them needs setup() changed to match.
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI1
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART2
+        };
+        (i2c, $p:ident) => {
+            $p.I2C2
+        };
+        (spi_new) => {
+            Spi::spi1
+        };
+        (gps_usart_new) => {
+            Serial::usart2
+        };
+        (i2c_new) => {
+            BlockingI2c::i2c2
+        };
+    }
+
+    pub type GpsUsart = stm32f1xx_hal::pac::USART2;
+    pub type I2cBus = stm32f1xx_hal::pac::I2C2;
+    pub const LED_ACTIVE_LOW: bool = true;
+}
+
+#[cfg(feature = "stm32f1xx")]
+pub use f1::*;
+
+// stm32f3xx boards. Ports (gpioa, gpiob, gpioe).
+#[cfg(feature = "stm32f3xx")]
+#[macro_use]
+mod f3 {
+    // Discovery kit stm32f303 (the default, board-discovery-stm32f303), led LD6 (green)
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $e:ident) => {
+            $a.pa5
+                .into_af5_push_pull(&mut $a.moder, &mut $a.otyper, &mut $a.afrl)
+        };
+        (miso, $a:ident, $b:ident, $e:ident) => {
+            $a.pa6
+                .into_af5_push_pull(&mut $a.moder, &mut $a.otyper, &mut $a.afrl)
+        };
+        (mosi, $a:ident, $b:ident, $e:ident) => {
+            $a.pa7
+                .into_af5_push_pull(&mut $a.moder, &mut $a.otyper, &mut $a.afrl)
+        };
+        (cs, $a:ident, $b:ident, $e:ident) => {
+            $a.pa1.into_push_pull_output(&mut $a.moder, &mut $a.otyper)
+        };
+        (busy, $a:ident, $b:ident, $e:ident) => {
+            $b.pb8.into_floating_input(&mut $b.moder, &mut $b.pupdr)
+        };
+        (ready, $a:ident, $b:ident, $e:ident) => {
+            $b.pb9.into_floating_input(&mut $b.moder, &mut $b.pupdr)
+        };
+        (reset, $a:ident, $b:ident, $e:ident) => {
+            $a.pa0.into_push_pull_output(&mut $a.moder, &mut $a.otyper)
+        };
+        (gps_tx, $a:ident, $b:ident, $e:ident) => {
+            $a.pa2
+                .into_af7_push_pull(&mut $a.moder, &mut $a.otyper, &mut $a.afrl)
+        };
+        (gps_rx, $a:ident, $b:ident, $e:ident) => {
+            $a.pa3
+                .into_af7_push_pull(&mut $a.moder, &mut $a.otyper, &mut $a.afrl)
+        };
+        (scl, $a:ident, $b:ident, $e:ident) => {
+            $a.pa9
+                .into_af4_open_drain(&mut $a.moder, &mut $a.otyper, &mut $a.afrh)
+        };
+        (sda, $a:ident, $b:ident, $e:ident) => {
+            $a.pa10
+                .into_af4_open_drain(&mut $a.moder, &mut $a.otyper, &mut $a.afrh)
+        };
+        (led, $a:ident, $b:ident, $e:ident) => {
+            $e.pe15.into_push_pull_output(&mut $e.moder, &mut $e.otyper)
+        };
+    }
+
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI1
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART2
+        };
+        (i2c, $p:ident) => {
+            $p.I2C2
+        };
+    }
+
+    pub type GpsUsart = stm32f3xx_hal::pac::USART2;
+    pub type I2cBus = stm32f3xx_hal::pac::I2C2;
+    pub const LED_ACTIVE_LOW: bool = false;
+}
+
+#[cfg(feature = "stm32f3xx")]
+pub use f3::*;
+
+// stm32f4xx boards. Ports (gpioa, gpiob, gpioc).
+#[cfg(all(
+    feature = "stm32f4xx",
+    not(any(feature = "board-nucleo-f401", feature = "board-nucleo-f411"))
+))]
+#[macro_use]
+mod f4 {
+    // blackpill stm32f401 or stm32f411 (the default), with an RFM95 breakout
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $c:ident) => {
+            $a.pa5.into_alternate()
+        };
+        (miso, $a:ident, $b:ident, $c:ident) => {
+            $a.pa6.into_alternate()
+        };
+        (mosi, $a:ident, $b:ident, $c:ident) => {
+            $a.pa7.into_alternate()
+        };
+        (cs, $a:ident, $b:ident, $c:ident) => {
+            $a.pa1.into_push_pull_output()
+        };
+        (busy, $a:ident, $b:ident, $c:ident) => {
+            $b.pb8.into_floating_input()
+        };
+        (ready, $a:ident, $b:ident, $c:ident) => {
+            $b.pb9.into_floating_input()
+        };
+        (reset, $a:ident, $b:ident, $c:ident) => {
+            $a.pa0.into_push_pull_output()
+        };
+        (gps_tx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa2.into_alternate()
+        };
+        (gps_rx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa3.into_alternate()
+        };
+        (scl, $a:ident, $b:ident, $c:ident) => {
+            $b.pb10.into_alternate().set_open_drain()
+        };
+        (sda, $a:ident, $b:ident, $c:ident) => {
+            $b.pb3.into_alternate().set_open_drain()
+        };
+        (led, $a:ident, $b:ident, $c:ident) => {
+            $c.pc13.into_push_pull_output()
+        };
+    }
+
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI1
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART2
+        };
+        (i2c, $p:ident) => {
+            $p.I2C2
+        };
+    }
+
+    pub type GpsUsart = stm32f4xx_hal::pac::USART2;
+    pub type I2cBus = stm32f4xx_hal::pac::I2C2;
+    pub const LED_ACTIVE_LOW: bool = true;
+}
+
+#[cfg(any(feature = "board-nucleo-f401", feature = "board-nucleo-f411"))]
+#[macro_use]
+mod f4 {
+    // Nucleo-64 stm32f401 or stm32f411. The led LD2 (PA5) is the usual SPI1 sck and USART2 (PA2, PA3)
+    // is the ST-LINK virtual com port, so the radio is on SPI2 and the GPS on USART1 (D8, D2).
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $c:ident) => {
+            $b.pb13.into_alternate()
+        };
+        (miso, $a:ident, $b:ident, $c:ident) => {
+            $b.pb14.into_alternate()
+        };
+        (mosi, $a:ident, $b:ident, $c:ident) => {
+            $b.pb15.into_alternate()
+        };
+        (cs, $a:ident, $b:ident, $c:ident) => {
+            $b.pb12.into_push_pull_output()
+        };
+        (busy, $a:ident, $b:ident, $c:ident) => {
+            $b.pb8.into_floating_input()
+        };
+        (ready, $a:ident, $b:ident, $c:ident) => {
+            $b.pb9.into_floating_input()
+        };
+        (reset, $a:ident, $b:ident, $c:ident) => {
+            $a.pa0.into_push_pull_output()
+        };
+        (gps_tx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa9.into_alternate()
+        };
+        (gps_rx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa10.into_alternate()
+        };
+        (scl, $a:ident, $b:ident, $c:ident) => {
+            $b.pb10.into_alternate().set_open_drain()
+        };
+        (sda, $a:ident, $b:ident, $c:ident) => {
+            $b.pb3.into_alternate().set_open_drain()
+        };
+        (led, $a:ident, $b:ident, $c:ident) => {
+            $a.pa5.into_push_pull_output()
+        };
+    }
+
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI2
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART1
+        };
+        (i2c, $p:ident) => {
+            $p.I2C2
+        };
+    }
+
+    pub type GpsUsart = stm32f4xx_hal::pac::USART1;
+    pub type I2cBus = stm32f4xx_hal::pac::I2C2;
+    pub const LED_ACTIVE_LOW: bool = false;
+}
+
+#[cfg(feature = "stm32f4xx")]
+pub use f4::*;
+
+// stm32l1xx boards. Ports (gpioa, gpiob, gpioc).
+#[cfg(all(feature = "stm32l1xx", not(feature = "board-discovery-stm32l100")))]
+#[macro_use]
+mod l1 {
+    // Heltec lora_node STM32L151CCU6 (the default), radio on the board
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $c:ident) => {
+            $a.pa5
+        };
+        (miso, $a:ident, $b:ident, $c:ident) => {
+            $a.pa6
+        };
+        (mosi, $a:ident, $b:ident, $c:ident) => {
+            $a.pa7
+        };
+        (cs, $a:ident, $b:ident, $c:ident) => {
+            $a.pa4.into_push_pull_output()
+        };
+        (busy, $a:ident, $b:ident, $c:ident) => {
+            $b.pb11.into_floating_input()
+        };
+        (ready, $a:ident, $b:ident, $c:ident) => {
+            $b.pb10.into_floating_input()
+        };
+        (reset, $a:ident, $b:ident, $c:ident) => {
+            $a.pa3.into_push_pull_output()
+        };
+        (gps_tx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa9
+        };
+        (gps_rx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa10
+        };
+        (scl, $a:ident, $b:ident, $c:ident) => {
+            $b.pb8.into_open_drain_output()
+        };
+        (sda, $a:ident, $b:ident, $c:ident) => {
+            $b.pb9.into_open_drain_output()
+        };
+        (led, $a:ident, $b:ident, $c:ident) => {
+            $b.pb6.into_push_pull_output()
+        };
+    }
+
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI1
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART1
+        };
+        (i2c, $p:ident) => {
+            $p.I2C1
+        };
+    }
+
+    pub type GpsUsart = stm32l1xx_hal::stm32::USART1;
+    pub type I2cBus = stm32l1xx_hal::stm32::I2C1;
+    pub const LED_ACTIVE_LOW: bool = false;
+}
+
+#[cfg(feature = "board-discovery-stm32l100")]
+#[macro_use]
+mod l1 {
+    // Discovery kit stm32l100 with an RFM95 breakout, led LD3 (green). PA0 is the user button.
+    macro_rules! board_pin {
+        (sck, $a:ident, $b:ident, $c:ident) => {
+            $a.pa5
+        };
+        (miso, $a:ident, $b:ident, $c:ident) => {
+            $a.pa6
+        };
+        (mosi, $a:ident, $b:ident, $c:ident) => {
+            $a.pa7
+        };
+        (cs, $a:ident, $b:ident, $c:ident) => {
+            $a.pa4.into_push_pull_output()
+        };
+        (busy, $a:ident, $b:ident, $c:ident) => {
+            $b.pb11.into_floating_input()
+        };
+        (ready, $a:ident, $b:ident, $c:ident) => {
+            $b.pb10.into_floating_input()
+        };
+        (reset, $a:ident, $b:ident, $c:ident) => {
+            $a.pa1.into_push_pull_output()
+        };
+        (gps_tx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa2
+        };
+        (gps_rx, $a:ident, $b:ident, $c:ident) => {
+            $a.pa3
+        };
+        (scl, $a:ident, $b:ident, $c:ident) => {
+            $b.pb8.into_open_drain_output()
+        };
+        (sda, $a:ident, $b:ident, $c:ident) => {
+            $b.pb9.into_open_drain_output()
+        };
+        (led, $a:ident, $b:ident, $c:ident) => {
+            $c.pc9.into_push_pull_output()
+        };
+    }
+
+    macro_rules! board_periph {
+        (spi, $p:ident) => {
+            $p.SPI1
+        };
+        (gps_usart, $p:ident) => {
+            $p.USART2
+        };
+        (i2c, $p:ident) => {
+            $p.I2C1
+        };
+    }
+
+    pub type GpsUsart = stm32l1xx_hal::stm32::USART2;
+    pub type I2cBus = stm32l1xx_hal::stm32::I2C1;
+    pub const LED_ACTIVE_LOW: bool = false;
+}
+
+#[cfg(feature = "stm32l1xx")]
+pub use l1::*;
@@ -0,0 +1,347 @@
+//! Emulated board for QEMU (lm3s6965evb), so the binaries can be tested without hardware.
+//!
+//!  setup() with feature lm3s6965 returns a simulated radio and a GPS usart that plays NMEA_SCRIPT,
+//!  so send_gps and receive_spi run unchanged. The simulated radio checks each transmitted packet
+//!  against EXPECTED_TX, delivers RX_SCRIPT to a receiver, and fails if the radio is used out of
+//!  order (eg check_receive without start_receive, or not restarting receive after a re-initialisation).
+//!  The scripts are in tests/emulated/scripts.rs. receive_spi only reports what it received in its
+//!  log, which CI compares with tests/emulated/receive_spi.log.
+//!  When a script is finished, or on the first mismatch, QEMU is stopped with a semihosting exit code
+//!  (0 for success), eg
+//!    SENDER_ID=qemu cargo run --target thumbv7m-none-eabi --features lm3s6965 --bin send_gps
+//!  with the QEMU runner given in README.md.
+//!  Delays return at once, so scenarios run in well under a second.
+
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use cortex_m_semihosting::debug;
+use embedded_hal::delay::blocking::DelayMs;
+use heapless::Vec;
+use radio::{Receive, Transmit};
+use radio_sx127x::device::{Channel, PacketInfo};
+use radio_sx127x::Error as sx127xError;
+
+use crate::fsk::{ModemConfig, SetModem};
+use crate::hopping::{Fhss, HopConfig};
+use crate::lbt::{ChannelActivity, LbtConfig};
+use crate::logln;
+use crate::packet::{decode, Header, Kind, MAX_PACKET};
+use crate::supervisor::Reinit;
+
+// what the simulated radio reports to each check_receive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RxEvent {
+    Nothing,
+    Packet(Kind, &'static [u8]), // from source "sim", with sequence numbers counting from 0
+    Text(&'static [u8]),         // plain text, as from send_spi
+    Crc,                         // transient error, only counted by the supervisor
+    Bus,                         // SPI error, REINIT_AFTER in a row re-initialise the radio
+}
+
+// NMEA_SCRIPT, EXPECTED_TX and RX_SCRIPT, the test data
+include!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/emulated/scripts.rs"
+));
+
+// packets in EXPECTED_TX sent so far, checked when NMEA_SCRIPT is finished
+static SENT: AtomicUsize = AtomicUsize::new(0);
+
+// Stop QEMU, with exit code 0 if ok.
+pub fn finish(ok: bool) -> ! {
+    let status = if ok {
+        debug::EXIT_SUCCESS
+    } else {
+        debug::EXIT_FAILURE
+    };
+    loop {
+        debug::exit(status);
+    }
+}
+
+fn fail(why: &str) -> ! {
+    logln!("emulated board: FAILED, {}", why);
+    finish(false)
+}
+
+// usart errors, also used for the radio SPI errors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle, // standby, after setup() or a re-initialisation
+    Transmitting,
+    Receiving,
+}
+
+pub struct SimRadio {
+    state: State,
+    rx_next: usize,
+    rx_seq: u16,
+    pending: Option<Vec<u8, MAX_PACKET>>,
+    received: usize, // packets read with get_received
+}
+
+impl Default for SimRadio {
+    fn default() -> Self {
+        SimRadio {
+            state: State::Idle,
+            rx_next: 0,
+            rx_seq: 0,
+            pending: None,
+            received: 0,
+        }
+    }
+}
+
+impl SimRadio {
+    pub fn new() -> Self {
+        SimRadio::default()
+    }
+
+    // RX_SCRIPT is finished. Receive is continuous, as on the real radios, so a receiver must have
+    // read every packet in it, not only the first.
+    fn finish_receive(&self) -> ! {
+        let packets = RX_SCRIPT
+            .iter()
+            .filter(|e| matches!(e, RxEvent::Packet(..) | RxEvent::Text(_)))
+            .count();
+        if self.received < packets || packets < 2 {
+            logln!("{} of {} packets received", self.received, packets);
+            fail("receive stopped after a packet");
+        };
+        logln!(
+            "emulated board: ok, {} radio events, {} packets received",
+            RX_SCRIPT.len(),
+            self.received
+        );
+        finish(true)
+    }
+}
+
+type SimRadioError = sx127xError<SimError, Infallible, Infallible>;
+
+impl DelayMs<u32> for SimRadio {
+    type Error = Infallible;
+
+    fn delay_ms(&mut self, _ms: u32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Transmit for SimRadio {
+    type Error = SimRadioError;
+
+    fn start_transmit(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let n = SENT.load(Ordering::Relaxed);
+        let (kind, expected) = match EXPECTED_TX.get(n) {
+            Some(e) => *e,
+            None => fail("more packets transmitted than expected"),
+        };
+        match decode(data) {
+            Some((h, payload)) if h.kind == kind && payload == expected => (),
+            _ => {
+                logln!("packet {}, expected {:?} {:?}", n, kind, expected);
+                logln!("transmitted {:?}", data);
+                fail("unexpected packet")
+            }
+        };
+        SENT.store(n + 1, Ordering::Relaxed);
+        self.state = State::Transmitting;
+        Ok(())
+    }
+
+    fn check_transmit(&mut self) -> Result<bool, Self::Error> {
+        let done = self.state == State::Transmitting;
+        if done {
+            self.state = State::Idle;
+        };
+        Ok(done)
+    }
+}
+
+impl Receive for SimRadio {
+    type Info = PacketInfo;
+    type Error = SimRadioError;
+
+    fn start_receive(&mut self) -> Result<(), Self::Error> {
+        self.state = State::Receiving;
+        Ok(())
+    }
+
+    fn check_receive(&mut self, _restart: bool) -> Result<bool, Self::Error> {
+        if self.state != State::Receiving {
+            fail("check_receive without start_receive");
+        };
+        if self.pending.is_some() {
+            fail("received packet not read with get_received");
+        };
+        let event = match RX_SCRIPT.get(self.rx_next) {
+            Some(e) => *e,
+            None => self.finish_receive(),
+        };
+        self.rx_next += 1;
+
+        let mut packet = Vec::new();
+        match event {
+            RxEvent::Nothing => return Ok(false),
+            RxEvent::Crc => return Err(sx127xError::Crc),
+            RxEvent::Bus => return Err(sx127xError::Comms(SimError)),
+            RxEvent::Packet(kind, payload) => {
+                Header::new(kind, b"sim", self.rx_seq).encode(payload, &mut packet);
+                self.rx_seq = self.rx_seq.wrapping_add(1);
+            }
+            RxEvent::Text(text) => packet.extend_from_slice(text).unwrap(), // fits, text is short
+        };
+        self.pending = Some(packet);
+        Ok(true)
+    }
+
+    fn get_received(
+        &mut self,
+        _info: &mut Self::Info,
+        buff: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        let packet = match self.pending.take() {
+            Some(p) => p,
+            None => fail("get_received without a packet"),
+        };
+        if buff.len() < packet.len() {
+            fail("receive buffer too small");
+        };
+        buff[..packet.len()].copy_from_slice(&packet);
+        self.received += 1;
+        Ok(packet.len())
+    }
+}
+
+impl radio::Channel for SimRadio {
+    type Channel = Channel;
+    type Error = SimRadioError;
+
+    fn set_channel(&mut self, _channel: &Self::Channel) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Reinit for SimRadio {
+    type Error = SimRadioError;
+
+    // as the real radio, left in standby
+    fn reinit(&mut self) -> Result<(), Self::Error> {
+        self.state = State::Idle;
+        self.pending = None;
+        Ok(())
+    }
+}
+
+impl SetModem for SimRadio {
+    type Error = SimRadioError;
+
+    // Packets are delivered the same way with either modem and sync word. As the real radios, left in
+    // standby and refusing a channel of the other modem.
+    fn set_modem(&mut self, modem: &ModemConfig, channel: &Channel) -> Result<(), Self::Error> {
+        match (modem, channel) {
+            (ModemConfig::LoRa(_), Channel::LoRa(_))
+            | (ModemConfig::LoRaWan(_), Channel::LoRa(_))
+            | (ModemConfig::Fsk(_), Channel::FskOok(_)) => {
+                self.state = State::Idle;
+                self.pending = None;
+                Ok(())
+            }
+            _ => Err(sx127xError::InvalidConfiguration),
+        }
+    }
+}
+
+impl ChannelActivity for SimRadio {
+    type Error = SimRadioError;
+
+    // the simulated channel is always clear, so EXPECTED_TX is sent without backoff
+    fn channel_busy(&mut self, _config: &LbtConfig) -> Result<bool, Self::Error> {
+        self.state = State::Idle;
+        Ok(false)
+    }
+}
+
+impl Fhss for SimRadio {
+    type Error = SimRadioError;
+
+    fn fhss_service(&mut self, _config: &HopConfig) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
+
+// GPS usart receive, playing NMEA_SCRIPT. When it is finished the transmitted packets are checked.
+#[derive(Default)]
+pub struct ScriptRx {
+    next: usize,
+}
+
+impl ScriptRx {
+    pub fn new() -> Self {
+        ScriptRx::default()
+    }
+}
+
+impl old_e_h::serial::Read<u8> for ScriptRx {
+    type Error = SimError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if let Some(b) = NMEA_SCRIPT.get(self.next) {
+            self.next += 1;
+            return Ok(*b);
+        };
+        let sent = SENT.load(Ordering::Relaxed);
+        if sent < EXPECTED_TX.len() {
+            logln!("{} of {} packets transmitted", sent, EXPECTED_TX.len());
+            fail("GPS script finished before all packets were transmitted");
+        };
+        logln!("emulated board: ok, {} packets transmitted", sent);
+        finish(true)
+    }
+}
+
+// GPS usart transmit (and log output with feature log-uart), discarded
+pub struct SinkTx;
+
+impl old_e_h::serial::Write<u8> for SinkTx {
+    type Error = SimError;
+
+    fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+// nothing on the i2c bus, every transfer fails
+pub struct NoI2c;
+
+impl old_e_h::blocking::i2c::Write for NoI2c {
+    type Error = SimError;
+
+    fn write(&mut self, _address: u8, _bytes: &[u8]) -> Result<(), Self::Error> {
+        Err(SimError)
+    }
+}
+
+impl old_e_h::blocking::i2c::WriteRead for NoI2c {
+    type Error = SimError;
+
+    fn write_read(
+        &mut self,
+        _address: u8,
+        _bytes: &[u8],
+        _buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        Err(SimError)
+    }
+}
+
+// on board led, not emulated
+pub struct NoLed;
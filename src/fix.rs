@@ -0,0 +1,320 @@
+//! GPS fix quality, tracked from the RMC, GGA and GSA sentences.
+//!
+//!  RMC gives the position and whether it is valid, GGA the fix quality (GPS, DGPS, ...), number
+//!  of satellites and HDOP, and GSA whether the fix is 2D or 3D and the PDOP. The state is
+//!  reported on each RMC sentence (once per GPS epoch) using the latest GGA and GSA, which may be
+//!  from the previous epoch as receivers send the sentences in different orders.
+//!  A GPS that only sends RMC is treated as having good 3D fixes whenever RMC is valid.
+//!  u-blox modules can instead send NAV-PVT (see src/ubx.rs), which has all of this in one message
+//!  as well as an accuracy estimate.
+
+use heapless::Vec;
+
+use crate::geodesy::Position;
+use crate::nmea::{format_time, is_rmc, parse_gga, parse_gsa, parse_rmc_position, parse_rmc_time};
+use crate::ubx::NavPvt;
+
+// A fix lost less than this long ago is stale (eg passing under a bridge) rather than no fix.
+pub const STALE_S: u32 = 30;
+
+// Fixes with fewer satellites or larger dilution of precision are degraded.
+pub const MIN_SATELLITES: u8 = 5;
+pub const MAX_HDOP: f32 = 4.0;
+pub const MAX_PDOP: f32 = 6.0;
+// horizontal accuracy estimate, only from NAV-PVT
+pub const MAX_H_ACC_M: f32 = 25.0;
+
+const DAY_S: u32 = 24 * 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FixKind {
+    Fix2D,
+    Fix3D,
+    Dgps, // differential (including RTK), best accuracy
+}
+
+// why a fix is degraded, in order of precedence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Degraded {
+    Estimated,     // dead reckoning, not a GPS fix
+    TwoD,          // no altitude, and horizontal position is less accurate
+    FewSatellites, // fewer than MIN_SATELLITES
+    HighDop,       // HDOP above MAX_HDOP or PDOP above MAX_PDOP
+    PoorAccuracy,  // horizontal accuracy estimate worse than MAX_H_ACC_M
+}
+
+impl Degraded {
+    // short tag added to reports of degraded positions
+    pub fn tag(self) -> &'static [u8] {
+        match self {
+            Degraded::Estimated => b"EST",
+            Degraded::TwoD => b"2D",
+            Degraded::FewSatellites => b"SATS",
+            Degraded::HighDop => b"DOP",
+            Degraded::PoorAccuracy => b"ACC",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FixState {
+    // no valid fix, since the time of the last fix (UTC seconds of day) or None if never
+    NoFix { since: Option<u32> },
+    // fix lost less than STALE_S ago, at time since
+    Stale { since: u32 },
+    Degraded(Position, Degraded),
+    Good(Position, FixKind),
+}
+
+#[derive(Default)]
+pub struct FixTracker {
+    quality: Option<u8>, // from GGA
+    satellites: Option<u8>,
+    hdop: Option<f32>,
+    mode: Option<u8>, // from GSA
+    pdop: Option<f32>,
+    h_acc: Option<f32>, // metres, from NAV-PVT
+    time: Option<u32>,  // of the latest RMC or NAV-PVT
+    last_fix: Option<u32>,
+}
+
+impl FixTracker {
+    pub fn new() -> Self {
+        FixTracker::default()
+    }
+
+    // Update from a line from the GPS. Returns the fix state for RMC lines, None for others.
+    pub fn update(&mut self, line: &[u8]) -> Option<FixState> {
+        if let Some(gga) = parse_gga(line) {
+            self.quality = Some(gga.quality);
+            self.satellites = gga.satellites;
+            self.hdop = gga.hdop;
+            return None;
+        };
+
+        if let Some(gsa) = parse_gsa(line) {
+            self.mode = Some(gsa.mode);
+            self.pdop = gsa.pdop;
+            if gsa.hdop.is_some() {
+                self.hdop = gsa.hdop;
+            };
+            return None;
+        };
+
+        if !is_rmc(line) {
+            return None;
+        };
+        Some(self.state(parse_rmc_time(line), parse_rmc_position(line)))
+    }
+
+    // Update from a NAV-PVT message, which replaces the NMEA sentences.
+    pub fn update_pvt(&mut self, pvt: &NavPvt) -> FixState {
+        // as the GGA fix quality and GSA mode
+        self.quality = Some(match pvt.fix_type {
+            _ if !pvt.fix_ok => 0,
+            1 => 6,
+            _ if pvt.differential => 2,
+            2..=4 => 1,
+            _ => 0,
+        });
+        self.mode = Some(match pvt.fix_type {
+            2 => 2,
+            3 | 4 => 3,
+            _ => 1,
+        });
+        self.satellites = Some(pvt.satellites);
+        self.hdop = None;
+        self.pdop = Some(pvt.pdop as f32 * 0.01);
+        self.h_acc = Some(pvt.h_acc as f32 * 0.001);
+
+        self.state(pvt.time(), pvt.position())
+    }
+
+    // state at time now (if the GPS has it), with the position if there is a valid fix
+    fn state(&mut self, now: Option<u32>, position: Option<Position>) -> FixState {
+        self.time = now;
+        match position {
+            Some(p) => {
+                if now.is_some() {
+                    self.last_fix = now;
+                };
+                match self.degraded() {
+                    Some(d) => FixState::Degraded(p, d),
+                    None => FixState::Good(p, self.kind()),
+                }
+            }
+            None => match (now, self.last_fix) {
+                (Some(t), Some(since)) if (t + DAY_S - since) % DAY_S < STALE_S => {
+                    FixState::Stale { since }
+                }
+                (_, since) => FixState::NoFix { since },
+            },
+        }
+    }
+
+    // kind of the current fix, assuming RMC is valid
+    pub fn kind(&self) -> FixKind {
+        match (self.quality, self.mode) {
+            (_, Some(2)) => FixKind::Fix2D,
+            (Some(2), _) | (Some(4), _) | (Some(5), _) => FixKind::Dgps,
+            _ => FixKind::Fix3D,
+        }
+    }
+
+    // UTC time of day of the latest state (so of the fix it reports), if the GPS sent it
+    pub fn time(&self) -> Option<u32> {
+        self.time
+    }
+
+    pub fn satellites(&self) -> Option<u8> {
+        self.satellites
+    }
+
+    pub fn hdop(&self) -> Option<f32> {
+        self.hdop
+    }
+
+    pub fn pdop(&self) -> Option<f32> {
+        self.pdop
+    }
+
+    pub fn h_acc(&self) -> Option<f32> {
+        self.h_acc
+    }
+
+    // Reason the current fix is low quality, if it is. Values not sent by the GPS are not checked.
+    pub fn degraded(&self) -> Option<Degraded> {
+        if self.quality == Some(6) {
+            Some(Degraded::Estimated)
+        } else if self.kind() == FixKind::Fix2D {
+            Some(Degraded::TwoD)
+        } else if matches!(self.satellites, Some(n) if n < MIN_SATELLITES) {
+            Some(Degraded::FewSatellites)
+        } else if matches!(self.hdop, Some(d) if d > MAX_HDOP)
+            || matches!(self.pdop, Some(d) if d > MAX_PDOP)
+        {
+            Some(Degraded::HighDop)
+        } else if matches!(self.h_acc, Some(a) if a > MAX_H_ACC_M) {
+            Some(Degraded::PoorAccuracy)
+        } else {
+            None
+        }
+    }
+}
+
+// Status message  "no fix since 03:17:37Z"  (UTC), or  "no fix since start"  if there never was one.
+pub fn no_fix_message<const N: usize>(out: &mut Vec<u8, N>, since: Option<u32>) -> bool {
+    out.clear();
+    if out.extend_from_slice(b"no fix since ").is_err() {
+        return false;
+    };
+    match since {
+        None => out.extend_from_slice(b"start").is_ok(),
+        Some(t) => out.extend_from_slice(format_time(t).as_bytes()).is_ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RMC: &[u8] = b"$GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66";
+    const RMC_VOID: &[u8] = b"$GPRMC,031750.00,V,,,,,,,300321,,,N*7E";
+    const T: u32 = 3 * 3600 + 17 * 60 + 37;
+
+    fn tracker(quality: Option<u8>, mode: Option<u8>) -> FixTracker {
+        let mut t = FixTracker::new();
+        t.quality = quality;
+        t.mode = mode;
+        t
+    }
+
+    #[test]
+    fn kind() {
+        assert_eq!(tracker(None, None).kind(), FixKind::Fix3D);
+        assert_eq!(tracker(Some(1), Some(3)).kind(), FixKind::Fix3D);
+        assert_eq!(tracker(Some(1), Some(2)).kind(), FixKind::Fix2D);
+        assert_eq!(tracker(Some(2), Some(3)).kind(), FixKind::Dgps);
+        assert_eq!(tracker(Some(4), None).kind(), FixKind::Dgps);
+        assert_eq!(tracker(Some(5), Some(3)).kind(), FixKind::Dgps);
+        // a differential 2D fix is still 2D
+        assert_eq!(tracker(Some(2), Some(2)).kind(), FixKind::Fix2D);
+        assert_eq!(tracker(Some(4), Some(2)).kind(), FixKind::Fix2D);
+    }
+
+    #[test]
+    fn degraded() {
+        assert_eq!(
+            tracker(Some(6), Some(2)).degraded(),
+            Some(Degraded::Estimated)
+        );
+        assert_eq!(tracker(Some(2), Some(2)).degraded(), Some(Degraded::TwoD));
+
+        let mut t = tracker(Some(1), Some(3));
+        assert_eq!(t.degraded(), None);
+        t.satellites = Some(MIN_SATELLITES - 1);
+        assert_eq!(t.degraded(), Some(Degraded::FewSatellites));
+        t.satellites = Some(MIN_SATELLITES);
+        t.pdop = Some(MAX_PDOP + 0.1);
+        assert_eq!(t.degraded(), Some(Degraded::HighDop));
+        t.pdop = None;
+        t.hdop = Some(MAX_HDOP + 0.1);
+        assert_eq!(t.degraded(), Some(Degraded::HighDop));
+        t.hdop = Some(MAX_HDOP);
+        t.h_acc = Some(MAX_H_ACC_M + 1.0);
+        assert_eq!(t.degraded(), Some(Degraded::PoorAccuracy));
+    }
+
+    #[test]
+    fn update_from_nmea() {
+        let mut t = FixTracker::new();
+        // RMC only, so a good 3D fix
+        let p = match t.update(RMC) {
+            Some(FixState::Good(p, FixKind::Fix3D)) => p,
+            s => panic!("{:?}", s),
+        };
+        assert_eq!(t.time(), Some(T));
+
+        let gga = b"$GPGGA,031737.00,4523.74241,N,07540.61255,W,2,08,1.01,75.2,M,-34.3,M,,*6B";
+        assert_eq!(t.update(gga), None);
+        assert_eq!((t.satellites(), t.hdop()), (Some(8), Some(1.01)));
+        assert_eq!(t.update(RMC), Some(FixState::Good(p, FixKind::Dgps)));
+
+        let gsa = b"$GPGSA,A,2,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39";
+        assert_eq!(t.update(gsa), None);
+        assert_eq!((t.pdop(), t.hdop()), (Some(2.5), Some(1.3)));
+        assert_eq!(t.update(RMC), Some(FixState::Degraded(p, Degraded::TwoD)));
+    }
+
+    #[test]
+    fn stale_then_no_fix() {
+        let mut t = FixTracker::new();
+        assert_eq!(t.update(RMC_VOID), Some(FixState::NoFix { since: None }));
+
+        assert!(matches!(t.update(RMC), Some(FixState::Good(..))));
+        // 13s later
+        assert_eq!(t.update(RMC_VOID), Some(FixState::Stale { since: T }));
+        let late = b"$GPRMC,031837.00,V,,,,,,,300321,,,N*7E";
+        assert_eq!(t.update(late), Some(FixState::NoFix { since: Some(T) }));
+
+        let mut out: Vec<u8, 32> = Vec::new();
+        assert!(no_fix_message(&mut out, Some(T)));
+        assert_eq!(&out[..], b"no fix since 03:17:37Z");
+        assert!(no_fix_message(&mut out, None));
+        assert_eq!(&out[..], b"no fix since start");
+    }
+
+    #[test]
+    fn stale_across_midnight() {
+        let mut t = FixTracker::new();
+        let before = b"$GPRMC,235955.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66";
+        let after = b"$GPRMC,000010.00,V,,,,,,,310321,,,N*7E";
+        assert!(matches!(t.update(before), Some(FixState::Good(..))));
+        assert_eq!(
+            t.update(after),
+            Some(FixState::Stale {
+                since: 24 * 3600 - 5
+            })
+        );
+    }
+}
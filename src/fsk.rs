@@ -0,0 +1,192 @@
+//! FSK (GFSK) packet mode, for short range links at a much higher bitrate than LoRa, eg downloading
+//! a tracker's logged track.
+//!
+//!  The radio starts in LoRa with CONFIG_RADIO. SetModem::set_modem switches it to FSK with the
+//!  packet settings of an FskConfig (eg CONFIG_FSK) and a Channel::FskOok channel (eg CONFIG_FSK_CH)
+//!  with the frequency, bitrate, deviation and receive bandwidth, and back with ModemConfig::LoRa
+//!  (eg with CONFIG_LORA) and a LoRa channel (eg CONFIG_RADIO.channel). ModemConfig::LoRa also
+//!  changes LoRa packet settings, such as invert_iq for LoRaWAN downlinks. ModemConfig::LoRaWan is
+//!  LoRa with the public sync word of LoRaWAN networks instead of the private one, and
+//!  ModemConfig::LoRa returns to the private one. Once switched radio::Channel::set_channel changes
+//!  the channel as before, but only to a channel of the same modem, and Transmit and Receive are
+//!  unchanged.
+//!  Reinit (after radio errors) returns the radio to LoRa with CONFIG_RADIO, so a binary using FSK
+//!  calls set_modem again after Recovery::Reinitialised.
+//!  Both ends need the same settings. The SX127x, SX126x and STM32WL radios use the same whitening
+//!  and CRC (CCITT), so they can talk to each other in FSK as they do in LoRa.
+//!  The SX127x implementation is in lora_spi_gps_usart.rs, as it needs CONFIG_RADIO, using the
+//!  register settings here. The SX126x, STM32WL and emulated radios implement it in their modules.
+//!  With feature fsk send_spi and receive_spi switch to FSK with CONFIG_FSK and CONFIG_FSK_CH.
+
+use radio_sx127x::device::fsk::{
+    Bandwidth, Crc, CrcWhitening, DcFree, FskConfig as Sx127xFskConfig,
+};
+use radio_sx127x::device::lora::LoRaConfig;
+use radio_sx127x::device::Channel;
+
+// FSK packet settings, always with a variable length packet (length byte first).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FskConfig {
+    pub preamble_len: u16, // bytes
+    pub sync_word: [u8; 8],
+    pub sync_len: u8,    // bytes of sync_word used, 1 to 8
+    pub whitening: bool, // data whitening, so long runs of 0 or 1 do not upset the receiver
+    pub crc: bool,       // 2 byte CCITT CRC, packets with a bad CRC are reported as Crc errors
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModemConfig {
+    LoRa(LoRaConfig),    // eg CONFIG_LORA, with the private sync word
+    LoRaWan(LoRaConfig), // LoRa with the public sync word
+    Fsk(FskConfig),
+}
+
+// LoRa sync words as the SX126x (and STM32WL) register. The SX127x register has the high nibbles
+// of the two bytes, 0x12 and 0x34.
+pub const LORA_SYNC_PRIVATE: [u8; 2] = [0x14, 0x24];
+pub const LORA_SYNC_PUBLIC: [u8; 2] = [0x34, 0x44];
+
+// Radios that can switch modem after setup(). The channel must be for the same modem, otherwise
+// the result is InvalidConfiguration and the radio is not changed. The radio is left in standby.
+pub trait SetModem {
+    type Error;
+    fn set_modem(&mut self, modem: &ModemConfig, channel: &Channel) -> Result<(), Self::Error>;
+}
+
+pub fn valid(fsk: &FskConfig) -> bool {
+    (1..=8).contains(&fsk.sync_len)
+}
+
+// sync word of a LoRa modem
+pub fn lora_sync_word(modem: &ModemConfig) -> [u8; 2] {
+    match modem {
+        ModemConfig::LoRaWan(_) => LORA_SYNC_PUBLIC,
+        _ => LORA_SYNC_PRIVATE,
+    }
+}
+
+// Receive bandwidth in Hz, both sides of the carrier. The SX127x setting is for one side, the
+// SX126x (and STM32WL) setting for both.
+pub fn rx_bandwidth(bw: Bandwidth) -> u32 {
+    2 * match bw {
+        Bandwidth::Bw2600 => 2_600,
+        Bandwidth::Bw3100 => 3_100,
+        Bandwidth::Bw3900 => 3_900,
+        Bandwidth::Bw5200 => 5_200,
+        Bandwidth::Bw6300 => 6_300,
+        Bandwidth::Bw7800 => 7_800,
+        Bandwidth::Bw10400 => 10_400,
+        Bandwidth::Bw12500 => 12_500,
+        Bandwidth::Bw15600 => 15_600,
+        Bandwidth::Bw20800 => 20_800,
+        Bandwidth::Bw25000 => 25_000,
+        Bandwidth::Bw31300 => 31_300,
+        Bandwidth::Bw41700 => 41_700,
+        Bandwidth::Bw50000 => 50_000,
+        Bandwidth::Bw62500 => 62_500,
+        Bandwidth::Bw83300 => 83_300,
+        Bandwidth::Bw100000 => 100_000,
+        Bandwidth::Bw125000 => 125_000,
+        Bandwidth::Bw166700 => 166_700,
+        Bandwidth::Bw200000 => 200_000,
+        Bandwidth::Bw250000 => 250_000,
+    }
+}
+
+// SX127x FSK registers the driver configuration does not cover
+pub const REG_SYNC_CONFIG: u8 = 0x27;
+pub const REG_SYNC_VALUE1: u8 = 0x28;
+
+// SX127x LoRa sync word register, not covered by the driver configuration
+pub const REG_LORA_SYNC_WORD: u8 = 0x39;
+
+// sync word on, restarting receive after a packet (as the reset value), sync size is bits 2:0 + 1
+const SYNC_CONFIG: u8 = 0x90;
+
+// FskConfig as the SX127x driver configuration, which has no sync word settings
+pub fn sx127x_fsk_config(fsk: &FskConfig) -> Sx127xFskConfig {
+    Sx127xFskConfig {
+        preamble_len: fsk.preamble_len,
+        dc_free: if fsk.whitening {
+            DcFree::Whitening
+        } else {
+            DcFree::Off
+        },
+        crc: if fsk.crc { Crc::On } else { Crc::Off },
+        crc_whitening: CrcWhitening::Ccitt,
+        ..Default::default()
+    }
+}
+
+// SX127x REG_SYNC_CONFIG value, with the sync word length of a valid FskConfig. The sync word
+// itself goes in REG_SYNC_VALUE1 onwards.
+pub fn sx127x_sync_config(fsk: &FskConfig) -> u8 {
+    SYNC_CONFIG | (fsk.sync_len - 1)
+}
+
+// SX127x REG_LORA_SYNC_WORD value of a sync word
+pub fn sx127x_lora_sync_word(word: [u8; 2]) -> u8 {
+    (word[0] & 0xF0) | (word[1] >> 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FSK: FskConfig = FskConfig {
+        preamble_len: 5,
+        sync_word: [0xC1, 0x94, 0xC1, 0, 0, 0, 0, 0],
+        sync_len: 3,
+        whitening: true,
+        crc: true,
+    };
+
+    #[test]
+    fn valid_sync_len() {
+        assert!(valid(&FSK));
+        assert!(valid(&FskConfig { sync_len: 1, ..FSK }));
+        assert!(valid(&FskConfig { sync_len: 8, ..FSK }));
+        assert!(!valid(&FskConfig { sync_len: 0, ..FSK }));
+        assert!(!valid(&FskConfig { sync_len: 9, ..FSK }));
+    }
+
+    #[test]
+    fn rx_bandwidth_both_sides() {
+        assert_eq!(rx_bandwidth(Bandwidth::Bw2600), 5_200);
+        assert_eq!(rx_bandwidth(Bandwidth::Bw83300), 166_600);
+        assert_eq!(rx_bandwidth(Bandwidth::Bw250000), 500_000);
+    }
+
+    #[test]
+    fn sx127x_config() {
+        let c = sx127x_fsk_config(&FSK);
+        assert_eq!(c.preamble_len, 5);
+        assert_eq!(c.dc_free, DcFree::Whitening);
+        assert_eq!(c.crc, Crc::On);
+        assert_eq!(c.crc_whitening, CrcWhitening::Ccitt);
+
+        let plain = sx127x_fsk_config(&FskConfig {
+            whitening: false,
+            crc: false,
+            ..FSK
+        });
+        assert_eq!(plain.dc_free, DcFree::Off);
+        assert_eq!(plain.crc, Crc::Off);
+    }
+
+    #[test]
+    fn sx127x_sync() {
+        assert_eq!(sx127x_sync_config(&FSK), 0x92);
+        assert_eq!(sx127x_sync_config(&FskConfig { sync_len: 1, ..FSK }), 0x90);
+        assert_eq!(sx127x_sync_config(&FskConfig { sync_len: 8, ..FSK }), 0x97);
+    }
+
+    #[test]
+    fn lora_sync_words() {
+        let lora = LoRaConfig::default();
+        assert_eq!(lora_sync_word(&ModemConfig::LoRa(lora)), [0x14, 0x24]);
+        assert_eq!(lora_sync_word(&ModemConfig::LoRaWan(lora)), [0x34, 0x44]);
+        assert_eq!(sx127x_lora_sync_word(LORA_SYNC_PRIVATE), 0x12);
+        assert_eq!(sx127x_lora_sync_word(LORA_SYNC_PUBLIC), 0x34);
+    }
+}
@@ -0,0 +1,36 @@
+//! Gateway output: each received packet and its PacketInfo as one line of JSON on a serial port,
+//!  for a PC to read (see the host crate in host/).
+//!
+//!  {"rssi":-87,"snr":7,"data":"A50003FF002A06423431312D32..."}
+//!
+//!  data is the whole packet in hex, so the PC can decode it with the same codec as the firmware.
+//!  snr is null when the radio does not report it.
+
+use core::fmt::Write as _;
+
+use heapless::String;
+use nb::block;
+use old_e_h::serial::Write;
+
+// enough for a full MAX_PACKET packet in hex plus the metadata
+pub const MAX_LINE: usize = 600;
+
+// format one packet as a JSON line (ending in \n). Returns false if it does not fit.
+pub fn json_line(out: &mut String<MAX_LINE>, rssi: i16, snr: Option<i16>, data: &[u8]) -> bool {
+    out.clear();
+    let ok = match snr {
+        Some(snr) => write!(out, "{{\"rssi\":{},\"snr\":{},\"data\":\"", rssi, snr),
+        None => write!(out, "{{\"rssi\":{},\"snr\":null,\"data\":\"", rssi),
+    }
+    .is_ok();
+
+    ok && data.iter().all(|b| write!(out, "{:02X}", b).is_ok()) && out.push_str("\"}\n").is_ok()
+}
+
+// write bytes to a serial port, blocking until all are sent
+pub fn write_all<W: Write<u8>>(tx: &mut W, bytes: &[u8]) -> Result<(), W::Error> {
+    for b in bytes {
+        block!(tx.write(*b))?;
+    }
+    block!(tx.flush())
+}
@@ -0,0 +1,229 @@
+//! Distance and direction between GPS positions.
+//!  Haversine (spherical earth) and Vincenty (WGS84 ellipsoid) distances, initial bearing
+//!  and the compass point for a bearing. Angles are in degrees, distances in metres.
+//!  This is no_std, so the trigonometry comes from crate libm.
+
+use core::f64::consts::PI;
+
+use libm::{atan, atan2, cos, fabs, sin, sqrt, tan};
+
+// mean earth radius (IUGG) used for haversine
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// WGS84 ellipsoid used for Vincenty
+pub const WGS84_A: f64 = 6_378_137.0;
+pub const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+// decimal degrees, north and east positive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Position {
+    pub const fn new(lat: f64, lon: f64) -> Self {
+        Position { lat, lon }
+    }
+}
+
+fn to_rad(deg: f64) -> f64 {
+    deg * PI / 180.0
+}
+
+fn to_deg(rad: f64) -> f64 {
+    rad * 180.0 / PI
+}
+
+// great circle distance on a sphere. Good to about 0.5%, which is plenty for walking to a tracker.
+pub fn haversine(from: Position, to: Position) -> f64 {
+    let phi1 = to_rad(from.lat);
+    let phi2 = to_rad(to.lat);
+    let dphi = to_rad(to.lat - from.lat);
+    let dlambda = to_rad(to.lon - from.lon);
+
+    let a = sin(dphi / 2.0) * sin(dphi / 2.0)
+        + cos(phi1) * cos(phi2) * sin(dlambda / 2.0) * sin(dlambda / 2.0);
+
+    2.0 * EARTH_RADIUS_M * atan2(sqrt(a), sqrt(1.0 - a))
+}
+
+// Vincenty inverse formula on the WGS84 ellipsoid, accurate to well under a metre.
+// Returns None if the iteration does not converge (nearly antipodal points).
+pub fn vincenty(from: Position, to: Position) -> Option<f64> {
+    let b = (1.0 - WGS84_F) * WGS84_A;
+
+    let l = to_rad(to.lon - from.lon);
+    let u1 = atan((1.0 - WGS84_F) * tan(to_rad(from.lat)));
+    let u2 = atan((1.0 - WGS84_F) * tan(to_rad(to.lat)));
+    let (sin_u1, cos_u1) = (sin(u1), cos(u1));
+    let (sin_u2, cos_u2) = (sin(u2), cos(u2));
+
+    let mut lambda = l;
+    let mut converged = false;
+
+    let (mut sin_sigma, mut cos_sigma, mut sigma) = (0.0, 0.0, 0.0);
+    let (mut cos_sq_alpha, mut cos_2sigma_m) = (0.0, 0.0);
+
+    for _ in 0..100 {
+        let (sin_lambda, cos_lambda) = (sin(lambda), cos(lambda));
+
+        let t1 = cos_u2 * sin_lambda;
+        let t2 = cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda;
+        sin_sigma = sqrt(t1 * t1 + t2 * t2);
+        if sin_sigma == 0.0 {
+            return Some(0.0); // coincident points
+        }
+        cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        sigma = atan2(sin_sigma, cos_sigma);
+
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        // on the equator cos_sq_alpha is 0 and cos_2sigma_m is not used
+        cos_2sigma_m = if cos_sq_alpha != 0.0 {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+        } else {
+            0.0
+        };
+
+        let c = WGS84_F / 16.0 * cos_sq_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_sq_alpha));
+        let lambda_prev = lambda;
+        lambda = l
+            + (1.0 - c)
+                * WGS84_F
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m
+                            + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+
+        if fabs(lambda - lambda_prev) < 1e-12 {
+            converged = true;
+            break;
+        }
+    }
+
+    if !converged {
+        return None;
+    }
+
+    let u_sq = cos_sq_alpha * (WGS84_A * WGS84_A - b * b) / (b * b);
+    let a_ = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+    let b_ = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+    let delta_sigma = b_
+        * sin_sigma
+        * (cos_2sigma_m
+            + b_ / 4.0
+                * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                    - b_ / 6.0
+                        * cos_2sigma_m
+                        * (-3.0 + 4.0 * sin_sigma * sin_sigma)
+                        * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+
+    Some(b * a_ * (sigma - delta_sigma))
+}
+
+// Vincenty, falling back to haversine if it does not converge.
+pub fn distance(from: Position, to: Position) -> f64 {
+    match vincenty(from, to) {
+        Some(d) => d,
+        None => haversine(from, to),
+    }
+}
+
+// initial (forward azimuth) bearing from one position towards another,
+// in degrees clockwise from true north, 0 <= bearing < 360.
+pub fn initial_bearing(from: Position, to: Position) -> f64 {
+    let phi1 = to_rad(from.lat);
+    let phi2 = to_rad(to.lat);
+    let dlambda = to_rad(to.lon - from.lon);
+
+    let y = sin(dlambda) * cos(phi2);
+    let x = cos(phi1) * sin(phi2) - sin(phi1) * cos(phi2) * cos(dlambda);
+
+    (to_deg(atan2(y, x)) + 360.0) % 360.0
+}
+
+const COMPASS_POINTS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+// 16 point compass name for a bearing in degrees, eg 100.0 => "E"
+pub fn compass_point(bearing: f64) -> &'static str {
+    let b = ((bearing % 360.0) + 360.0) % 360.0;
+    COMPASS_POINTS[((b + 11.25) / 22.5) as usize % 16]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Flinders Peak to Buninyong, the worked example in Vincenty (1975): 54972.271m at 306 52' 05.37"
+    const FLINDERS: Position = Position::new(-37.951_033_416_7, 144.424_867_888_9);
+    const BUNINYONG: Position = Position::new(-37.652_821_138_9, 143.926_495_527_8);
+
+    #[test]
+    fn vincenty_worked_example() {
+        let d = vincenty(FLINDERS, BUNINYONG).unwrap();
+        assert!(fabs(d - 54_972.271) < 0.01, "{}", d);
+    }
+
+    #[test]
+    fn vincenty_degree_on_equator() {
+        // one degree of longitude on the equator is an arc of the major axis
+        let d = vincenty(Position::new(0.0, 0.0), Position::new(0.0, 1.0)).unwrap();
+        assert!(fabs(d - WGS84_A * PI / 180.0) < 0.001, "{}", d);
+    }
+
+    #[test]
+    fn haversine_within_half_percent() {
+        let d = haversine(FLINDERS, BUNINYONG);
+        assert!(fabs(d - 54_972.271) < 0.005 * 54_972.271, "{}", d);
+    }
+
+    #[test]
+    fn coincident_points() {
+        let p = Position::new(45.3790, -75.6768);
+        assert_eq!(vincenty(p, p), Some(0.0));
+        assert_eq!(haversine(p, p), 0.0);
+        assert_eq!(distance(p, p), 0.0);
+    }
+
+    #[test]
+    fn antipodal_falls_back_to_haversine() {
+        let from = Position::new(0.0, 0.0);
+        let to = Position::new(0.0, 180.0);
+        assert_eq!(vincenty(from, to), None);
+        assert!(fabs(distance(from, to) - PI * EARTH_RADIUS_M) < 1.0);
+    }
+
+    #[test]
+    fn bearing_cardinal_directions() {
+        let from_origin =
+            |lat, lon| initial_bearing(Position::new(0.0, 0.0), Position::new(lat, lon));
+        assert!(fabs(from_origin(1.0, 0.0)) < 1e-9);
+        assert!(fabs(from_origin(0.0, 1.0) - 90.0) < 1e-9);
+        assert!(fabs(from_origin(-1.0, 0.0) - 180.0) < 1e-9);
+        assert!(fabs(from_origin(0.0, -1.0) - 270.0) < 1e-9);
+    }
+
+    #[test]
+    fn bearing_worked_example() {
+        // on the sphere, so only close to the ellipsoidal 306.868
+        let b = initial_bearing(FLINDERS, BUNINYONG);
+        assert!(fabs(b - 306.868) < 0.2, "{}", b);
+    }
+
+    #[test]
+    fn compass_points() {
+        assert_eq!(compass_point(0.0), "N");
+        assert_eq!(compass_point(11.24), "N");
+        assert_eq!(compass_point(11.25), "NNE");
+        assert_eq!(compass_point(100.0), "E");
+        assert_eq!(compass_point(225.0), "SW");
+        assert_eq!(compass_point(348.75), "N");
+        assert_eq!(compass_point(360.0), "N");
+        assert_eq!(compass_point(-90.0), "W");
+    }
+}
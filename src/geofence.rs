@@ -0,0 +1,234 @@
+//! Geofences (circles and small polygons) and boundary crossing detection.
+//!  Each decoded fix is checked against a list of fences and a crossing is reported
+//!  when the tracker enters or leaves one of them.
+
+use heapless::Vec;
+use libm::fabs;
+
+use crate::geodesy::{distance, Position};
+
+// fence state is kept in a bit mask
+pub const MAX_GEOFENCES: usize = 32;
+
+pub enum Geofence {
+    // centre and radius in metres
+    Circle { center: Position, radius_m: f64 },
+    // vertices in order, the last is joined to the first
+    Polygon(&'static [Position]),
+}
+
+impl Geofence {
+    pub fn contains(&self, p: Position) -> bool {
+        match self {
+            Geofence::Circle { center, radius_m } => distance(*center, p) <= *radius_m,
+            Geofence::Polygon(vertices) => point_in_polygon(p, vertices),
+        }
+    }
+}
+
+// true if p is on the segment a-b, to within rounding
+fn on_segment(p: Position, a: Position, b: Position) -> bool {
+    let cross = (b.lat - a.lat) * (p.lon - a.lon) - (b.lon - a.lon) * (p.lat - a.lat);
+    fabs(cross) < 1e-12
+        && p.lat >= a.lat.min(b.lat)
+        && p.lat <= a.lat.max(b.lat)
+        && p.lon >= a.lon.min(b.lon)
+        && p.lon <= a.lon.max(b.lon)
+}
+
+// Ray casting (even-odd rule) treating lat/lon as plane coordinates. This is fine for
+// polygons of a few km that do not cross the antimeridian or include a pole.
+// Points on an edge or vertex are inside, as points on the circle are for Circle.
+pub fn point_in_polygon(p: Position, vertices: &[Position]) -> bool {
+    if vertices.len() < 3 {
+        return false;
+    };
+
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for i in 0..vertices.len() {
+        let (a, b) = (vertices[i], vertices[j]);
+        if on_segment(p, a, b) {
+            return true;
+        };
+        // does a ray from p going east cross edge a-b ?
+        if (a.lat > p.lat) != (b.lat > p.lat)
+            && p.lon < (b.lon - a.lon) * (p.lat - a.lat) / (b.lat - a.lat) + a.lon
+        {
+            inside = !inside;
+        };
+        j = i;
+    }
+    inside
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crossing {
+    // index of the fence in the list
+    Entered(usize),
+    Exited(usize),
+}
+
+pub struct GeofenceMonitor<'a> {
+    fences: &'a [Geofence],
+    inside: u32, // bit i set when inside fences[i]
+    known: bool, // false until the first fix
+}
+
+impl<'a> GeofenceMonitor<'a> {
+    // only the first MAX_GEOFENCES fences are checked
+    pub fn new(fences: &'a [Geofence]) -> Self {
+        GeofenceMonitor {
+            fences: &fences[..fences.len().min(MAX_GEOFENCES)],
+            inside: 0,
+            known: false,
+        }
+    }
+
+    // Check a fix against every fence and return the crossings since the previous fix.
+    // The first fix only establishes where the tracker is, so gives no crossings.
+    pub fn update(&mut self, p: Position) -> Vec<Crossing, MAX_GEOFENCES> {
+        let mut crossings = Vec::new();
+
+        let mut inside = 0u32;
+        for (i, fence) in self.fences.iter().enumerate() {
+            if fence.contains(p) {
+                inside |= 1 << i;
+            };
+        }
+
+        if self.known {
+            let changed = inside ^ self.inside;
+            for i in 0..self.fences.len() {
+                if changed & (1 << i) != 0 {
+                    let c = if inside & (1 << i) != 0 {
+                        Crossing::Entered(i)
+                    } else {
+                        Crossing::Exited(i)
+                    };
+                    // cannot fail, there are at most MAX_GEOFENCES fences
+                    crossings.push(c).unwrap();
+                };
+            }
+        };
+
+        self.inside = inside;
+        self.known = true;
+        crossings
+    }
+
+    pub fn is_inside(&self, fence: usize) -> bool {
+        self.known && self.inside & (1 << fence) != 0
+    }
+
+    // true when the last fix was not inside any fence (false before the first fix or with no fences)
+    pub fn is_outside_all(&self) -> bool {
+        self.known && !self.fences.is_empty() && self.inside == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 1 x 1 degree square, and an L shape (concave) made of three such squares
+    const SQUARE: &[Position] = &[
+        Position::new(0.0, 0.0),
+        Position::new(0.0, 1.0),
+        Position::new(1.0, 1.0),
+        Position::new(1.0, 0.0),
+    ];
+    const L_SHAPE: &[Position] = &[
+        Position::new(0.0, 0.0),
+        Position::new(0.0, 2.0),
+        Position::new(1.0, 2.0),
+        Position::new(1.0, 1.0),
+        Position::new(2.0, 1.0),
+        Position::new(2.0, 0.0),
+    ];
+
+    #[test]
+    fn inside_and_outside() {
+        assert!(point_in_polygon(Position::new(0.5, 0.5), SQUARE));
+        assert!(!point_in_polygon(Position::new(1.5, 0.5), SQUARE));
+        assert!(!point_in_polygon(Position::new(0.5, -0.5), SQUARE));
+        assert!(!point_in_polygon(Position::new(-0.5, -0.5), SQUARE));
+    }
+
+    #[test]
+    fn edges_and_vertices_are_inside() {
+        assert!(point_in_polygon(Position::new(0.0, 0.5), SQUARE)); // bottom edge
+        assert!(point_in_polygon(Position::new(0.5, 1.0), SQUARE)); // right edge
+        assert!(point_in_polygon(Position::new(1.0, 0.5), SQUARE)); // top edge
+        assert!(point_in_polygon(Position::new(0.5, 0.0), SQUARE)); // left edge
+        for v in SQUARE {
+            assert!(point_in_polygon(*v, SQUARE), "{:?}", v);
+        }
+        // on the line through an edge, but beyond the vertex
+        assert!(!point_in_polygon(Position::new(0.0, 1.5), SQUARE));
+    }
+
+    #[test]
+    fn concave_polygon() {
+        assert!(point_in_polygon(Position::new(0.5, 1.5), L_SHAPE));
+        assert!(point_in_polygon(Position::new(1.5, 0.5), L_SHAPE));
+        // the notch of the L
+        assert!(!point_in_polygon(Position::new(1.5, 1.5), L_SHAPE));
+        // the reflex vertex and the edges of the notch
+        assert!(point_in_polygon(Position::new(1.0, 1.0), L_SHAPE));
+        assert!(point_in_polygon(Position::new(1.0, 1.5), L_SHAPE));
+        assert!(point_in_polygon(Position::new(1.5, 1.0), L_SHAPE));
+    }
+
+    #[test]
+    fn degenerate_polygon() {
+        assert!(!point_in_polygon(Position::new(0.0, 0.0), &SQUARE[..2]));
+    }
+
+    #[test]
+    fn circle() {
+        let fence = Geofence::Circle {
+            center: Position::new(45.0, -75.0),
+            radius_m: 1000.0,
+        };
+        assert!(fence.contains(Position::new(45.0, -75.0)));
+        assert!(fence.contains(Position::new(45.008, -75.0))); // about 890m north
+        assert!(!fence.contains(Position::new(45.01, -75.0))); // about 1110m north
+    }
+
+    const FENCES: &[Geofence] = &[Geofence::Polygon(SQUARE), Geofence::Polygon(L_SHAPE)];
+
+    #[test]
+    fn first_fix_gives_no_crossing() {
+        let mut monitor = GeofenceMonitor::new(FENCES);
+        assert!(!monitor.is_outside_all()); // not known yet
+        assert!(monitor.update(Position::new(0.5, 0.5)).is_empty());
+        assert!(monitor.is_inside(0) && monitor.is_inside(1));
+    }
+
+    #[test]
+    fn enter_and_exit() {
+        let mut monitor = GeofenceMonitor::new(FENCES);
+        monitor.update(Position::new(3.0, 3.0));
+        assert!(monitor.is_outside_all());
+
+        // into the arm of the L only
+        assert_eq!(
+            &monitor.update(Position::new(1.5, 0.5))[..],
+            &[Crossing::Entered(1)]
+        );
+        // staying inside is not a crossing
+        assert!(monitor.update(Position::new(1.6, 0.5)).is_empty());
+        // into the square, which is also in the L
+        assert_eq!(
+            &monitor.update(Position::new(0.5, 0.5))[..],
+            &[Crossing::Entered(0)]
+        );
+        // into the notch, leaving both
+        assert_eq!(
+            &monitor.update(Position::new(1.5, 1.5))[..],
+            &[Crossing::Exited(0), Crossing::Exited(1)]
+        );
+        assert!(monitor.is_outside_all());
+    }
+}
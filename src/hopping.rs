@@ -0,0 +1,257 @@
+//! Frequency hopping over a set of channels, to spread traffic and get away from interference.
+//!
+//!  With feature hopping transmit() sends each packet with a header on a channel of CONFIG_HOP.set,
+//!  picked pseudo randomly from the sequence number in the header and CONFIG_HOP.key. Repeats of a
+//!  packet (same sequence number) and relayed copies go on the same channel. Receivers use a
+//!  Follower, which either listens on a matching schedule (Listen::Schedule: after a packet with
+//!  sequence number n it moves to the channel of n + 1) or rotates through the channels, staying
+//!  dwell_ms on each (Listen::Rotate). A scheduled receiver follows one sender, the last one heard,
+//!  and rotates until it hears one, or again after sync_timeout_ms without a packet.
+//!  Packets without a header (eg APRS) are sent on the channel the radio is on.
+//!  With feature fhss the SX127x also hops within a packet, every FHSS_PERIOD symbols, which keeps
+//!  long packets within dwell time limits. The radio asks for each new channel, so
+//!  Fhss::fhss_service has to be called (about every ms) while a packet is sent or received.
+//!  The SX126x and STM32WL radios cannot hop within a packet.
+//!  Both ends need the same CONFIG_HOP. With feature hopping FREQUENCY is channel 0 of the set, so
+//!  packets sent without hopping (eg by send_spi) are heard by receivers rotating through it.
+//!  The mesh does not hop, mesh_spi cannot be built with feature hopping.
+//!  The radio side (HopConfig::channel, Follower::listen, fhss_delay and the SX127x Fhss) is in
+//!  lora_spi_gps_usart.rs, with the CONFIG_CH and CONFIG_RADIO settings it uses.
+
+use crate::logln;
+use crate::prng::XorShift32;
+
+// count channels from first, spacing Hz apart. count must not be 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelSet {
+    pub first: u32, // Hz
+    pub spacing: u32,
+    pub count: u8,
+}
+
+// US915 sub-band 2 (LoRaWAN uplink channels 8 to 15), as used by TTN
+pub const US915_SUBBAND2: ChannelSet = ChannelSet {
+    first: 903_900_000,
+    spacing: 200_000,
+    count: 8,
+};
+
+impl ChannelSet {
+    pub const fn freq(&self, n: u8) -> u32 {
+        self.first + self.spacing * (n % self.count) as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Listen {
+    Schedule, // follow the sender's hops
+    Rotate,   // change channel every dwell_ms
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HopConfig {
+    pub set: ChannelSet,
+    pub key: u32, // the hop sequence, shared by the senders and receivers of a network
+    pub listen: Listen,
+    pub dwell_ms: u32,        // time on each channel while rotating
+    pub sync_timeout_ms: u32, // a scheduled receiver rotates again after this without a packet
+}
+
+impl HopConfig {
+    // Channel (of set) for the packet with sequence number seq, or for hop seq within a packet.
+    pub fn index(&self, seq: u16) -> u8 {
+        let mut rng = XorShift32::new(self.key ^ (seq as u32).wrapping_mul(0x9E37_79B9));
+        rng.next_u32(); // spread consecutive sequence numbers
+        (rng.next_u32() % self.set.count as u32) as u8
+    }
+}
+
+pub struct Follower {
+    config: HopConfig,
+    channel: u8,
+    next_seq: Option<u16>, // Some while following a schedule
+    waited_ms: u32,
+}
+
+impl Follower {
+    pub fn new(config: HopConfig) -> Self {
+        Follower {
+            config,
+            channel: 0,
+            next_seq: None,
+            waited_ms: 0,
+        }
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    // frequency of the current channel, Hz
+    pub fn freq(&self) -> u32 {
+        self.config.set.freq(self.channel)
+    }
+
+    // A packet was received, with the sequence number if it had a header. Returns the channel to
+    // listen on next if it changes.
+    pub fn received(&mut self, seq: Option<u16>) -> Option<u8> {
+        self.waited_ms = 0;
+        match (self.config.listen, seq) {
+            (Listen::Schedule, Some(seq)) => {
+                let next = seq.wrapping_add(1);
+                self.next_seq = Some(next);
+                self.retune(self.config.index(next))
+            }
+            _ => None,
+        }
+    }
+
+    // ms passed without a packet. Returns the channel to listen on next if it changes.
+    pub fn elapsed(&mut self, ms: u32) -> Option<u8> {
+        self.waited_ms += ms;
+        let limit = match self.next_seq {
+            Some(_) => self.config.sync_timeout_ms,
+            None => self.config.dwell_ms,
+        };
+        if self.waited_ms < limit {
+            return None;
+        };
+        self.waited_ms = 0;
+        if self.next_seq.take().is_some() {
+            logln!("hopping schedule lost, rotating");
+        };
+        self.retune((self.channel + 1) % self.config.set.count)
+    }
+
+    fn retune(&mut self, n: u8) -> Option<u8> {
+        if n == self.channel {
+            None
+        } else {
+            self.channel = n;
+            Some(n)
+        }
+    }
+}
+
+// Radios that can hop within a packet. With frequency hopping enabled in the LoRa configuration
+// the radio asks for the next channel every hop period, while transmitting or receiving.
+pub trait Fhss {
+    type Error;
+    // Retune if the radio asks for the next channel, the one for its hop count. Returns true if it
+    // did. Radios that cannot hop within a packet return Ok(false).
+    fn fhss_service(&mut self, config: &HopConfig) -> Result<bool, Self::Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOP: HopConfig = HopConfig {
+        set: US915_SUBBAND2,
+        key: 0x4C6F_5261,
+        listen: Listen::Schedule,
+        dwell_ms: 300,
+        sync_timeout_ms: 6000,
+    };
+
+    const ROTATE: HopConfig = HopConfig {
+        listen: Listen::Rotate,
+        ..HOP
+    };
+
+    #[test]
+    fn channel_set() {
+        assert_eq!(US915_SUBBAND2.freq(0), 903_900_000);
+        assert_eq!(US915_SUBBAND2.freq(7), 905_300_000);
+        assert_eq!(US915_SUBBAND2.freq(8), 903_900_000); // wraps
+    }
+
+    #[test]
+    fn index_in_set_and_repeatable() {
+        for seq in 0..2000u16 {
+            let n = HOP.index(seq);
+            assert!(n < HOP.set.count);
+            assert_eq!(n, HOP.index(seq));
+        }
+        assert!(HOP.index(u16::MAX) < HOP.set.count);
+    }
+
+    #[test]
+    fn index_spreads_over_set() {
+        let mut used = [0u32; 8];
+        for seq in 0..800u16 {
+            used[HOP.index(seq) as usize] += 1;
+        }
+        // every channel, and none much more than its share of 100
+        assert!(used.iter().all(|&u| u > 50 && u < 150), "{:?}", used);
+
+        // consecutive packets do not stay on one channel
+        let repeats = (0..800u16)
+            .filter(|&seq| HOP.index(seq) == HOP.index(seq + 1))
+            .count();
+        assert!(repeats < 200, "{}", repeats);
+    }
+
+    #[test]
+    fn index_depends_on_key() {
+        let other = HopConfig { key: 1, ..HOP };
+        let same = (0..100u16)
+            .filter(|&seq| HOP.index(seq) == other.index(seq))
+            .count();
+        assert!(same < 50, "{}", same);
+    }
+
+    #[test]
+    fn follower_rotates() {
+        let mut f = Follower::new(ROTATE);
+        assert_eq!(f.channel(), 0);
+        assert_eq!(f.freq(), 903_900_000);
+
+        assert_eq!(f.elapsed(200), None);
+        assert_eq!(f.elapsed(100), Some(1));
+        assert_eq!(f.freq(), 904_100_000);
+
+        // a packet does not change the rotation, but restarts the dwell time
+        assert_eq!(f.elapsed(200), None);
+        assert_eq!(f.received(Some(5)), None);
+        assert_eq!(f.elapsed(200), None);
+        assert_eq!(f.elapsed(100), Some(2));
+
+        for n in 3..8 {
+            assert_eq!(f.elapsed(300), Some(n));
+        }
+        assert_eq!(f.elapsed(300), Some(0)); // wraps
+    }
+
+    #[test]
+    fn follower_schedule() {
+        let mut f = Follower::new(HOP);
+
+        // rotates until it hears a sender
+        assert_eq!(f.elapsed(300), Some(1));
+
+        // then listens on the channel of the next packet
+        let seq = (0..100u16).find(|&s| HOP.index(s + 1) != 1).unwrap();
+        let next = HOP.index(seq + 1);
+        assert_eq!(f.received(Some(seq)), Some(next));
+        assert_eq!(f.channel(), next);
+        assert_eq!(f.freq(), HOP.set.freq(next));
+
+        // and stays there longer than dwell_ms
+        assert_eq!(f.elapsed(300), None);
+        assert_eq!(f.elapsed(5000), None);
+
+        // a packet with the same next channel is no change
+        let mut f2 = Follower::new(HOP);
+        f2.received(Some(seq));
+        assert_eq!(f2.received(Some(seq)), None);
+
+        // a packet without a header changes nothing, but restarts the timeout
+        assert_eq!(f.received(None), None);
+        assert_eq!(f.elapsed(5999), None);
+
+        // the schedule is lost after sync_timeout_ms, and it rotates again every dwell_ms
+        assert_eq!(f.elapsed(1), Some((next + 1) % 8));
+        assert_eq!(f.elapsed(300), Some((next + 2) % 8));
+    }
+}
@@ -0,0 +1,177 @@
+//! Listen before talk, so trackers sharing a channel do not transmit over each other.
+//!
+//!  transmit() asks the radio whether the channel is busy before each transmission. With
+//!  Method::Cad a LoRa radio uses channel activity detection, which finds LoRa preambles even below
+//!  the noise floor. With Method::Rssi, and always in FSK (there is no CAD), the signal strength
+//!  on the channel is compared with rssi_threshold instead. While the channel is busy the sender
+//!  backs off for a random time, between backoff_min_ms and backoff_max_ms, and listens again.
+//!  After max_attempts busy checks the packet is dropped rather than sent into a collision.
+//!  The watchdog is fed through the backoffs. ListenBeforeTalk counts clear channels, deferred
+//!  transmissions, backoffs and dropped packets for the statistics, and logs them when a packet is
+//!  dropped. The binaries also log them every so often.
+
+use embedded_hal::delay::blocking::DelayMs;
+use radio_sx127x::base::Base;
+use radio_sx127x::prelude::*; // Sx127x
+use radio_sx127x::Error as sx127xError;
+
+use crate::logln;
+use crate::lora_spi_gps_usart::{delay_fed, Feed, CONFIG_RADIO, FREQUENCY};
+use crate::prng::XorShift32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Cad,  // channel activity detection in LoRa, RSSI in FSK
+    Rssi, // signal strength in both
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LbtConfig {
+    pub method: Method,
+    pub rssi_threshold: i16, // dBm, the channel is busy at or above this
+    pub cad_symbols: u8,     // 1, 2, 4, 8 or 16. The SX127x always uses 1.
+    pub cad_det_peak: u8, // SX126x and STM32WL CAD detection thresholds, depend on SF (AN1200.48)
+    pub cad_det_min: u8,
+    pub backoff_min_ms: u32,
+    pub backoff_max_ms: u32,
+    pub max_attempts: u32, // busy checks before the packet is dropped
+}
+
+// Radios that can tell whether another transmission is on the channel, after setup().
+// The radio is left in standby.
+pub trait ChannelActivity {
+    type Error;
+    fn channel_busy(&mut self, config: &LbtConfig) -> Result<bool, Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+    pub clear: u32,    // transmissions with the channel clear at the first check
+    pub deferred: u32, // transmissions that waited for the channel at least once
+    pub backoffs: u32,
+    pub dropped: u32, // the channel stayed busy
+    pub errors: u32,  // failed checks, the packet is sent anyway
+}
+
+pub struct ListenBeforeTalk {
+    config: LbtConfig,
+    rng: XorShift32,
+    counters: Counters,
+}
+
+impl ListenBeforeTalk {
+    // The seed should differ between senders (eg SENDER_ID), so they do not back off in step.
+    pub fn new(config: LbtConfig, seed: &[u8]) -> Self {
+        let mut rng = XorShift32::new(0);
+        rng.mix(seed);
+        ListenBeforeTalk {
+            config,
+            rng,
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    // Wait for a clear channel. Returns false if it was still busy after max_attempts checks, and
+    // then the packet should not be sent. A failed check does not hold up the transmission, which
+    // will report the radio error. The watchdog is fed while backing off.
+    pub fn wait_clear<T>(&mut self, radio: &mut T, wdog: &mut impl Feed) -> bool
+    where
+        T: ChannelActivity + DelayMs<u32>,
+    {
+        for attempt in 0..self.config.max_attempts {
+            match radio.channel_busy(&self.config) {
+                Ok(false) => {
+                    if attempt == 0 {
+                        self.counters.clear += 1;
+                    };
+                    return true;
+                }
+                Ok(true) => {
+                    if attempt == 0 {
+                        self.counters.deferred += 1;
+                    };
+                }
+                Err(_e) => {
+                    self.counters.errors += 1;
+                    return true;
+                }
+            };
+            self.counters.backoffs += 1;
+            let wait = self
+                .rng
+                .between(self.config.backoff_min_ms, self.config.backoff_max_ms);
+            let _ = delay_fed(radio, wdog, wait);
+        }
+        self.counters.dropped += 1;
+        logln!("channel busy, packet dropped, {:?}", self.counters);
+        false
+    }
+}
+
+// SX127x registers, the driver has no CAD or instant RSSI
+const REG_OP_MODE: u8 = 0x01;
+const REG_FSK_RSSI_VALUE: u8 = 0x11;
+const REG_LORA_IRQ_FLAGS: u8 = 0x12;
+const REG_LORA_RSSI_VALUE: u8 = 0x1B;
+
+const LONG_RANGE_MODE: u8 = 0x80; // LoRa
+const MODE_MASK: u8 = 0x07;
+const MODE_STANDBY: u8 = 0x01;
+const MODE_RX_CONTINUOUS: u8 = 0x05;
+const MODE_CAD: u8 = 0x07;
+
+const IRQ_CAD_DETECTED: u8 = 0x01;
+const IRQ_CAD_DONE: u8 = 0x04;
+
+// time for the receiver to start and the RSSI to settle
+pub const RSSI_SETTLE_MS: u32 = 2;
+
+impl<B, C, P, D> ChannelActivity for Sx127x<B>
+where
+    B: Base<C, P, D>,
+    Self: DelayMs<u32>,
+{
+    type Error = sx127xError<C, P, D>;
+
+    fn channel_busy(&mut self, config: &LbtConfig) -> Result<bool, Self::Error> {
+        let op = self.read_reg(REG_OP_MODE)? & !MODE_MASK;
+        let lora = op & LONG_RANGE_MODE != 0;
+
+        let busy = if lora && config.method == Method::Cad {
+            self.write_reg(REG_LORA_IRQ_FLAGS, IRQ_CAD_DONE | IRQ_CAD_DETECTED)?;
+            self.write_reg(REG_OP_MODE, op | MODE_CAD)?;
+            let mut flags = 0;
+            for _ in 0..CONFIG_RADIO.timeout_ms {
+                flags = self.read_reg(REG_LORA_IRQ_FLAGS)?;
+                if flags & IRQ_CAD_DONE != 0 {
+                    break;
+                };
+                let _ = self.delay_ms(1u32);
+            }
+            self.write_reg(REG_LORA_IRQ_FLAGS, IRQ_CAD_DONE | IRQ_CAD_DETECTED)?;
+            if flags & IRQ_CAD_DONE == 0 {
+                self.write_reg(REG_OP_MODE, op | MODE_STANDBY)?;
+                return Err(sx127xError::Timeout);
+            };
+            flags & IRQ_CAD_DETECTED != 0
+        } else {
+            self.write_reg(REG_OP_MODE, op | MODE_RX_CONTINUOUS)?;
+            let _ = self.delay_ms(RSSI_SETTLE_MS);
+            // LoRa RSSI offset for the high frequency port (above 779MHz), or the low one
+            let rssi = if lora {
+                let offset = if FREQUENCY >= 779_000_000 { -157 } else { -164 };
+                offset + self.read_reg(REG_LORA_RSSI_VALUE)? as i16
+            } else {
+                -(self.read_reg(REG_FSK_RSSI_VALUE)? as i16) / 2
+            };
+            rssi >= config.rssi_threshold
+        };
+
+        self.write_reg(REG_OP_MODE, op | MODE_STANDBY)?;
+        Ok(busy)
+    }
+}
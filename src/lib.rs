@@ -1,12 +1,46 @@
 #![no_std]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 
+// global logger for feature log-defmt
+#[cfg(feature = "log-defmt")]
+use defmt_rtt as _;
+
+pub mod aprs;
+pub mod arch;
+// before lora_spi_gps_usart, which uses its macros
+#[macro_use]
+pub mod board;
+#[cfg(feature = "lm3s6965")]
+pub mod emulated;
+pub mod fix;
+pub mod fsk;
+pub mod gateway;
+pub mod geodesy;
+pub mod geofence;
+pub mod hopping;
+pub mod lbt;
+pub mod log;
 pub mod lora_spi_gps_usart;
+#[cfg(feature = "lorawan")]
+pub mod lorawan;
+pub mod mesh;
+pub mod nmea;
+pub mod packet;
+pub mod pmtk;
+pub mod prng;
+pub mod relay;
+#[cfg(feature = "stm32wlxx")]
+pub mod subghz;
+pub mod supervisor;
+#[cfg(feature = "sx126x")]
+pub mod sx126x;
+pub mod ubx;
 
 // consider putting some real tests here
 
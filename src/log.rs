@@ -0,0 +1,142 @@
+//! Logging used by the library and binaries, in place of semihosting hprintln! directly.
+//!
+//!   logln!("relay {} listening", addr);     like hprintln!, but nothing to unwrap
+//!   log!(".");                              like hprint!
+//!
+//!  The backend is chosen by feature
+//!    log-defmt   defmt over RTT (eg probe-run, or any RTT viewer). Lines are formatted on the MCU,
+//!                and log! output is held until the next logln! (or MAX_LINE) so it is one defmt line.
+//!    log-uart    buffered, and written to a usart when the application calls log::drain(&mut tx).
+//!                Binaries that read a GPS on the usart (send_gps, monitor_gps) refuse this feature.
+//!  otherwise semihosting in debug builds, and nothing in release builds (or on RISC-V, which has
+//!  no semihosting).
+//!  Semihosting halts the MCU when no debugger is attached, so release builds (without log-defmt
+//!  or log-uart) run standalone, eg on battery.
+
+use core::fmt;
+
+#[cfg(any(feature = "log-uart", feature = "log-defmt"))]
+use crate::arch::{self, Mutex};
+#[cfg(any(feature = "log-uart", feature = "log-defmt"))]
+use core::cell::RefCell;
+#[cfg(feature = "log-uart")]
+use core::fmt::Write as _;
+#[cfg(feature = "log-uart")]
+use heapless::Deque;
+#[cfg(feature = "log-uart")]
+use nb::block;
+
+use old_e_h::serial::Write;
+
+#[cfg(all(feature = "log-defmt", feature = "log-uart"))]
+compile_error!("features log-defmt and log-uart cannot be used together");
+
+#[macro_export]
+macro_rules! logln {
+    ($($arg:tt)*) => {
+        $crate::log::write(format_args!($($arg)*), true)
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::write(format_args!($($arg)*), false)
+    };
+}
+
+// longest line sent with defmt, a longer line is sent in parts
+#[cfg(feature = "log-defmt")]
+pub const MAX_LINE: usize = 128;
+
+// log! output waiting for the end of the line
+#[cfg(feature = "log-defmt")]
+static LINE: Mutex<RefCell<heapless::String<MAX_LINE>>> =
+    Mutex::new(RefCell::new(heapless::String::new()));
+
+#[cfg(feature = "log-defmt")]
+pub fn write(args: fmt::Arguments, newline: bool) {
+    use core::fmt::Write as _;
+    arch::free(|cs| {
+        let mut line = LINE.borrow(cs).borrow_mut();
+        let start = line.len();
+        if line.write_fmt(args).is_err() {
+            // full, send the line so far and start again (a single longer line is truncated)
+            line.truncate(start);
+            defmt::println!("{=str}", line.as_str());
+            line.clear();
+            let _ = line.write_fmt(args);
+        };
+        if newline {
+            // println! rather than info! so nothing depends on DEFMT_LOG
+            defmt::println!("{=str}", line.as_str());
+            line.clear();
+        };
+    });
+}
+
+// bytes waiting for drain(). When full, further output is dropped until there is room.
+#[cfg(feature = "log-uart")]
+pub const UART_BUFFER: usize = 512;
+
+#[cfg(feature = "log-uart")]
+static BUFFER: Mutex<RefCell<Deque<u8, UART_BUFFER>>> = Mutex::new(RefCell::new(Deque::new()));
+
+#[cfg(feature = "log-uart")]
+struct Queue<'a>(&'a mut Deque<u8, UART_BUFFER>);
+
+#[cfg(feature = "log-uart")]
+impl fmt::Write for Queue<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            self.0.push_back(b).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log-uart")]
+pub fn write(args: fmt::Arguments, newline: bool) {
+    arch::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        let mut q = Queue(&mut buffer);
+        let _ = q.write_fmt(args);
+        if newline {
+            let _ = q.write_str("\r\n");
+        };
+    });
+}
+
+// Send buffered output to tx. Called regularly from the application loop.
+#[cfg(feature = "log-uart")]
+pub fn drain<W: Write<u8>>(tx: &mut W) {
+    while let Some(b) = arch::free(|cs| BUFFER.borrow(cs).borrow_mut().pop_front()) {
+        // nothing useful to do if the usart fails
+        let _ = block!(tx.write(b));
+    }
+}
+
+#[cfg(all(
+    not(feature = "log-defmt"),
+    not(feature = "log-uart"),
+    not(feature = "gd32vf103xx"),
+    debug_assertions
+))]
+pub fn write(args: fmt::Arguments, newline: bool) {
+    let _ = if newline {
+        cortex_m_semihosting::hprintln!("{}", args)
+    } else {
+        cortex_m_semihosting::hprint!("{}", args)
+    };
+}
+
+#[cfg(all(
+    not(feature = "log-defmt"),
+    not(feature = "log-uart"),
+    any(not(debug_assertions), feature = "gd32vf103xx")
+))]
+pub fn write(_args: fmt::Arguments, _newline: bool) {}
+
+// only log-uart needs the usart, so applications can call drain() whatever the backend
+#[cfg(not(feature = "log-uart"))]
+pub fn drain<W: Write<u8>>(_tx: &mut W) {}
@@ -1,11 +1,24 @@
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "gd32vf103xx")))]
 use panic_semihosting as _;
 
-#[cfg(not(debug_assertions))]
+// semihosting is only available on Cortex-M
+#[cfg(any(not(debug_assertions), feature = "gd32vf103xx"))]
 use panic_halt as _;
 
 use core::convert::Infallible;
 
+use crate::fsk::{
+    lora_sync_word, sx127x_fsk_config, sx127x_lora_sync_word, sx127x_sync_config, valid,
+    ModemConfig, SetModem, REG_LORA_SYNC_WORD, REG_SYNC_CONFIG, REG_SYNC_VALUE1,
+};
+use crate::hopping::{Fhss, Follower, HopConfig};
+use crate::lbt::{ChannelActivity, ListenBeforeTalk};
+#[cfg(feature = "lorawan")]
+use crate::lorawan::{self, LoRaWanRadio};
+use crate::mesh::{Link, MeshRadio};
+use crate::supervisor::{last_resort, Reinit, Supervisor};
+use crate::{log, logln};
+
 use embedded_hal::delay::blocking::DelayMs;
 //use embedded_hal_compat::eh1_0::blocking::delay::{DelayMs as _};
 
@@ -14,13 +27,16 @@ use embedded_hal::delay::blocking::DelayMs;
 // When passing the older hal crate objects to the newer rust-radio-sx127x methods
 // the objects are appended with .forward().
 
+#[cfg(not(feature = "sx126x"))]
 use embedded_hal_compat::ForwardCompat;
 
 // MODE needs the old version as it is passed to the device hal crates
 use old_e_h::spi::{Mode, Phase, Polarity};
 
+use radio_sx127x::base::Base;
 use radio_sx127x::Error as sx127xError; // Error name conflict with hals
 use radio_sx127x::{
+    device::fsk::{Bandwidth as FskBandwidth, FskChannel},
     device::lora::{
         Bandwidth, CodingRate, FrequencyHopping, LoRaChannel, LoRaConfig, PayloadCrc,
         PayloadLength, SpreadingFactor,
@@ -34,14 +50,28 @@ use radio::{Receive, Transmit};
 
 // lora and radio parameters
 
+#[cfg(not(feature = "sx126x"))]
 pub const MODE: Mode = Mode {
     //  SPI mode for radio
     phase: Phase::CaptureOnSecondTransition,
     polarity: Polarity::IdleHigh,
 };
 
+// the SX126x only supports SPI mode 0
+#[cfg(feature = "sx126x")]
+pub const MODE: Mode = Mode {
+    phase: Phase::CaptureOnFirstTransition,
+    polarity: Polarity::IdleLow,
+};
+
+#[cfg(not(feature = "hopping"))]
 pub const FREQUENCY: u32 = 907_400_000; // frequency in hertz ch_12: 915_000_000, ch_2: 907_400_000
 
+// With feature hopping, channel 0 of CONFIG_HOP, so the radio starts (and after Reinit is back) on
+// a channel of the set, where packets without hopping (eg from send_spi) are heard.
+#[cfg(feature = "hopping")]
+pub const FREQUENCY: u32 = CONFIG_HOP.set.freq(0);
+
 pub const CONFIG_CH: LoRaChannel = LoRaChannel {
     freq: FREQUENCY as u32, // frequency in hertz
     bw: Bandwidth::Bw125kHz,
@@ -54,10 +84,26 @@ pub const CONFIG_LORA: LoRaConfig = LoRaConfig {
     symbol_timeout: 0x64,
     payload_len: PayloadLength::Variable,
     payload_crc: PayloadCrc::Enabled,
+    #[cfg(not(feature = "fhss"))]
     frequency_hop: FrequencyHopping::Disabled,
+    #[cfg(feature = "fhss")]
+    frequency_hop: FrequencyHopping::Enabled(FHSS_PERIOD),
     invert_iq: false,
 };
 
+// Frequency hopping (see src/hopping.rs), between packets with feature hopping and within packets
+// with feature fhss. The receivers' sync_timeout_ms is longer than the send_gps REPORT_MS.
+pub const CONFIG_HOP: crate::hopping::HopConfig = crate::hopping::HopConfig {
+    set: crate::hopping::US915_SUBBAND2,
+    key: 0x4C6F_5261,
+    listen: crate::hopping::Listen::Schedule,
+    dwell_ms: 300,
+    sync_timeout_ms: 6000,
+};
+
+// symbols between hops within a packet, with feature fhss. About 20ms at SF7 and 125kHz.
+pub const FHSS_PERIOD: u8 = 20;
+
 //   compare other settings in python version
 //    lora.set_mode(sx127x_lora::RadioMode::Stdby).unwrap();
 //    set_tx_power(level, output_pin) level >17 => PA_BOOST.
@@ -81,12 +127,254 @@ pub const CONFIG_RADIO: radio_sx127x::device::Config = radio_sx127x::device::Con
     timeout_ms: 100,
 };
 
+// FSK (GFSK) settings for SetModem::set_modem (see src/fsk.rs), eg for a short range bulk transfer.
+// 50kbps with 25kHz deviation needs at least 50kHz (one side) receive bandwidth, the margin allows
+// for crystal offsets.
+pub const CONFIG_FSK_CH: FskChannel = FskChannel {
+    freq: FREQUENCY as u32,
+    br: 50_000, // bits per second
+    bw: FskBandwidth::Bw83300,
+    bw_afc: FskBandwidth::Bw100000,
+    fdev: 25_000, // Hz
+};
+
+pub const CONFIG_FSK: crate::fsk::FskConfig = crate::fsk::FskConfig {
+    preamble_len: 5,
+    sync_word: [0xC1, 0x94, 0xC1, 0, 0, 0, 0, 0],
+    sync_len: 3,
+    whitening: true,
+    crc: true,
+};
+
+// Listen before talk in transmit() (see src/lbt.rs). The CAD thresholds are the Semtech values for
+// SF7 with 2 symbols. The watchdog is fed while backing off, but a dropped packet holds up the
+// sender for up to max_attempts x backoff_max_ms (4s).
+pub const CONFIG_LBT: crate::lbt::LbtConfig = crate::lbt::LbtConfig {
+    method: crate::lbt::Method::Cad,
+    rssi_threshold: -90,
+    cad_symbols: 2,
+    cad_det_peak: 22,
+    cad_det_min: 10,
+    backoff_min_ms: 50,
+    backoff_max_ms: 500,
+    max_attempts: 8,
+};
+
+// SX126x settings that the SX127x does not have, for feature sx126x. A Heltec V3 (SX1262) has a
+// 1.8V TCXO on DIO3 and its antenna switch on DIO2.
+#[cfg(feature = "sx126x")]
+pub const CONFIG_SX126X: crate::sx126x::Config = crate::sx126x::Config {
+    device: crate::sx126x::Device::Sx1262,
+    tcxo: Some(crate::sx126x::Tcxo {
+        voltage: crate::sx126x::TcxoVoltage::V1_8,
+        startup_us: 5000,
+    }),
+    dio2_rf_switch: true,
+};
+
+// The radio on SPI, used by setup() with the hal objects (spi, cs, busy, ready, reset, delay).
+// An SX127x, or with feature sx126x an SX126x wired to the same pins, the SX127x DIO0 (BusyPin)
+// being the SX126x BUSY. Either way the result is a Result<radio, sx127xError<..>>.
+// (not used by the lm3s6965 and stm32wlxx setup(), which have no radio on SPI)
+#[cfg(not(feature = "sx126x"))]
+#[allow(unused_macros)]
+macro_rules! lora_spi {
+    ($spi:expr, $cs:expr, $busy:expr, $ready:expr, $reset:expr, $delay:expr $(,)?) => {
+        Sx127x::spi(
+            $spi.forward(),
+            $cs.forward(),
+            $busy.forward(),
+            $ready.forward(),
+            $reset.forward(),
+            $delay.forward(),
+            &CONFIG_RADIO,
+        )
+    };
+}
+
+// the SX126x driver uses the older embedded-hal traits, so the hal objects need no .forward()
+#[cfg(feature = "sx126x")]
+#[allow(unused_macros)]
+macro_rules! lora_spi {
+    ($spi:expr, $cs:expr, $busy:expr, $ready:expr, $reset:expr, $delay:expr $(,)?) => {
+        crate::sx126x::Sx126x::spi($spi, $cs, $busy, $ready, $reset, $delay, &CONFIG_SX126X)
+    };
+}
+
 // blink on board led to signal succesful transmit
 pub trait LED {
     fn on(&mut self) -> ();
     fn off(&mut self) -> ();
 }
 
+// on board led of a board in src/board.rs, which gives its polarity
+#[cfg(any(
+    feature = "stm32f1xx",
+    feature = "stm32f3xx",
+    feature = "stm32f4xx",
+    feature = "stm32l1xx"
+))]
+pub struct BoardLed<P> {
+    pin: P,
+}
+
+#[cfg(any(
+    feature = "stm32f1xx",
+    feature = "stm32f3xx",
+    feature = "stm32f4xx",
+    feature = "stm32l1xx"
+))]
+impl<P: old_e_h::digital::v2::OutputPin> BoardLed<P> {
+    pub fn new(pin: P) -> Self {
+        let mut led = BoardLed { pin };
+        led.off();
+        led
+    }
+}
+
+#[cfg(any(
+    feature = "stm32f1xx",
+    feature = "stm32f3xx",
+    feature = "stm32f4xx",
+    feature = "stm32l1xx"
+))]
+impl<P: old_e_h::digital::v2::OutputPin> LED for BoardLed<P> {
+    fn on(&mut self) -> () {
+        if crate::board::LED_ACTIVE_LOW {
+            old_e_h::digital::v2::OutputPin::set_low(&mut self.pin).ok();
+        } else {
+            old_e_h::digital::v2::OutputPin::set_high(&mut self.pin).ok();
+        }
+    }
+    fn off(&mut self) -> () {
+        if crate::board::LED_ACTIVE_LOW {
+            old_e_h::digital::v2::OutputPin::set_high(&mut self.pin).ok();
+        } else {
+            old_e_h::digital::v2::OutputPin::set_low(&mut self.pin).ok();
+        }
+    }
+}
+
+// Independent watchdog (IWDG). With feature watchdog setup() starts it and the MCU is reset if it is
+// not fed within WATCHDOG_MS, eg when block!(rx_gps.read()) waits forever on a loose GPS wire.
+// Binaries should feed it only when making progress (a GPS line read, the radio polled without error).
+// Once started the IWDG cannot be stopped, also not by a debugger halting the MCU.
+pub const WATCHDOG_MS: u32 = 8000;
+
+// Longest delay without feeding, in delay_fed(). Less than the 1s limit for the f0 and l0 hals.
+pub const FEED_MS: u32 = 500;
+
+// feed handle returned by setup()
+pub trait Feed {
+    fn feed(&mut self) -> ();
+}
+
+impl<W: old_e_h::watchdog::Watchdog> Feed for W {
+    fn feed(&mut self) -> () {
+        old_e_h::watchdog::Watchdog::feed(self)
+    }
+}
+
+// returned by setup() without feature watchdog, so binaries feed the same way
+pub struct NoWatchdog;
+
+impl old_e_h::watchdog::Watchdog for NoWatchdog {
+    fn feed(&mut self) -> () {}
+}
+
+// Baud rate of the GPS usart from setup(), the default of most GPS modules.
+pub const GPS_BAUD: u32 = 9600;
+
+// The usart registers behind the GPS Tx and Rx returned by setup(), for set_gps_baud().
+#[cfg(feature = "stm32f0xx")]
+type GpsUsartRegs = stm32f0xx_hal::pac::USART2;
+#[cfg(any(
+    feature = "stm32f1xx",
+    feature = "stm32f3xx",
+    feature = "stm32f4xx",
+    feature = "stm32l1xx"
+))]
+type GpsUsartRegs = crate::board::GpsUsart;
+#[cfg(feature = "stm32f7xx")]
+type GpsUsartRegs = stm32f7xx_hal::pac::USART2;
+#[cfg(feature = "stm32h7xx")]
+type GpsUsartRegs = stm32h7xx_hal::pac::USART2;
+#[cfg(feature = "stm32l0xx")]
+type GpsUsartRegs = stm32l0xx_hal::pac::USART2;
+#[cfg(feature = "stm32l4xx")]
+type GpsUsartRegs = stm32l4xx_hal::pac::USART2;
+#[cfg(feature = "stm32wlxx")]
+type GpsUsartRegs = stm32wlxx_hal::pac::USART2;
+
+// Change the GPS usart from its current baud rate (GPS_BAUD after setup()) to baud, after the GPS
+// has been told to switch (eg with ubx::cfg_prt_uart). The hals only set the rate when the usart
+// is created, so this scales the divider (BRR is the usart clock / baud), which needs no clocks
+// here. Waits for the last byte to be sent first. Returns false, and changes nothing, on MCUs
+// where it is not done.
+// Older usarts (f1, f4, l1) take a new BRR at any time.
+#[cfg(any(feature = "stm32f1xx", feature = "stm32f4xx", feature = "stm32l1xx"))]
+pub fn set_gps_baud(current: u32, baud: u32) -> bool {
+    let usart = unsafe { &*GpsUsartRegs::ptr() };
+    while usart.sr.read().bits() & (1 << 6) == 0 {} // TC, transmission complete
+    usart
+        .brr
+        .modify(|r, w| unsafe { w.bits((r.bits() * current + baud / 2) / baud) });
+    true
+}
+
+// Newer usarts only take a new BRR while disabled (UE, bit 0 of CR1).
+#[cfg(any(
+    feature = "stm32f0xx",
+    feature = "stm32f3xx",
+    feature = "stm32f7xx",
+    feature = "stm32h7xx",
+    feature = "stm32l0xx",
+    feature = "stm32l4xx",
+    feature = "stm32wlxx"
+))]
+pub fn set_gps_baud(current: u32, baud: u32) -> bool {
+    let usart = unsafe { &*GpsUsartRegs::ptr() };
+    while usart.isr.read().bits() & (1 << 6) == 0 {} // TC, transmission complete
+    usart.cr1.modify(|r, w| unsafe { w.bits(r.bits() & !1) });
+    usart
+        .brr
+        .modify(|r, w| unsafe { w.bits((r.bits() * current + baud / 2) / baud) });
+    usart.cr1.modify(|r, w| unsafe { w.bits(r.bits() | 1) });
+    true
+}
+
+// as the stm32f1 usart, with the registers named differently
+#[cfg(feature = "gd32vf103xx")]
+pub fn set_gps_baud(current: u32, baud: u32) -> bool {
+    let usart = unsafe { &*gd32vf103xx_hal::pac::USART1::ptr() };
+    while usart.stat.read().bits() & (1 << 6) == 0 {} // TC, transmission complete
+    usart
+        .baud
+        .modify(|r, w| unsafe { w.bits((r.bits() * current + baud / 2) / baud) });
+    true
+}
+
+// nrf52840 and rp2040 set the rate differently, and the emulated lm3s6965 has no GPS
+#[cfg(not(any(
+    feature = "stm32f0xx",
+    feature = "stm32f1xx",
+    feature = "stm32f3xx",
+    feature = "stm32f4xx",
+    feature = "stm32f7xx",
+    feature = "stm32h7xx",
+    feature = "stm32l0xx",
+    feature = "stm32l1xx",
+    feature = "stm32l4xx",
+    feature = "stm32wlxx",
+    feature = "gd32vf103xx"
+)))]
+pub fn set_gps_baud(_current: u32, _baud: u32) -> bool {
+    false
+}
+
+#[cfg(feature = "watchdog")]
+use old_e_h::watchdog::WatchdogEnable;
+
 // setup() does all  hal/MCU specific setup and returns generic object for use in main code.
 
 #[cfg(feature = "stm32f030xc")]
@@ -108,15 +396,24 @@ use stm32f0xx_hal::pac::I2C1 as I2C;
 #[cfg(feature = "stm32f0xx")]
 use old_e_h::digital::v2::OutputPin;
 
+#[cfg(all(feature = "stm32f0xx", feature = "watchdog"))]
+use stm32f0xx_hal::watchdog::Watchdog;
+
 #[cfg(feature = "stm32f0xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
     Tx<USART2>,
     Rx<USART2>,
     I2c<I2C, impl SclPin<I2C>, impl SdaPin<I2C>>,
     PC13<Output<PushPull>>,
+    impl Feed,
 ) {
     //  Infallible, Infallible   reflect the error type on the spi and gpio traits.
 
@@ -154,16 +451,7 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(),
-        pa1.forward(),
-        pb8.forward(),
-        pb9.forward(),
-        pa0.forward(),
-        delay.forward(),
-        &CONFIG_RADIO,
-    )
-    .unwrap(); // should handle error
+    let lora = lora_spi!(spi, pa1, pb8, pb9, pa0, delay,).unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     //  stm32f030xc builds with gpiob..into_alternate_af4(cs) USART3 on tx pb10, rx pb11
     //    but stm32f042  only has 2 usarts.
@@ -199,15 +487,23 @@ pub fn setup() -> (
 
     // led on pc13 with on/off  above
 
-    (lora, tx, rx, i2c, led)
+    // the f0 hal takes the timeout as a frequency, so 1s is the longest
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = Watchdog::new(p.IWDG);
+        wdog.start(1.hz());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32f1xx")] //  eg blue pill stm32f103
 use stm32f1xx_hal::{
     delay::Delay,
-    device::I2C2,
-    device::USART2,
-    gpio::{gpioc::PC13, Output, PushPull},
     i2c::{BlockingI2c, DutyCycle, Pins},
     pac::{CorePeripherals, Peripherals},
     prelude::*,
@@ -218,15 +514,24 @@ use stm32f1xx_hal::{
 //#[cfg(feature = "stm32f1xx")] //  eg blue pill stm32f103
 //use old_e_h::digital::v2::OutputPin;
 
+#[cfg(all(feature = "stm32f1xx", feature = "watchdog"))]
+use stm32f1xx_hal::watchdog::IndependentWatchdog;
+
 #[cfg(feature = "stm32f1xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
-    Tx<USART2>,
-    Rx<USART2>,
-    BlockingI2c<I2C2, impl Pins<I2C2>>,
-    PC13<Output<PushPull>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
+    Tx<crate::board::GpsUsart>,
+    Rx<crate::board::GpsUsart>,
+    BlockingI2c<crate::board::I2cBus, impl Pins<crate::board::I2cBus>>,
+    impl LED,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -243,12 +548,12 @@ pub fn setup() -> (
     let mut gpiob = p.GPIOB.split();
     let mut gpioc = p.GPIOC.split();
 
-    let spi = Spi::spi1(
-        p.SPI1,
+    let spi = board_periph!(spi_new)(
+        board_periph!(spi, p),
         (
-            gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl), //   sck   on PA5
-            gpioa.pa6.into_floating_input(&mut gpioa.crl),      //   miso  on PA6
-            gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl), //   mosi  on PA7
+            board_pin!(sck, gpioa, gpiob, gpioc),
+            board_pin!(miso, gpioa, gpiob, gpioc),
+            board_pin!(mosi, gpioa, gpiob, gpioc),
         ),
         &mut afio.mapr,
         MODE,
@@ -260,22 +565,21 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(),                                             //Spi
-        gpioa.pa1.into_push_pull_output(&mut gpioa.crl).forward(), //CsPin         on PA1
-        gpiob.pb8.into_floating_input(&mut gpiob.crh).forward(),   //BusyPin  DIO0 on PB8
-        gpiob.pb9.into_floating_input(&mut gpiob.crh).forward(),   //ReadyPin DIO1 on PB9
-        gpioa.pa0.into_push_pull_output(&mut gpioa.crl).forward(), //ResetPin      on PA0
-        delay.forward(),                                           //Delay
-        &CONFIG_RADIO,                                             //&Config
+    let lora = lora_spi!(
+        spi,
+        board_pin!(cs, gpioa, gpiob, gpioc),
+        board_pin!(busy, gpioa, gpiob, gpioc),
+        board_pin!(ready, gpioa, gpiob, gpioc),
+        board_pin!(reset, gpioa, gpiob, gpioc),
+        delay,
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
-    let (tx, rx) = Serial::usart2(
-        p.USART2,
+    let (tx, rx) = board_periph!(gps_usart_new)(
+        board_periph!(gps_usart, p),
         (
-            gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl), //tx pa2  for GPS rx
-            gpioa.pa3,                                          //rx pa3  for GPS tx
+            board_pin!(gps_tx, gpioa, gpiob, gpioc), // for GPS rx
+            board_pin!(gps_rx, gpioa, gpiob, gpioc), // for GPS tx
         ),
         &mut afio.mapr,
         Config::default().baudrate(9_600.bps()),
@@ -283,11 +587,11 @@ pub fn setup() -> (
     )
     .split();
 
-    let i2c = BlockingI2c::i2c2(
-        p.I2C2,
+    let i2c = board_periph!(i2c_new)(
+        board_periph!(i2c, p),
         (
-            gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh), // scl on PB10
-            gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh), // sda on PB11
+            board_pin!(scl, gpioa, gpiob, gpioc),
+            board_pin!(sda, gpioa, gpiob, gpioc),
         ),
         //&mut afio.mapr,  need this for i2c1 but not i2c2
         stm32f1xx_hal::i2c::Mode::Fast {
@@ -301,49 +605,55 @@ pub fn setup() -> (
         1000,
     );
 
-    //impl LED for dyn OutputPin<Error = Infallible> {
-    //    fn on(&mut self) -> () {
-    //        self.try_set_low().unwrap()
-    //    }
-    //    fn off(&mut self) -> () {
-    //        self.try_set_high().unwrap()
-    //    }
-    //}
+    let led = BoardLed::new(board_pin!(led, gpioa, gpiob, gpioc));
 
-    impl LED for PC13<Output<PushPull>> {
-        fn on(&mut self) -> () {
-            self.set_low()
-        }
-        fn off(&mut self) -> () {
-            self.set_high()
-        }
-    }
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchdog::new(p.IWDG);
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
 
-    let led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh); // led on pc13 with on/off
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
 
-    (lora, tx, rx, i2c, led)
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32f3xx")] //  eg Discovery-stm32f303
 use stm32f3xx_hal::{
     delay::Delay,
-    gpio::{gpioe::PE15, Output, PushPull},
     i2c::{I2c, SclPin, SdaPin},
-    pac::{CorePeripherals, Peripherals, I2C2, USART2},
+    pac::{CorePeripherals, Peripherals},
     prelude::*,
     serial::{Rx, RxPin, Serial, Tx, TxPin},
     spi::{Error, Spi},
 };
 
+#[cfg(all(feature = "stm32f3xx", feature = "watchdog"))]
+use stm32f3xx_hal::{time::duration::Milliseconds, watchdog::IndependentWatchDog};
+
 #[cfg(feature = "stm32f3xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
-    Tx<USART2, impl TxPin<USART2>>,
-    Rx<USART2, impl RxPin<USART2>>,
-    I2c<I2C2, (impl SclPin<I2C2>, impl SdaPin<I2C2>)>,
-    PE15<Output<PushPull>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
+    Tx<crate::board::GpsUsart, impl TxPin<crate::board::GpsUsart>>,
+    Rx<crate::board::GpsUsart, impl RxPin<crate::board::GpsUsart>>,
+    I2c<
+        crate::board::I2cBus,
+        (
+            impl SclPin<crate::board::I2cBus>,
+            impl SdaPin<crate::board::I2cBus>,
+        ),
+    >,
+    impl LED,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -360,17 +670,11 @@ pub fn setup() -> (
     let mut gpioe = p.GPIOE.split(&mut rcc.ahb);
 
     let spi = Spi::new(
-        p.SPI1,
+        board_periph!(spi, p),
         (
-            gpioa
-                .pa5
-                .into_af5_push_pull(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrl), // sck   on PA5
-            gpioa
-                .pa6
-                .into_af5_push_pull(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrl), // miso  on PA6
-            gpioa
-                .pa7
-                .into_af5_push_pull(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrl), // mosi  on PA7
+            board_pin!(sck, gpioa, gpiob, gpioe),
+            board_pin!(miso, gpioa, gpiob, gpioe),
+            board_pin!(mosi, gpioa, gpiob, gpioe),
         ),
         8_000_000.Hz(),
         clocks,
@@ -381,38 +685,21 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(), //Spi
-        gpioa
-            .pa1
-            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
-            .forward(), //CsPin   on PA1
-        gpiob
-            .pb8
-            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr)
-            .forward(), //BusyPin  DIO0 on PB8
-        gpiob
-            .pb9
-            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr)
-            .forward(), //ReadyPin DIO1 on PB9
-        gpioa
-            .pa0
-            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
-            .forward(), //ResetPin      on PA0
-        delay.forward(), //Delay
-        &CONFIG_RADIO, //&Config
+    let lora = lora_spi!(
+        spi,
+        board_pin!(cs, gpioa, gpiob, gpioe),
+        board_pin!(busy, gpioa, gpiob, gpioe),
+        board_pin!(ready, gpioa, gpiob, gpioe),
+        board_pin!(reset, gpioa, gpiob, gpioe),
+        delay,
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     let (tx, rx) = Serial::new(
-        p.USART2,
+        board_periph!(gps_usart, p),
         (
-            gpioa
-                .pa2
-                .into_af7_push_pull(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrl), //tx pa2 for GPS rx
-            gpioa
-                .pa3
-                .into_af7_push_pull(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrl), //rx pa3 for GPS tx
+            board_pin!(gps_tx, gpioa, gpiob, gpioe), // for GPS rx
+            board_pin!(gps_rx, gpioa, gpiob, gpioe), // for GPS tx
         ),
         9600.Bd(), // 115_200.bps(),
         clocks,
@@ -420,39 +707,38 @@ pub fn setup() -> (
     )
     .split();
 
-    let scl = gpioa
-        .pa9
-        .into_af4_open_drain(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrh);
-    let sda = gpioa
-        .pa10
-        .into_af4_open_drain(&mut gpioa.moder, &mut gpioa.otyper, &mut gpioa.afrh);
+    let scl = board_pin!(scl, gpioa, gpiob, gpioe);
+    let sda = board_pin!(sda, gpioa, gpiob, gpioe);
+
+    let i2c = I2c::new(
+        board_periph!(i2c, p),
+        (scl, sda),
+        400_000.Hz(),
+        clocks,
+        &mut rcc.apb1,
+    );
 
-    let i2c = I2c::new(p.I2C2, (scl, sda), 400_000.Hz(), clocks, &mut rcc.apb1);
+    let led = BoardLed::new(board_pin!(led, gpioa, gpiob, gpioe));
 
-    impl LED for PE15<Output<PushPull>> {
-        fn on(&mut self) -> () {
-            self.set_high().unwrap()
-        }
-        fn off(&mut self) -> () {
-            self.set_low().unwrap()
-        }
-    }
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchDog::new(p.IWDG);
+        wdog.start(Milliseconds(WATCHDOG_MS));
+        wdog
+    };
 
-    //led on pe15 with on/off
-    let led = gpioe
-        .pe15
-        .into_push_pull_output(&mut gpioe.moder, &mut gpioe.otyper);
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
 
-    (lora, tx, rx, i2c, led)
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32f4xx")]
-// eg Nucleo-64 stm32f411, blackpill stm32f411, blackpill stm32f401
+// eg Nucleo-64 stm32f411, blackpill stm32f411, blackpill stm32f401 (pins in src/board.rs)
 use stm32f4xx_hal::{
     delay::Delay,
-    gpio::{gpioc::PC13, Output, PushPull},
     i2c::{I2c, Pins},
-    pac::{CorePeripherals, Peripherals, I2C2, USART2},
+    pac::{CorePeripherals, Peripherals},
     prelude::*,
     serial::{config::Config, Rx, Serial, Tx},
     spi::{Error, Spi},
@@ -471,15 +757,24 @@ use stm32f4xx_hal::{
 // then
 //    pub fn setup() ->  LoraType {
 
+#[cfg(all(feature = "stm32f4xx", feature = "watchdog"))]
+use stm32f4xx_hal::watchdog::IndependentWatchdog;
+
 #[cfg(feature = "stm32f4xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
-    Tx<USART2>,
-    Rx<USART2>,
-    I2c<I2C2, impl Pins<I2C2>>,
-    PC13<Output<PushPull>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
+    Tx<crate::board::GpsUsart>,
+    Rx<crate::board::GpsUsart>,
+    I2c<crate::board::I2cBus, impl Pins<crate::board::I2cBus>>,
+    impl LED,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -489,14 +784,15 @@ pub fn setup() -> (
 
     let gpioa = p.GPIOA.split();
     let gpiob = p.GPIOB.split();
+    #[allow(unused_variables)] // led port on some boards
     let gpioc = p.GPIOC.split();
 
     let spi = Spi::new(
-        p.SPI1,
+        board_periph!(spi, p),
         (
-            gpioa.pa5.into_alternate(), // sck   on PA5
-            gpioa.pa6.into_alternate(), // miso  on PA6
-            gpioa.pa7.into_alternate(), // mosi  on PA7
+            board_pin!(sck, gpioa, gpiob, gpioc),
+            board_pin!(miso, gpioa, gpiob, gpioc),
+            board_pin!(mosi, gpioa, gpiob, gpioc),
         ),
         MODE,
         MegaHertz(8),
@@ -511,16 +807,15 @@ pub fn setup() -> (
     //            gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh),
     // however, gives trait bound  ... InputPin` is not satisfied
 
-    let lora = Sx127x::spi(
-        spi.forward(),                               //Spi
-        gpioa.pa1.into_push_pull_output().forward(), //CsPin         on PA1
-        gpiob.pb8.into_floating_input().forward(),   //BusyPin  DI00 on PB8
-        gpiob.pb9.into_floating_input().forward(),   //ReadyPin DI01 on PB9
-        gpioa.pa0.into_push_pull_output().forward(), //ResetPin      on PA0
-        delay.forward(),                             //Delay
-        &CONFIG_RADIO,                               //&Config
+    let lora = lora_spi!(
+        spi,
+        board_pin!(cs, gpioa, gpiob, gpioc),
+        board_pin!(busy, gpioa, gpiob, gpioc),  // DIO0
+        board_pin!(ready, gpioa, gpiob, gpioc), // DIO1
+        board_pin!(reset, gpioa, gpiob, gpioc),
+        delay,
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     //DIO0  triggers RxDone/TxDone status.
     //DIO1  triggers RxTimeout and other errors status.
@@ -529,10 +824,10 @@ pub fn setup() -> (
     //lora.lora_configure( config_lora, &config_ch ).unwrap(); # not yet pub, to change something
 
     let (tx, rx) = Serial::new(
-        p.USART2,
+        board_periph!(gps_usart, p),
         (
-            gpioa.pa2.into_alternate(), //tx pa2  for GPS rx
-            gpioa.pa3.into_alternate(), //rx pa3  for GPS tx
+            board_pin!(gps_tx, gpioa, gpiob, gpioc), // for GPS rx
+            board_pin!(gps_rx, gpioa, gpiob, gpioc), // for GPS tx
         ),
         Config::default().baudrate(9600.bps()),
         &clocks,
@@ -540,25 +835,25 @@ pub fn setup() -> (
     .unwrap()
     .split();
 
-    let scl = gpiob.pb10.into_alternate().set_open_drain(); // scl on PB10
-    let sda = gpiob.pb3.into_alternate().set_open_drain(); // sda on PB3
+    let scl = board_pin!(scl, gpioa, gpiob, gpioc);
+    let sda = board_pin!(sda, gpioa, gpiob, gpioc);
 
-    let i2c = I2c::new(p.I2C2, (scl, sda), 400.khz(), &clocks);
+    let i2c = I2c::new(board_periph!(i2c, p), (scl, sda), 400.khz(), &clocks);
 
-    // Note that blackpill with stm32f411 and nucleo-64 with stm32f411 have onboard led wired
-    // differently. Next will be reversed for nucleo-64 (in addition to PA5 vs PC13).
-    impl LED for PC13<Output<PushPull>> {
-        fn on(&mut self) -> () {
-            self.set_low()
-        }
-        fn off(&mut self) -> () {
-            self.set_high()
-        }
-    }
+    // blackpill and nucleo-64 leds are wired differently, see LED_ACTIVE_LOW in src/board.rs
+    let led = BoardLed::new(board_pin!(led, gpioa, gpiob, gpioc));
 
-    let led = gpioc.pc13.into_push_pull_output(); // led on pc13 with on/off (note delay is in lora)
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchdog::new(p.IWDG);
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
 
-    (lora, tx, rx, i2c, led)
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32f7xx")]
@@ -572,15 +867,24 @@ use stm32f7xx_hal::{
     spi::{ClockDivider, Error, Spi},
 };
 
+#[cfg(all(feature = "stm32f7xx", feature = "watchdog"))]
+use stm32f7xx_hal::watchdog::IndependentWatchdog;
+
 #[cfg(feature = "stm32f7xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
     Tx<USART2>,
     Rx<USART2>,
     BlockingI2c<I2C2, impl PinScl<I2C2>, impl PinSda<I2C2>>,
     PC13<Output<PushPull>>,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -608,16 +912,15 @@ pub fn setup() -> (
     // Create lora radio instance
 
     // spi::new  partially consumes rcc which causes problem for second use of clocks
-    let lora = Sx127x::spi(
-        spi.forward(),                               //Spi
-        gpioa.pa1.into_push_pull_output().forward(), //CsPin         on PA1
-        gpiob.pb8.into_floating_input().forward(),   //BusyPin  DIO0 on PB8
-        gpiob.pb9.into_floating_input().forward(),   //ReadyPin DIO1 on PB9
-        gpioa.pa0.into_push_pull_output().forward(), //ResetPin      on PA0
-        delay.forward(),                             //Delay
-        &CONFIG_RADIO,                               //&Config
+    let lora = lora_spi!(
+        spi,                               //Spi
+        gpioa.pa1.into_push_pull_output(), //CsPin         on PA1
+        gpiob.pb8.into_floating_input(),   //BusyPin  DIO0 on PB8
+        gpiob.pb9.into_floating_input(),   //ReadyPin DIO1 on PB9
+        gpioa.pa0.into_push_pull_output(), //ResetPin      on PA0
+        delay,                             //Delay
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     let (tx, rx) = Serial::new(
         p.USART2,
@@ -637,8 +940,8 @@ pub fn setup() -> (
     let scl = gpiob.pb10.into_alternate().set_open_drain();
     let sda = gpiob.pb11.into_alternate().set_open_drain();
 
-    let i2c = BlockingI2c::i2c2(
-        p.I2C2,
+    let i2c = board_periph!(i2c_new)(
+        board_periph!(i2c, p),
         (scl, sda),
         stm32f7xx_hal::i2c::Mode::standard(400_000.Hz()),
         clocks,
@@ -657,7 +960,17 @@ pub fn setup() -> (
 
     let led = gpioc.pc13.into_push_pull_output(); // led on pc13 with on/off
 
-    (lora, tx, rx, i2c, led)
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchdog::new(p.IWDG);
+        wdog.start(WATCHDOG_MS.millis());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32h7xx")]
@@ -675,15 +988,24 @@ use stm32h7xx_hal::{
 #[cfg(feature = "stm32h7xx")]
 use old_e_h::digital::v2::OutputPin;
 
+#[cfg(all(feature = "stm32h7xx", feature = "watchdog"))]
+use stm32h7xx_hal::independent_watchdog::IndependentWatchdog;
+
 #[cfg(feature = "stm32h7xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Never, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Never, Infallible>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Never, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Never, Infallible>>
+        + Reinit<Error = sx127xError<Error, Never, Infallible>>
+        + SetModem<Error = sx127xError<Error, Never, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Never, Infallible>>
+        + Fhss<Error = sx127xError<Error, Never, Infallible>>,
     Tx<USART2>,
     Rx<USART2>,
     I2c<I2C2>,
     PC13<Output<PushPull>>,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -714,16 +1036,15 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(),                               //Spi
-        gpioa.pa1.into_push_pull_output().forward(), //CsPin         on PA1
-        gpiob.pb8.into_floating_input().forward(),   //BusyPin  DIO0 on PB8
-        gpiob.pb9.into_floating_input().forward(),   //ReadyPin DIO1 on PB9
-        gpioa.pa0.into_push_pull_output().forward(), //ResetPin      on PA0
-        delay.forward(),                             //Delay
-        &CONFIG_RADIO,                               //&Config
+    let lora = lora_spi!(
+        spi,                               //Spi
+        gpioa.pa1.into_push_pull_output(), //CsPin         on PA1
+        gpiob.pb8.into_floating_input(),   //BusyPin  DIO0 on PB8
+        gpiob.pb9.into_floating_input(),   //ReadyPin DIO1 on PB9
+        gpioa.pa0.into_push_pull_output(), //ResetPin      on PA0
+        delay,                             //Delay
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     let (tx, rx) = p
         .USART2
@@ -757,7 +1078,17 @@ pub fn setup() -> (
 
     let led = gpioc.pc13.into_push_pull_output(); // led on pc13 with on/off
 
-    (lora, tx, rx, i2c, led)
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchdog::new(p.IWDG);
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32l0xx")]
@@ -775,11 +1106,17 @@ use stm32l0xx_hal::{
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, void::Void, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, void::Void, Infallible>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, void::Void, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, void::Void, Infallible>>
+        + Reinit<Error = sx127xError<Error, void::Void, Infallible>>
+        + SetModem<Error = sx127xError<Error, void::Void, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, void::Void, Infallible>>
+        + Fhss<Error = sx127xError<Error, void::Void, Infallible>>,
     Tx<USART2>,
     Rx<USART2>,
     I2c<I2C2, impl SDAPin<I2C2>, impl SCLPin<I2C2>>,
     PC13<Output<PushPull>>,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -805,16 +1142,15 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(),                               //Spi
-        gpioa.pa1.into_push_pull_output().forward(), //CsPin         on PA1
-        gpiob.pb8.into_floating_input().forward(),   //BusyPin  DIO0 on PB8
-        gpiob.pb9.into_floating_input().forward(),   //ReadyPin DIO1 on PB9
-        gpioa.pa0.into_push_pull_output().forward(), //ResetPin      on PA0
-        delay.forward(),                             //Delay
-        &CONFIG_RADIO,                               //&Config
+    let lora = lora_spi!(
+        spi,                               //Spi
+        gpioa.pa1.into_push_pull_output(), //CsPin         on PA1
+        gpiob.pb8.into_floating_input(),   //BusyPin  DIO0 on PB8
+        gpiob.pb9.into_floating_input(),   //ReadyPin DIO1 on PB9
+        gpioa.pa0.into_push_pull_output(), //ResetPin      on PA0
+        delay,                             //Delay
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     let (tx, rx) = p
         .USART2
@@ -843,32 +1179,45 @@ pub fn setup() -> (
 
     let led = gpioc.pc13.into_push_pull_output(); // led on pc13 with on/off
 
-    (lora, tx, rx, i2c, led)
+    // the l0 hal takes the timeout as a frequency, so 1s is the longest
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = p.IWDG.watchdog();
+        wdog.start(1.Hz());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32l1xx")] // eg  Discovery kit stm32l100 and Heltec lora_node STM32L151CCU6
 use stm32l1xx_hal::{
-    gpio::{gpiob::PB6, Output, PushPull},
     i2c::{I2c, Pins},
     prelude::*,
     rcc, // for ::Config but note name conflict with serial
     serial::{Config, Rx, SerialExt, Tx},
     spi::Error,
-    stm32::{CorePeripherals, Peripherals, I2C1, USART1},
+    stm32::{CorePeripherals, Peripherals},
 };
 
-#[cfg(feature = "stm32l1xx")]
-use old_e_h::digital::v2::OutputPin;
-
 #[cfg(feature = "stm32l1xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
-    Tx<USART1>,
-    Rx<USART1>,
-    I2c<I2C1, impl Pins<I2C1>>,
-    PB6<Output<PushPull>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
+    Tx<crate::board::GpsUsart>,
+    Rx<crate::board::GpsUsart>,
+    I2c<crate::board::I2cBus, impl Pins<crate::board::I2cBus>>,
+    impl LED,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -876,12 +1225,14 @@ pub fn setup() -> (
 
     let gpioa = p.GPIOA.split(&mut rcc);
     let gpiob = p.GPIOB.split(&mut rcc);
+    #[allow(unused_variables)] // led port on some boards
+    let gpioc = p.GPIOC.split(&mut rcc);
 
-    let spi = p.SPI1.spi(
+    let spi = board_periph!(spi, p).spi(
         (
-            gpioa.pa5, // sck   on PA5  in board on Heltec
-            gpioa.pa6, // miso  on PA6  in board on Heltec
-            gpioa.pa7, // mosi  on PA7  in board on Heltec
+            board_pin!(sck, gpioa, gpiob, gpioc),
+            board_pin!(miso, gpioa, gpiob, gpioc),
+            board_pin!(mosi, gpioa, gpiob, gpioc),
         ),
         MODE,
         8.mhz(),
@@ -892,24 +1243,21 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    //  Heltec lora_node STM32L151CCU6
-    let lora = Sx127x::spi(
-        spi.forward(),                               //Spi
-        gpioa.pa4.into_push_pull_output().forward(), //CsPin         on PA4  in board on Heltec
-        gpiob.pb11.into_floating_input().forward(),  //BusyPin  DIO0 on PB11 in board on Heltec
-        gpiob.pb10.into_floating_input().forward(),  //ReadyPin DIO1 on PB10 in board on Heltec
-        gpioa.pa3.into_push_pull_output().forward(), //ResetPin      on PA3  in board on Heltec
-        delay.forward(),                             //Delay
-        &CONFIG_RADIO,                               //&Config
+    let lora = lora_spi!(
+        spi,
+        board_pin!(cs, gpioa, gpiob, gpioc),
+        board_pin!(busy, gpioa, gpiob, gpioc),  // DIO0
+        board_pin!(ready, gpioa, gpiob, gpioc), // DIO1
+        board_pin!(reset, gpioa, gpiob, gpioc),
+        delay,
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
-    let (tx, rx) = p
-        .USART1
+    let (tx, rx) = board_periph!(gps_usart, p)
         .usart(
             (
-                gpioa.pa9,  //tx pa9   for GPS rx
-                gpioa.pa10, //rx pa10  for GPS tx
+                board_pin!(gps_tx, gpioa, gpiob, gpioc), // for GPS rx
+                board_pin!(gps_rx, gpioa, gpiob, gpioc), // for GPS tx
             ),
             Config::default().baudrate(9600.bps()),
             &mut rcc,
@@ -917,23 +1265,24 @@ pub fn setup() -> (
         .unwrap()
         .split();
 
-    let scl = gpiob.pb8.into_open_drain_output();
-    let sda = gpiob.pb9.into_open_drain_output();
+    let scl = board_pin!(scl, gpioa, gpiob, gpioc);
+    let sda = board_pin!(sda, gpioa, gpiob, gpioc);
 
-    let i2c = p.I2C1.i2c((scl, sda), 400.khz(), &mut rcc);
+    let i2c = board_periph!(i2c, p).i2c((scl, sda), 400.khz(), &mut rcc);
 
-    impl LED for PB6<Output<PushPull>> {
-        fn on(&mut self) -> () {
-            self.set_high().unwrap()
-        }
-        fn off(&mut self) -> () {
-            self.set_low().unwrap()
-        }
-    }
+    let led = BoardLed::new(board_pin!(led, gpioa, gpiob, gpioc));
+
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = p.IWDG.watchdog();
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
 
-    let led = gpiob.pb6.into_push_pull_output(); // led on pb6 with on/off
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
 
-    (lora, tx, rx, i2c, led)
+    (lora, tx, rx, i2c, led, wdog)
 }
 
 #[cfg(feature = "stm32l4xx")]
@@ -947,15 +1296,24 @@ use stm32l4xx_hal::{
     spi::{Error, Spi},
 };
 
+#[cfg(all(feature = "stm32l4xx", feature = "watchdog"))]
+use stm32l4xx_hal::watchdog::IndependentWatchdog;
+
 #[cfg(feature = "stm32l4xx")]
 pub fn setup() -> (
     impl DelayMs<u32>
         + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
-        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>,
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
     Tx<USART2>,
     Rx<USART2>,
     I2c<I2C1, (impl SclPin<I2C1>, impl SdaPin<I2C1>)>,
     PC13<Output<PushPull>>,
+    impl Feed,
 ) {
     let cp = CorePeripherals::take().unwrap();
     let p = Peripherals::take().unwrap();
@@ -1002,28 +1360,23 @@ pub fn setup() -> (
 
     // Create lora radio instance
 
-    let lora = Sx127x::spi(
-        spi.forward(), //Spi
+    let lora = lora_spi!(
+        spi, //Spi
         gpioa
             .pa1
-            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
-            .forward(), //CsPin   on PA1
+            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper), //CsPin   on PA1
         gpiob
             .pb8
-            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr)
-            .forward(), //BusyPin  DIO0 on PB8
+            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr), //BusyPin  DIO0 on PB8
         gpiob
             .pb9
-            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr)
-            .forward(), //ReadyPin DIO1 on PB9
+            .into_floating_input(&mut gpiob.moder, &mut gpiob.pupdr), //ReadyPin DIO1 on PB9
         gpioa
             .pa0
-            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper)
-            .forward(), //ResetPin      on PA0
-        delay.forward(), //Delay
-        &CONFIG_RADIO, //&Config
+            .into_push_pull_output(&mut gpioa.moder, &mut gpioa.otyper), //ResetPin      on PA0
+        delay, //Delay
     )
-    .unwrap(); // should handle error
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
 
     let (tx, rx) = Serial::usart2(
         p.USART2,
@@ -1078,7 +1431,994 @@ pub fn setup() -> (
         .pc13
         .into_push_pull_output(&mut gpioc.moder, &mut gpioc.otyper);
 
-    (lora, tx, rx, i2c, led)
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = IndependentWatchdog::new(p.IWDG);
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
+}
+
+#[cfg(feature = "nrf52840")] //  eg Adafruit Feather nRF52840 with an RFM95 FeatherWing
+use nrf52840_hal::{
+    clocks::Clocks,
+    delay::Delay,
+    gpio::{p0, p1, Level, Output, Pin, PushPull},
+    pac::{CorePeripherals, Peripherals, TWIM0, UARTE0},
+    prelude::*,
+    spim::{self, Spim},
+    twim::{self, Twim},
+    uarte::{self, Baudrate, Parity, Uarte, UarteRx, UarteTx},
+};
+
+#[cfg(all(feature = "nrf52840", feature = "watchdog"))]
+use nrf52840_hal::wdt::{count, handles::Hdl0, Watchdog, WatchdogHandle};
+
+// The nRF watchdog is fed ("petted") through a handle, wrapped so it has the usual feed().
+#[cfg(all(feature = "nrf52840", feature = "watchdog"))]
+pub struct NrfWatchdog(WatchdogHandle<Hdl0>);
+
+#[cfg(all(feature = "nrf52840", feature = "watchdog"))]
+impl old_e_h::watchdog::Watchdog for NrfWatchdog {
+    fn feed(&mut self) -> () {
+        self.0.pet()
+    }
+}
+
+#[cfg(feature = "nrf52840")]
+pub fn setup() -> (
+    impl DelayMs<u32>
+        + Transmit<Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + Receive<Info = PacketInfo, Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + Reinit<Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + SetModem<Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + ChannelActivity<Error = sx127xError<spim::Error, void::Void, Infallible>>
+        + Fhss<Error = sx127xError<spim::Error, void::Void, Infallible>>,
+    UarteTx<UARTE0>,
+    UarteRx<UARTE0>,
+    Twim<TWIM0>,
+    Pin<Output<PushPull>>,
+    impl Feed,
+) {
+    let cp = CorePeripherals::take().unwrap();
+    let p = Peripherals::take().unwrap();
+
+    // the external crystal, as the internal RC oscillator is not accurate enough for the usart
+    let _clocks = Clocks::new(p.CLOCK).enable_ext_hfosc();
+
+    let port0 = p0::Parts::new(p.P0);
+    let port1 = p1::Parts::new(p.P1);
+
+    // SPIM0 and TWIM0 share an instance (as do SPIM1 and TWIM1), so the radio uses SPIM2
+    let spi = Spim::new(
+        p.SPIM2,
+        spim::Pins {
+            sck: port0.p0_14.into_push_pull_output(Level::Low).degrade(), // sck  on P0.14
+            mosi: Some(port0.p0_13.into_push_pull_output(Level::Low).degrade()), // mosi on P0.13
+            miso: Some(port0.p0_15.into_floating_input().degrade()),      // miso on P0.15
+        },
+        spim::Frequency::M8,
+        MODE,
+        0,
+    );
+
+    let delay = Delay::new(cp.SYST);
+
+    // Create lora radio instance
+
+    let cs = port0.p0_26.into_push_pull_output(Level::High).degrade(); //CsPin         on P0.26 (D9)
+    let dio0 = port0.p0_06.into_floating_input().degrade(); //BusyPin  DIO0 on P0.06 (D11)
+    let dio1 = port0.p0_08.into_floating_input().degrade(); //ReadyPin DIO1 on P0.08 (D12)
+    let reset = port0.p0_27.into_push_pull_output(Level::High).degrade(); //ResetPin on P0.27 (D10)
+
+    let lora = lora_spi!(
+        spi,   //Spi
+        cs,    //CsPin
+        dio0,  //BusyPin
+        dio1,  //ReadyPin
+        reset, //ResetPin
+        delay, //Delay
+    )
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
+
+    let uarte = Uarte::new(
+        p.UARTE0,
+        uarte::Pins {
+            txd: port0.p0_25.into_push_pull_output(Level::High).degrade(), //tx P0.25  for GPS rx
+            rxd: port0.p0_24.into_floating_input().degrade(),              //rx P0.24  for GPS tx
+            cts: None,
+            rts: None,
+        },
+        Parity::EXCLUDED,
+        Baudrate::BAUD9600,
+    );
+
+    // the uarte transfers by DMA from RAM, so tx and rx each need a static byte
+    let tx_buf = cortex_m::singleton!(: [u8; 1] = [0; 1]).unwrap();
+    let rx_buf = cortex_m::singleton!(: [u8; 1] = [0; 1]).unwrap();
+    let (tx, rx) = uarte.split(tx_buf, rx_buf).unwrap();
+
+    let i2c = Twim::new(
+        p.TWIM0,
+        twim::Pins {
+            scl: port0.p0_11.into_floating_input().degrade(), // scl on P0.11
+            sda: port0.p0_12.into_floating_input().degrade(), // sda on P0.12
+        },
+        twim::Frequency::K400,
+    );
+
+    impl LED for Pin<Output<PushPull>> {
+        fn on(&mut self) -> () {
+            self.set_high().unwrap()
+        }
+        fn off(&mut self) -> () {
+            self.set_low().unwrap()
+        }
+    }
+
+    let led = port1.p1_15.into_push_pull_output(Level::Low).degrade(); // red led on P1.15
+
+    // A soft reset (eg last_resort()) leaves the watchdog running, so it may need recovering.
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let handles = match Watchdog::try_new(p.WDT) {
+            Ok(mut wdt) => {
+                wdt.set_lfosc_ticks(WATCHDOG_MS * 32_768 / 1000);
+                wdt.activate::<count::One>().handles
+            }
+            Err(running) => match Watchdog::try_recover::<count::One>(running.release()) {
+                Ok(parts) => parts.handles,
+                Err(_wdt) => last_resort(),
+            },
+        };
+        NrfWatchdog(handles.0)
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
+}
+
+#[cfg(feature = "rp2040")] //  eg Raspberry Pi Pico with an RFM95 breakout
+use rp2040_hal::{
+    clocks::{init_clocks_and_plls, Clock},
+    fugit::RateExtU32,
+    gpio::{
+        bank0::{Gpio0, Gpio1, Gpio2, Gpio25, Gpio3},
+        FunctionI2C, FunctionSpi, FunctionUart, Pin, Pins, PushPullOutput,
+    },
+    i2c::I2C,
+    pac::{CorePeripherals, Peripherals, I2C1, UART0},
+    sio::Sio,
+    spi::Spi,
+    uart::{DataBits, Reader, StopBits, UartConfig, UartPeripheral, Writer},
+    watchdog::Watchdog,
+};
+
+#[cfg(all(feature = "rp2040", feature = "watchdog"))]
+use rp2040_hal::fugit::ExtU32;
+
+// The RP2040 boots from external flash, with this second stage bootloader (for the W25Q080 flash on
+// the Pico) in the first 256 bytes. memoryMaps/RP2040/memory.x places it there.
+#[cfg(feature = "rp2040")]
+#[link_section = ".boot2"]
+#[no_mangle]
+#[used]
+pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
+
+// crystal on the Pico
+#[cfg(feature = "rp2040")]
+const XOSC_HZ: u32 = 12_000_000;
+
+#[cfg(feature = "rp2040")]
+type UartPins = (Pin<Gpio0, FunctionUart>, Pin<Gpio1, FunctionUart>);
+
+#[cfg(feature = "rp2040")]
+pub fn setup() -> (
+    impl DelayMs<u32>
+        + Transmit<Error = sx127xError<Infallible, Infallible, Infallible>>
+        + Receive<Info = PacketInfo, Error = sx127xError<Infallible, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Infallible, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Infallible, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Infallible, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Infallible, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Infallible, Infallible, Infallible>>,
+    Writer<UART0, UartPins>,
+    Reader<UART0, UartPins>,
+    I2C<I2C1, (Pin<Gpio2, FunctionI2C>, Pin<Gpio3, FunctionI2C>)>,
+    Pin<Gpio25, PushPullOutput>,
+    impl Feed,
+) {
+    let cp = CorePeripherals::take().unwrap();
+    let mut p = Peripherals::take().unwrap();
+
+    // clock setup uses the watchdog tick, the watchdog itself is only started for feature watchdog
+    #[cfg_attr(not(feature = "watchdog"), allow(unused_mut))]
+    let mut watchdog = Watchdog::new(p.WATCHDOG);
+    let clocks = init_clocks_and_plls(
+        XOSC_HZ,
+        p.XOSC,
+        p.CLOCKS,
+        p.PLL_SYS,
+        p.PLL_USB,
+        &mut p.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let sio = Sio::new(p.SIO);
+    let pins = Pins::new(p.IO_BANK0, p.PADS_BANK0, sio.gpio_bank0, &mut p.RESETS);
+
+    let _sck = pins.gpio18.into_mode::<FunctionSpi>(); // sck  on GP18
+    let _mosi = pins.gpio19.into_mode::<FunctionSpi>(); // mosi on GP19
+    let _miso = pins.gpio16.into_mode::<FunctionSpi>(); // miso on GP16
+    let spi = Spi::<_, _, 8>::new(p.SPI0).init(
+        &mut p.RESETS,
+        clocks.peripheral_clock.freq(),
+        8.MHz(),
+        &MODE,
+    );
+
+    let delay = cortex_m::delay::Delay::new(cp.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Create lora radio instance
+
+    let lora = lora_spi!(
+        spi,                                 //Spi
+        pins.gpio17.into_push_pull_output(), //CsPin         on GP17
+        pins.gpio21.into_floating_input(),   //BusyPin  DIO0 on GP21
+        pins.gpio22.into_floating_input(),   //ReadyPin DIO1 on GP22
+        pins.gpio20.into_push_pull_output(), //ResetPin      on GP20
+        delay,                               //Delay
+    )
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
+
+    let (rx, tx) = UartPeripheral::new(
+        p.UART0,
+        (
+            pins.gpio0.into_mode::<FunctionUart>(), //tx GP0  for GPS rx
+            pins.gpio1.into_mode::<FunctionUart>(), //rx GP1  for GPS tx
+        ),
+        &mut p.RESETS,
+    )
+    .enable(
+        UartConfig::new(9600.Hz(), DataBits::Eight, None, StopBits::One),
+        clocks.peripheral_clock.freq(),
+    )
+    .unwrap()
+    .split();
+
+    let i2c = I2C::i2c1(
+        p.I2C1,
+        pins.gpio2.into_mode::<FunctionI2C>(), // sda on GP2
+        pins.gpio3.into_mode::<FunctionI2C>(), // scl on GP3
+        400.kHz(),
+        &mut p.RESETS,
+        clocks.system_clock.freq(),
+    );
+
+    impl LED for Pin<Gpio25, PushPullOutput> {
+        fn on(&mut self) -> () {
+            self.set_high().unwrap()
+        }
+        fn off(&mut self) -> () {
+            self.set_low().unwrap()
+        }
+    }
+
+    let led = pins.gpio25.into_push_pull_output(); // led on GP25 (Pico) with on/off
+
+    // at most about 8.3s (RP2040-E1, the count is decremented twice per tick)
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        watchdog.start(WATCHDOG_MS.millis());
+        watchdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
+}
+
+#[cfg(feature = "gd32vf103xx")]
+//  eg Longan Nano GD32VF103CBT6 (RISC-V), pins as for blue pill
+use gd32vf103xx_hal::{
+    delay::McycleDelay,
+    gpio::{gpioc::PC13, Output, PushPull},
+    i2c::{BlockingI2c, DutyCycle, Pins},
+    pac::{Peripherals, I2C1, USART1},
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+    spi::{Error, Spi},
+};
+
+#[cfg(all(feature = "gd32vf103xx", feature = "watchdog"))]
+use gd32vf103xx_hal::watchdog::FreeWatchdog;
+
+#[cfg(feature = "gd32vf103xx")]
+pub fn setup() -> (
+    impl DelayMs<u32>
+        + Transmit<Error = sx127xError<Error, Infallible, Infallible>>
+        + Receive<Info = PacketInfo, Error = sx127xError<Error, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<Error, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<Error, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<Error, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<Error, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<Error, Infallible, Infallible>>,
+    Tx<USART1>,
+    Rx<USART1>,
+    BlockingI2c<I2C1, impl Pins<I2C1>>,
+    PC13<Output<PushPull>>,
+    impl Feed,
+) {
+    let p = Peripherals::take().unwrap();
+
+    let mut rcu = p
+        .RCU
+        .configure()
+        .ext_hf_clock(8.mhz())
+        .sysclk(108.mhz())
+        .freeze();
+
+    let mut afio = p.AFIO.constrain(&mut rcu);
+    let gpioa = p.GPIOA.split(&mut rcu);
+    let gpiob = p.GPIOB.split(&mut rcu);
+    let gpioc = p.GPIOC.split(&mut rcu);
+
+    let spi = Spi::spi0(
+        p.SPI0,
+        (
+            gpioa.pa5.into_alternate_push_pull(), //   sck   on PA5
+            gpioa.pa6.into_floating_input(),      //   miso  on PA6
+            gpioa.pa7.into_alternate_push_pull(), //   mosi  on PA7
+        ),
+        &mut afio,
+        MODE,
+        8.mhz(),
+        &mut rcu,
+    );
+
+    // no SysTick on RISC-V, the delay counts machine cycles
+    let delay = McycleDelay::new(&rcu.clocks);
+
+    // Create lora radio instance
+
+    let lora = lora_spi!(
+        spi,                               //Spi
+        gpioa.pa1.into_push_pull_output(), //CsPin         on PA1
+        gpiob.pb8.into_floating_input(),   //BusyPin  DIO0 on PB8
+        gpiob.pb9.into_floating_input(),   //ReadyPin DIO1 on PB9
+        gpioa.pa0.into_push_pull_output(), //ResetPin      on PA0
+        delay,                             //Delay
+    )
+    .unwrap_or_else(|_e| last_resort()); // radio not responding, eg not connected
+
+    let (tx, rx) = Serial::new(
+        p.USART1,
+        (
+            gpioa.pa2.into_alternate_push_pull(), //tx pa2  for GPS rx
+            gpioa.pa3.into_floating_input(),      //rx pa3  for GPS tx
+        ),
+        Config::default().baudrate(9_600.bps()),
+        &mut afio,
+        &mut rcu,
+    )
+    .split();
+
+    let i2c = BlockingI2c::i2c1(
+        p.I2C1,
+        (
+            gpiob.pb10.into_alternate_open_drain(), // scl on PB10
+            gpiob.pb11.into_alternate_open_drain(), // sda on PB11
+        ),
+        gd32vf103xx_hal::i2c::Mode::Fast {
+            frequency: 400_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        &mut rcu,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    impl LED for PC13<Output<PushPull>> {
+        fn on(&mut self) -> () {
+            self.set_low().unwrap()
+        }
+        fn off(&mut self) -> () {
+            self.set_high().unwrap()
+        }
+    }
+
+    let led = gpioc.pc13.into_push_pull_output(); // red led on pc13 (Longan Nano) with on/off
+
+    // the free watchdog (FWDGT) is the GD32 name for the independent watchdog
+    #[cfg(feature = "watchdog")]
+    let wdog = {
+        let mut wdog = FreeWatchdog::new(p.FWDGT);
+        wdog.start(WATCHDOG_MS.ms());
+        wdog
+    };
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
+}
+
+#[cfg(feature = "stm32wlxx")] //  eg Seeed LoRa-E5 (STM32WLE5JC), radio in the MCU
+use stm32wlxx_hal::{
+    gpio::{pins, Output, PortA, PortB},
+    i2c::I2c2,
+    pac::{self, CorePeripherals, Peripherals},
+    rcc,
+    subghz::SubGhz,
+    uart::{self, Uart2},
+};
+
+#[cfg(feature = "stm32wlxx")]
+use cortex_m::{delay::Delay, peripheral::syst::SystClkSource};
+
+#[cfg(feature = "stm32wlxx")]
+use crate::subghz::{RfSwitch, SubGhzError, SubGhzRadio};
+
+#[cfg(feature = "stm32wlxx")]
+use core::cell::RefCell;
+
+#[cfg(feature = "stm32wlxx")]
+use crate::arch::Mutex;
+
+// The stm32wlxx hal usart cannot be split into tx and rx, so both halves share it.
+#[cfg(feature = "stm32wlxx")]
+type Gps = Uart2<pins::A3, pins::A2>;
+
+#[cfg(feature = "stm32wlxx")]
+pub struct SharedTx(&'static Mutex<RefCell<Gps>>);
+
+#[cfg(feature = "stm32wlxx")]
+pub struct SharedRx(&'static Mutex<RefCell<Gps>>);
+
+#[cfg(feature = "stm32wlxx")]
+impl old_e_h::serial::Write<u8> for SharedTx {
+    type Error = uart::Error;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        crate::arch::free(|cs| self.0.borrow(cs).borrow_mut().write(byte))
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        crate::arch::free(|cs| self.0.borrow(cs).borrow_mut().flush())
+    }
+}
+
+#[cfg(feature = "stm32wlxx")]
+impl old_e_h::serial::Read<u8> for SharedRx {
+    type Error = uart::Error;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        crate::arch::free(|cs| self.0.borrow(cs).borrow_mut().read())
+    }
+}
+
+// independent watchdog, the stm32wlxx hal has no driver for it
+#[cfg(all(feature = "stm32wlxx", feature = "watchdog"))]
+pub struct Iwdg(pac::IWDG);
+
+#[cfg(all(feature = "stm32wlxx", feature = "watchdog"))]
+impl Iwdg {
+    // LSI is 32kHz, divided by 256 the 12 bit reload is at most about 32s
+    fn start(iwdg: pac::IWDG, ms: u32) -> Self {
+        iwdg.kr.write(|w| unsafe { w.bits(0xCCCC) }); // start, also starts LSI
+        iwdg.kr.write(|w| unsafe { w.bits(0x5555) }); // unlock PR and RLR
+        iwdg.pr.write(|w| unsafe { w.bits(0b110) }); // divide by 256
+        iwdg.rlr
+            .write(|w| unsafe { w.bits((ms * 32 / 256).min(0xFFF)) });
+        while iwdg.sr.read().bits() != 0 {} // wait for the update
+        iwdg.kr.write(|w| unsafe { w.bits(0xAAAA) });
+        Iwdg(iwdg)
+    }
+}
+
+#[cfg(all(feature = "stm32wlxx", feature = "watchdog"))]
+impl old_e_h::watchdog::Watchdog for Iwdg {
+    fn feed(&mut self) -> () {
+        self.0.kr.write(|w| unsafe { w.bits(0xAAAA) })
+    }
+}
+
+#[cfg(feature = "stm32wlxx")]
+pub fn setup() -> (
+    impl DelayMs<u32>
+        + Transmit<Error = SubGhzError>
+        + Receive<Info = PacketInfo, Error = SubGhzError>
+        + radio::Channel<Channel = Channel, Error = SubGhzError>
+        + Reinit<Error = SubGhzError>
+        + SetModem<Error = SubGhzError>
+        + ChannelActivity<Error = SubGhzError>
+        + Fhss<Error = SubGhzError>,
+    SharedTx,
+    SharedRx,
+    I2c2<(pins::B15, pins::A15)>,
+    Output<pins::B5>,
+    impl Feed,
+) {
+    let cp = CorePeripherals::take().unwrap();
+    let mut p = Peripherals::take().unwrap();
+
+    // 48MHz from the MSI, and HSI16 for the usart so the baud rate does not depend on sysclk
+    cortex_m::interrupt::free(|cs| unsafe {
+        rcc::set_sysclk_msi_max(&mut p.FLASH, &mut p.PWR, &mut p.RCC, cs)
+    });
+    p.RCC.cr.modify(|_, w| w.hsion().set_bit());
+    while p.RCC.cr.read().hsirdy().is_not_ready() {}
+
+    let gpioa = PortA::split(p.GPIOA, &mut p.RCC);
+    let gpiob = PortB::split(p.GPIOB, &mut p.RCC);
+
+    let delay = Delay::new(cp.SYST, rcc::cpu_systick_hz(&p.RCC, SystClkSource::Core));
+
+    // Create lora radio instance. The radio is on the internal SPI3, with its own busy and irq lines.
+
+    let (rf, uart, i2c, led) = cortex_m::interrupt::free(|cs| {
+        let rf = RfSwitch::new(
+            Output::default(gpioa.a4, cs), // rx switch on PA4
+            Output::default(gpioa.a5, cs), // tx switch on PA5
+        );
+        let uart = Uart2::new(p.USART2, 9600, uart::Clk::Hsi16, &mut p.RCC)
+            .enable_rx(gpioa.a3, cs) //rx PA3  for GPS tx
+            .enable_tx(gpioa.a2, cs); //tx PA2  for GPS rx
+        let i2c = I2c2::new(
+            p.I2C2,
+            (gpiob.b15, gpioa.a15), // scl on PB15, sda on PA15
+            400_000,
+            &mut p.RCC,
+            true,
+            cs,
+        );
+        let led = Output::default(gpiob.b5, cs); // led on PB5 (LoRa-E5 mini) with on/off
+        (rf, uart, i2c, led)
+    });
+
+    let lora = SubGhzRadio::new(SubGhz::new(p.SPI3, &mut p.RCC), rf, delay)
+        .unwrap_or_else(|_e| last_resort()); // radio not responding
+
+    let gps: &'static Mutex<RefCell<Gps>> =
+        cortex_m::singleton!(: Mutex<RefCell<Gps>> = Mutex::new(RefCell::new(uart))).unwrap();
+    let (tx, rx) = (SharedTx(gps), SharedRx(gps));
+
+    impl LED for Output<pins::B5> {
+        fn on(&mut self) -> () {
+            self.set_level_low()
+        }
+        fn off(&mut self) -> () {
+            self.set_level_high()
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    let wdog = Iwdg::start(p.IWDG, WATCHDOG_MS);
+
+    #[cfg(not(feature = "watchdog"))]
+    let wdog = NoWatchdog;
+
+    (lora, tx, rx, i2c, led, wdog)
+}
+
+#[cfg(feature = "lm3s6965")] //  QEMU lm3s6965evb, simulated radio and GPS for automated tests
+use crate::emulated::{NoI2c, NoLed, ScriptRx, SimError, SimRadio, SinkTx};
+
+#[cfg(feature = "lm3s6965")]
+pub fn setup() -> (
+    impl DelayMs<u32>
+        + Transmit<Error = sx127xError<SimError, Infallible, Infallible>>
+        + Receive<Info = PacketInfo, Error = sx127xError<SimError, Infallible, Infallible>>
+        + radio::Channel<Channel = Channel, Error = sx127xError<SimError, Infallible, Infallible>>
+        + Reinit<Error = sx127xError<SimError, Infallible, Infallible>>
+        + SetModem<Error = sx127xError<SimError, Infallible, Infallible>>
+        + ChannelActivity<Error = sx127xError<SimError, Infallible, Infallible>>
+        + Fhss<Error = sx127xError<SimError, Infallible, Infallible>>,
+    SinkTx,
+    ScriptRx,
+    NoI2c,
+    NoLed,
+    impl Feed,
+) {
+    impl LED for NoLed {
+        fn on(&mut self) -> () {}
+        fn off(&mut self) -> () {}
+    }
+
+    logln!("emulated lm3s6965, simulated radio and GPS (see src/emulated.rs)");
+
+    // nothing to reset a hung binary, QEMU is run with a timeout instead
+    (
+        SimRadio::new(),
+        SinkTx,
+        ScriptRx::new(),
+        NoI2c,
+        NoLed,
+        NoWatchdog,
+    )
 }
 
 // End of hal/MCU specific setup. Following should be generic code.
+
+// Transmit buf and blink the led. Returns true if the radio reports the transmission complete.
+// This replaces the start_transmit / check_transmit sections that were repeated in the binaries.
+// The channel is checked first, and the packet is dropped if it stays busy (see src/lbt.rs).
+// With feature hopping a packet with a header is sent on the channel for its sequence number.
+// The watchdog is fed while waiting.
+pub fn transmit<T>(
+    lora: &mut T,
+    led: &mut impl LED,
+    lbt: &mut ListenBeforeTalk,
+    wdog: &mut impl Feed,
+    buf: &[u8],
+) -> bool
+where
+    T: Transmit + radio::Channel<Channel = Channel> + ChannelActivity + Fhss + DelayMs<u32>,
+{
+    #[cfg(feature = "hopping")]
+    if let Some((header, _)) = crate::packet::decode(buf) {
+        let n = CONFIG_HOP.index(header.seq);
+        if lora.set_channel(&CONFIG_HOP.channel(n)).is_err() {
+            logln!("Error returned from lora.set_channel().");
+        };
+    };
+
+    if !lbt.wait_clear(lora, wdog) {
+        return false;
+    };
+
+    match lora.start_transmit(buf) {
+        Ok(_b) => {
+            led.on();
+            let _ = lora.delay_ms(2u32);
+            led.off();
+        }
+        Err(_err) => {
+            logln!("Error returned from lora.start_transmit().");
+            //panic!("should reset in release mode.");
+            return false;
+        }
+    };
+
+    // The first transmission often return false and prints "x", but works after that.
+    // If this continually returns "TX not complete" then the radio should probably be reset,
+    //  but should avoid panic_reset after first transmission.
+
+    // With feature fhss the radio asks for a new channel every FHSS_PERIOD symbols, so wait for
+    // the end of the packet, servicing it.
+    #[cfg(feature = "fhss")]
+    let done = {
+        let mut waited = 0;
+        loop {
+            match lora.check_transmit() {
+                Ok(false) if waited < 2000 => (),
+                r => break r,
+            };
+            let _ = lora.fhss_service(&CONFIG_HOP);
+            let _ = lora.delay_ms(1u32);
+            wdog.feed();
+            waited += 1;
+        }
+    };
+    #[cfg(not(feature = "fhss"))]
+    let done = lora.check_transmit();
+
+    match done {
+        Ok(b) => {
+            if !b {
+                log!("x");
+            }
+            b
+        }
+        Err(_err) => {
+            logln!("Error returned from lora.check_transmit().");
+            false
+        }
+    }
+}
+
+// Delay for ms, feeding the watchdog at least every FEED_MS. For deliberate waits, such as the
+// report interval, which are longer than the watchdog timeout.
+pub fn delay_fed<T>(lora: &mut T, wdog: &mut impl Feed, ms: u32) -> Result<(), T::Error>
+where
+    T: DelayMs<u32>,
+{
+    let mut left = ms;
+    while left > 0 {
+        let step = left.min(FEED_MS);
+        lora.delay_ms(step)?;
+        wdog.feed();
+        left -= step;
+    }
+    Ok(())
+}
+
+// Poll check_transmit until the radio reports the transmission complete, or timeout_ms has passed,
+// feeding the watchdog meanwhile. Needed before switching back to receive, which would otherwise cut
+// off the transmission.
+pub fn wait_transmit<T>(lora: &mut T, wdog: &mut impl Feed, timeout_ms: u32) -> bool
+where
+    T: Transmit + DelayMs<u32>,
+{
+    let mut waited = 0;
+    while waited < timeout_ms {
+        if let Ok(true) = lora.check_transmit() {
+            return true;
+        };
+        let _ = lora.delay_ms(10u32);
+        wdog.feed();
+        waited += 10;
+    }
+    false
+}
+
+// FSK for the SX127x (see src/fsk.rs). configure() resets the radio and writes the whole
+// configuration, with the new modem. The sync words are not part of the configuration.
+impl<B, C, P, D> SetModem for Sx127x<B>
+where
+    B: Base<C, P, D>,
+{
+    type Error = sx127xError<C, P, D>;
+
+    fn set_modem(&mut self, modem: &ModemConfig, channel: &Channel) -> Result<(), Self::Error> {
+        let config = match (modem, channel) {
+            (ModemConfig::LoRa(lora), Channel::LoRa(_))
+            | (ModemConfig::LoRaWan(lora), Channel::LoRa(_)) => radio_sx127x::device::Config {
+                modem: Modem::LoRa(*lora),
+                channel: channel.clone(),
+                ..CONFIG_RADIO
+            },
+            (ModemConfig::Fsk(fsk), Channel::FskOok(_)) if valid(fsk) => {
+                radio_sx127x::device::Config {
+                    modem: Modem::FskOok(sx127x_fsk_config(fsk)),
+                    channel: channel.clone(),
+                    ..CONFIG_RADIO
+                }
+            }
+            _ => return Err(sx127xError::InvalidConfiguration),
+        };
+        self.configure(&config)?;
+
+        match modem {
+            ModemConfig::Fsk(fsk) => {
+                self.write_reg(REG_SYNC_CONFIG, sx127x_sync_config(fsk))?;
+                for (n, b) in fsk.sync_word[..fsk.sync_len as usize].iter().enumerate() {
+                    self.write_reg(REG_SYNC_VALUE1 + n as u8, *b)?;
+                }
+            }
+            _ => {
+                let word = sx127x_lora_sync_word(lora_sync_word(modem));
+                self.write_reg(REG_LORA_SYNC_WORD, word)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+// The radio side of hopping (see src/hopping.rs), kept here so hopping.rs has no radio code.
+impl HopConfig {
+    // LoRa channel n of set, with the other settings of CONFIG_CH
+    pub fn channel(&self, n: u8) -> Channel {
+        Channel::LoRa(LoRaChannel {
+            freq: self.set.freq(n),
+            ..CONFIG_CH
+        })
+    }
+}
+
+impl Follower {
+    // Switch the radio to the current channel and restart receive, eg after a change or Reinit.
+    pub fn listen<T, E>(&self, radio: &mut T) -> Result<(), E>
+    where
+        T: radio::Channel<Channel = Channel, Error = E> + Receive<Error = E>,
+    {
+        radio.set_channel(&Channel::LoRa(LoRaChannel {
+            freq: self.freq(),
+            ..CONFIG_CH
+        }))?;
+        radio.start_receive()
+    }
+}
+
+// Delay for ms in 1ms steps, servicing the hops of a packet being sent or received.
+pub fn fhss_delay<T>(
+    radio: &mut T,
+    config: &HopConfig,
+    ms: u32,
+) -> Result<(), <T as DelayMs<u32>>::Error>
+where
+    T: Fhss + DelayMs<u32>,
+{
+    for _ in 0..ms {
+        let _ = radio.fhss_service(config);
+        radio.delay_ms(1u32)?;
+    }
+    Ok(())
+}
+
+// SX127x LoRa registers, the driver only sets the hop period
+const REG_FRF_MSB: u8 = 0x06;
+const REG_LORA_IRQ_FLAGS: u8 = 0x12;
+const REG_HOP_CHANNEL: u8 = 0x1C;
+
+const IRQ_FHSS_CHANGE_CHANNEL: u8 = 0x02;
+const HOP_CHANNEL_MASK: u8 = 0x3F; // FhssPresentChannel
+
+impl<B, C, P, D> Fhss for Sx127x<B>
+where
+    B: Base<C, P, D>,
+{
+    type Error = sx127xError<C, P, D>;
+
+    fn fhss_service(&mut self, config: &HopConfig) -> Result<bool, Self::Error> {
+        if self.read_reg(REG_LORA_IRQ_FLAGS)? & IRQ_FHSS_CHANGE_CHANNEL == 0 {
+            return Ok(false);
+        };
+        let hop = self.read_reg(REG_HOP_CHANNEL)? & HOP_CHANNEL_MASK;
+        let freq = config.set.freq(config.index(hop as u16));
+
+        // Frf = freq * 2^19 / xtal
+        let frf = (((freq as u64) << 19) / CONFIG_RADIO.xtal_freq as u64) as u32;
+        self.write_reg(REG_FRF_MSB, (frf >> 16) as u8)?;
+        self.write_reg(REG_FRF_MSB + 1, (frf >> 8) as u8)?;
+        self.write_reg(REG_FRF_MSB + 2, frf as u8)?;
+
+        self.write_reg(REG_LORA_IRQ_FLAGS, IRQ_FHSS_CHANGE_CHANNEL)?;
+        Ok(true)
+    }
+}
+
+// Switch to FSK with CONFIG_FSK and CONFIG_FSK_CH, for feature fsk in send_spi and receive_spi.
+// Needed after setup() and again after Recovery::Reinitialised, which returns the radio to LoRa.
+pub fn set_fsk<T, C, P, D>(lora: &mut T, supervisor: &mut Supervisor)
+where
+    T: SetModem<Error = sx127xError<C, P, D>> + Reinit,
+{
+    let set = lora.set_modem(
+        &ModemConfig::Fsk(CONFIG_FSK),
+        &Channel::FskOok(CONFIG_FSK_CH),
+    );
+    if supervisor.check(lora, set).is_err() {
+        logln!("Error returned from lora.set_modem().");
+    };
+}
+
+// A radio returned by setup() with the watchdog, for the mesh and LoRaWAN code (which have no
+// watchdog of their own) so it is fed while they wait for a transmission or a receive window.
+// The mesh also needs listen before talk, as transmit() (LoRaWAN relies on the network's duty cycle).
+//   mesh.poll(&mut Fed::with_lbt(&mut lora, &mut wdog, &mut lbt), ...)
+pub struct Fed<'a, T, W> {
+    radio: &'a mut T,
+    wdog: &'a mut W,
+    lbt: Option<&'a mut ListenBeforeTalk>,
+}
+
+impl<'a, T, W: Feed> Fed<'a, T, W> {
+    pub fn new(radio: &'a mut T, wdog: &'a mut W) -> Self {
+        Fed {
+            radio,
+            wdog,
+            lbt: None,
+        }
+    }
+
+    pub fn with_lbt(radio: &'a mut T, wdog: &'a mut W, lbt: &'a mut ListenBeforeTalk) -> Self {
+        Fed {
+            radio,
+            wdog,
+            lbt: Some(lbt),
+        }
+    }
+}
+
+// The mesh (see src/mesh.rs) on any of the radios returned by setup(). A packet is dropped if the
+// channel stays busy, as in transmit(), and the mesh sends it again later (beacons) or not at all.
+impl<T, E, W> MeshRadio for Fed<'_, T, W>
+where
+    T: Transmit<Error = E> + Receive<Info = PacketInfo, Error = E> + ChannelActivity + DelayMs<u32>,
+    W: Feed,
+{
+    type Error = E;
+
+    fn send(&mut self, buf: &[u8]) -> Result<(), E> {
+        if let Some(lbt) = self.lbt.as_mut() {
+            if !lbt.wait_clear(self.radio, self.wdog) {
+                return self.radio.start_receive();
+            };
+        };
+        self.radio.start_transmit(buf)?;
+        wait_transmit(self.radio, self.wdog, 2000);
+        self.radio.start_receive()
+    }
+
+    fn receive(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Link)>, E> {
+        if !self.radio.check_receive(false)? {
+            return Ok(None);
+        };
+        let mut info = PacketInfo::default();
+        let n = self.radio.get_received(&mut info, buff)?;
+        Ok(Some((
+            n,
+            Link {
+                rssi: info.rssi,
+                snr: info.snr,
+            },
+        )))
+    }
+}
+
+// LoRaWAN (see src/lorawan.rs) on any of the radios returned by setup().
+#[cfg(feature = "lorawan")]
+impl<T, E, W> LoRaWanRadio for Fed<'_, T, W>
+where
+    T: Transmit<Error = E>
+        + Receive<Info = PacketInfo, Error = E>
+        + SetModem<Error = E>
+        + DelayMs<u32>,
+    W: Feed,
+{
+    type Error = E;
+
+    fn transmit(&mut self, channel: &LoRaChannel, frame: &[u8]) -> Result<(), E> {
+        let channel = Channel::LoRa(channel.clone());
+        self.radio
+            .set_modem(&lorawan::modem(&CONFIG_LORA, false), &channel)?;
+        self.radio.start_transmit(frame)?;
+        wait_transmit(self.radio, self.wdog, 3000);
+
+        // other LoRa devices use the private sync word
+        self.radio
+            .set_modem(&ModemConfig::LoRa(CONFIG_LORA), &channel)
+    }
+
+    fn delay(&mut self, ms: u32) -> Result<(), E> {
+        let _ = delay_fed(self.radio, self.wdog, ms);
+        Ok(())
+    }
+
+    fn receive(
+        &mut self,
+        channel: &LoRaChannel,
+        window_ms: u32,
+        buff: &mut [u8],
+    ) -> Result<Option<usize>, E> {
+        self.radio.set_modem(
+            &lorawan::modem(&CONFIG_LORA, true),
+            &Channel::LoRa(channel.clone()),
+        )?;
+        self.radio.start_receive()?;
+
+        let mut info = PacketInfo::default();
+        let mut received = None;
+        let mut waited = 0;
+        while waited < window_ms {
+            if let Ok(true) = self.radio.check_receive(false) {
+                received = Some(self.radio.get_received(&mut info, buff)?);
+                break;
+            };
+            let _ = self.radio.delay_ms(10u32);
+            self.wdog.feed();
+            waited += 10;
+        }
+
+        // other LoRa devices use the private sync word and normal IQ
+        self.radio.set_modem(
+            &ModemConfig::LoRa(CONFIG_LORA),
+            &Channel::LoRa(channel.clone()),
+        )?;
+        Ok(received)
+    }
+}
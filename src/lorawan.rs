@@ -0,0 +1,1332 @@
+//! LoRaWAN 1.0.x Class A end-device, as an alternative to the raw LoRa link.
+//!  OTAA join (join request / join accept) and ABP sessions, unconfirmed and confirmed uplinks with
+//!  AES-128 payload encryption and CMAC message integrity codes, RX1 / RX2 receive windows, and the
+//!  US915 and EU868 regional channel plans. Payloads are usually Cayenne LPP (see lpp_gps below).
+//!  The join accept sets the RX1 delay, the RX1 data rate offset and RX2 data rate (RxDelay and
+//!  DLSettings) and, with a CFList, the EU868 extra channels or the US915 channel mask.
+//!
+//!  The radio is used through the LoRaWanRadio trait, implemented in lora_spi_gps_usart.rs for the
+//!  radios setup() returns. LoRaWAN networks use the public sync word, and downlinks (including the
+//!  join accept) are sent by the network with inverted IQ, so the radio switches to the settings of
+//!  modem() below with SetModem for each frame and back to the private sync word and normal IQ after.
+
+use aes::cipher::{BlockEncrypt, NewBlockCipher};
+use aes::{Aes128, Block};
+use cmac::{Cmac, Mac, NewMac};
+
+use heapless::Vec;
+
+use radio_sx127x::device::lora::{Bandwidth, CodingRate, LoRaChannel, LoRaConfig, SpreadingFactor};
+
+use crate::fsk::ModemConfig;
+use crate::geodesy::Position;
+
+pub type Key = [u8; 16];
+pub type Eui = [u8; 8];
+
+// A frame is 13 bytes of header, port and MIC and the payload. Payloads are limited by the data
+// rate (see Region::max_payload), to 242 bytes at most and as little as 11 at US915 DR0.
+pub const MAX_FRAME: usize = 13 + 242;
+
+// Class A receive windows open this long after the end of an uplink
+pub const RECEIVE_DELAY1_MS: u32 = 1000;
+pub const JOIN_ACCEPT_DELAY1_MS: u32 = 5000;
+// time to listen in each window
+pub const RX_WINDOW_MS: u32 = 800;
+
+const MHDR_JOIN_REQUEST: u8 = 0x00;
+const MHDR_JOIN_ACCEPT: u8 = 0x20;
+const MHDR_UNCONFIRMED_UP: u8 = 0x40;
+const MHDR_UNCONFIRMED_DOWN: u8 = 0x60;
+const MHDR_CONFIRMED_UP: u8 = 0x80;
+const MHDR_CONFIRMED_DOWN: u8 = 0xA0;
+
+const FCTRL_ACK: u8 = 0x20;
+
+// ---------------------------------------------------------------------------------------------
+// Credentials, normally set with environment variables at build time (hex, as shown by the
+// network server console, most significant byte first).
+
+// Parse hex into N bytes. If reverse is true the result is little endian, as EUIs and DevAddr
+// are transmitted.
+pub fn parse_hex<const N: usize>(s: &str, reverse: bool) -> Option<[u8; N]> {
+    let s = s.as_bytes();
+    if s.len() != 2 * N {
+        return None;
+    };
+    let mut out = [0u8; N];
+    for i in 0..N {
+        let hi = (s[2 * i] as char).to_digit(16)?;
+        let lo = (s[2 * i + 1] as char).to_digit(16)?;
+        out[i] = (hi * 16 + lo) as u8;
+    }
+    if reverse {
+        out.reverse();
+    };
+    Some(out)
+}
+
+pub enum Credentials {
+    Otaa {
+        dev_eui: Eui,
+        app_eui: Eui,
+        app_key: Key,
+    },
+    Abp {
+        dev_addr: [u8; 4],
+        nwk_skey: Key,
+        app_skey: Key,
+    },
+}
+
+// Uplink channels a device may use, a bit of mask for each: US915 125kHz channels 0 to 63, or
+// EU868 channels 0 to 7, where 3 to 7 are added by a join accept CFList with their frequencies
+// in freqs (Hz).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelPlan {
+    pub mask: u64,
+    pub freqs: [u32; 5],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub dev_addr: [u8; 4], // little endian, as transmitted
+    pub nwk_skey: Key,
+    pub app_skey: Key,
+    pub fcnt_up: u32,
+    pub fcnt_down: u32,
+    pub rx1_delay_ms: u32, // RxDelay, RECEIVE_DELAY1_MS unless the join accept changes it
+    pub rx1_dr_offset: u8, // DLSettings
+    pub rx2_dr: u8,
+    pub plan: ChannelPlan,
+}
+
+impl Session {
+    // ABP sessions use the region defaults, as the network has no join accept to change them
+    pub fn abp(region: &Region, dev_addr: [u8; 4], nwk_skey: Key, app_skey: Key) -> Self {
+        Session {
+            dev_addr,
+            nwk_skey,
+            app_skey,
+            fcnt_up: 0,
+            fcnt_down: 0,
+            rx1_delay_ms: RECEIVE_DELAY1_MS,
+            rx1_dr_offset: 0,
+            rx2_dr: region.rx2_dr(),
+            plan: region.plan(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Crypto
+
+fn aes_encrypt(key: &Key, block: &mut [u8; 16]) {
+    let cipher = Aes128::new(&(*key).into());
+    let mut b: Block = (*block).into();
+    cipher.encrypt_block(&mut b);
+    block.copy_from_slice(&b);
+}
+
+// first 4 bytes of AES-CMAC over the concatenated parts
+fn mic(key: &Key, parts: &[&[u8]]) -> [u8; 4] {
+    // cannot fail, the key length is fixed
+    let mut mac = Cmac::<Aes128>::new_from_slice(key).unwrap();
+    for p in parts {
+        mac.update(p);
+    }
+    let tag = mac.finalize().into_bytes();
+    [tag[0], tag[1], tag[2], tag[3]]
+}
+
+// the B0 / A_i block used for data frame MIC and encryption. dir is 0 for uplink, 1 for downlink.
+fn block(first: u8, dir: u8, dev_addr: &[u8; 4], fcnt: u32, last: u8) -> [u8; 16] {
+    let mut b = [0u8; 16];
+    b[0] = first;
+    b[5] = dir;
+    b[6..10].copy_from_slice(dev_addr);
+    b[10..14].copy_from_slice(&fcnt.to_le_bytes());
+    b[15] = last;
+    b
+}
+
+// FRMPayload encryption (the same operation decrypts)
+fn crypt_payload(key: &Key, dir: u8, dev_addr: &[u8; 4], fcnt: u32, payload: &mut [u8]) {
+    for (i, chunk) in payload.chunks_mut(16).enumerate() {
+        let mut s = block(0x01, dir, dev_addr, fcnt, (i + 1) as u8);
+        aes_encrypt(key, &mut s);
+        for (b, k) in chunk.iter_mut().zip(s.iter()) {
+            *b ^= k;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Frames
+
+pub fn join_request(dev_eui: &Eui, app_eui: &Eui, dev_nonce: u16, app_key: &Key) -> [u8; 23] {
+    let mut f = [0u8; 23];
+    f[0] = MHDR_JOIN_REQUEST;
+    f[1..9].copy_from_slice(app_eui);
+    f[9..17].copy_from_slice(dev_eui);
+    f[17..19].copy_from_slice(&dev_nonce.to_le_bytes());
+    let m = mic(app_key, &[&f[..19]]);
+    f[19..].copy_from_slice(&m);
+    f
+}
+
+// Decrypt and check a join accept, and derive the session keys. None if it is not a valid
+// join accept for this join request. Settings the region does not have are ignored.
+pub fn join_accept(
+    frame: &[u8],
+    app_key: &Key,
+    dev_nonce: u16,
+    region: &Region,
+) -> Option<Session> {
+    // 17 bytes, or 33 with a CFList
+    if (frame.len() != 17 && frame.len() != 33) || frame[0] != MHDR_JOIN_ACCEPT {
+        return None;
+    };
+
+    // the network encrypts the join accept with AES decrypt, so the device uses AES encrypt
+    let mut plain = [0u8; 32];
+    let n = frame.len() - 1;
+    plain[..n].copy_from_slice(&frame[1..]);
+    for chunk in plain[..n].chunks_mut(16) {
+        let mut b = [0u8; 16];
+        b.copy_from_slice(chunk);
+        aes_encrypt(app_key, &mut b);
+        chunk.copy_from_slice(&b);
+    }
+
+    let (body, m) = plain[..n].split_at(n - 4);
+    if mic(app_key, &[&frame[..1], body]) != m {
+        return None;
+    };
+
+    // body: AppNonce(3) NetID(3) DevAddr(4) DLSettings(1) RxDelay(1) [CFList(16)]
+    let mut dev_addr = [0u8; 4];
+    dev_addr.copy_from_slice(&body[6..10]);
+
+    let derive = |kind: u8| {
+        let mut k = [0u8; 16];
+        k[0] = kind;
+        k[1..7].copy_from_slice(&body[0..6]); // AppNonce, NetID
+        k[7..9].copy_from_slice(&dev_nonce.to_le_bytes());
+        aes_encrypt(app_key, &mut k);
+        k
+    };
+
+    let mut session = Session::abp(region, dev_addr, derive(0x01), derive(0x02));
+
+    // DLSettings: RX1DROffset in bits 6:4, RX2DataRate in bits 3:0
+    session.rx1_dr_offset = (body[10] >> 4) & 0x07;
+    let rx2_dr = body[10] & 0x0F;
+    if region.data_rate(rx2_dr).is_some() {
+        session.rx2_dr = rx2_dr;
+    };
+    // RxDelay in seconds, 0 also meaning 1
+    session.rx1_delay_ms = (body[11] & 0x0F).max(1) as u32 * 1000;
+    if let Some(cflist) = body.get(12..28) {
+        region.apply_cflist(&mut session.plan, cflist);
+    };
+
+    Some(session)
+}
+
+// Build an uplink data frame and advance the frame counter. Returns false if it does not fit.
+pub fn uplink(
+    session: &mut Session,
+    port: u8,
+    payload: &[u8],
+    confirmed: bool,
+    ack: bool,
+    out: &mut Vec<u8, MAX_FRAME>,
+) -> bool {
+    out.clear();
+    if port == 0 || 13 + payload.len() > MAX_FRAME {
+        return false; // port 0 is for MAC commands, not supported
+    };
+
+    let fcnt = session.fcnt_up;
+    let mhdr = if confirmed {
+        MHDR_CONFIRMED_UP
+    } else {
+        MHDR_UNCONFIRMED_UP
+    };
+    let fctrl = if ack { FCTRL_ACK } else { 0 };
+    let f = (fcnt as u16).to_le_bytes();
+
+    // cannot fail, length checked above
+    out.push(mhdr).unwrap();
+    out.extend_from_slice(&session.dev_addr).unwrap();
+    out.extend_from_slice(&[fctrl, f[0], f[1], port]).unwrap();
+    let start = out.len();
+    out.extend_from_slice(payload).unwrap();
+    crypt_payload(
+        &session.app_skey,
+        0,
+        &session.dev_addr,
+        fcnt,
+        &mut out[start..],
+    );
+
+    let b0 = block(0x49, 0, &session.dev_addr, fcnt, out.len() as u8);
+    let m = mic(&session.nwk_skey, &[&b0, &out[..]]);
+    out.extend_from_slice(&m).unwrap();
+
+    session.fcnt_up = session.fcnt_up.wrapping_add(1);
+    true
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Downlink {
+    pub confirmed: bool, // the network wants an ack in the next uplink
+    pub ack: bool,       // the network acknowledges our confirmed uplink
+    pub port: Option<u8>,
+    pub payload: Vec<u8, MAX_FRAME>,
+}
+
+// Check and decrypt a downlink data frame for this session. None if it is not for this device,
+// fails the MIC, or is a replay.
+pub fn downlink(session: &mut Session, frame: &[u8]) -> Option<Downlink> {
+    if frame.len() < 12 {
+        return None;
+    };
+    let confirmed = match frame[0] {
+        MHDR_UNCONFIRMED_DOWN => false,
+        MHDR_CONFIRMED_DOWN => true,
+        _ => return None,
+    };
+    if frame[1..5] != session.dev_addr {
+        return None;
+    };
+
+    let fctrl = frame[5];
+    let fopts_len = (fctrl & 0x0F) as usize;
+
+    // only the low 16 bits of the counter are sent, take the nearest value not before the last one
+    let low = u16::from_le_bytes([frame[6], frame[7]]) as u32;
+    let mut fcnt = (session.fcnt_down & 0xFFFF_0000) | low;
+    if fcnt < session.fcnt_down {
+        fcnt = fcnt.wrapping_add(0x1_0000);
+    };
+
+    let (msg, m) = frame.split_at(frame.len() - 4);
+    let b0 = block(0x49, 1, &session.dev_addr, fcnt, msg.len() as u8);
+    if mic(&session.nwk_skey, &[&b0, msg]) != m {
+        return None;
+    };
+    session.fcnt_down = fcnt.wrapping_add(1);
+
+    let mut d = Downlink {
+        confirmed,
+        ack: fctrl & FCTRL_ACK != 0,
+        port: None,
+        payload: Vec::new(),
+    };
+    let rest = msg.get(8 + fopts_len..)?;
+    if let Some((port, payload)) = rest.split_first() {
+        d.port = Some(*port);
+        d.payload.extend_from_slice(payload).ok()?;
+        let key = if *port == 0 {
+            &session.nwk_skey
+        } else {
+            &session.app_skey
+        };
+        crypt_payload(key, 1, &session.dev_addr, fcnt, &mut d.payload);
+    };
+    Some(d)
+}
+
+// ---------------------------------------------------------------------------------------------
+// Cayenne LPP
+
+pub const LPP_ANALOG_INPUT: u8 = 2;
+pub const LPP_GPS: u8 = 136;
+
+// GPS location: lat and lon in 0.0001 degree, altitude in 0.01 m, each 3 bytes signed big endian
+pub fn lpp_gps<const N: usize>(out: &mut Vec<u8, N>, channel: u8, p: Position, alt_m: f64) -> bool {
+    let v = |x: f64| {
+        let b = (x as i32).to_be_bytes();
+        [b[1], b[2], b[3]]
+    };
+    out.extend_from_slice(&[channel, LPP_GPS]).is_ok()
+        && out.extend_from_slice(&v(p.lat * 10_000.0)).is_ok()
+        && out.extend_from_slice(&v(p.lon * 10_000.0)).is_ok()
+        && out.extend_from_slice(&v(alt_m * 100.0)).is_ok()
+}
+
+// analog value in 0.01 units, eg battery volts
+pub fn lpp_analog<const N: usize>(out: &mut Vec<u8, N>, channel: u8, value: f64) -> bool {
+    let b = ((value * 100.0) as i16).to_be_bytes();
+    out.extend_from_slice(&[channel, LPP_ANALOG_INPUT, b[0], b[1]])
+        .is_ok()
+}
+
+// ---------------------------------------------------------------------------------------------
+// Regional channel plans
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    // 8 channel sub-band 1 to 8 (TTN and most US networks use 2)
+    Us915 { subband: u8 },
+    Eu868,
+}
+
+impl Region {
+    // data rate of uplinks, DR3 (SF7 BW125) in US915 and DR5 (SF7 BW125) in EU868
+    pub fn uplink_dr(&self) -> u8 {
+        match self {
+            Region::Us915 { .. } => 3,
+            Region::Eu868 => 5,
+        }
+    }
+
+    // spreading factor and bandwidth of data rate dr, None if the region does not have it
+    pub fn data_rate(&self, dr: u8) -> Option<(SpreadingFactor, Bandwidth)> {
+        use Bandwidth::*;
+        use SpreadingFactor::*;
+        let r = match (self, dr) {
+            (Region::Us915 { .. }, 0) => (Sf10, Bw125kHz),
+            (Region::Us915 { .. }, 1) => (Sf9, Bw125kHz),
+            (Region::Us915 { .. }, 2) => (Sf8, Bw125kHz),
+            (Region::Us915 { .. }, 3) => (Sf7, Bw125kHz),
+            (Region::Us915 { .. }, 4) => (Sf8, Bw500kHz),
+            (Region::Us915 { .. }, 8) => (Sf12, Bw500kHz),
+            (Region::Us915 { .. }, 9) => (Sf11, Bw500kHz),
+            (Region::Us915 { .. }, 10) => (Sf10, Bw500kHz),
+            (Region::Us915 { .. }, 11) => (Sf9, Bw500kHz),
+            (Region::Us915 { .. }, 12) => (Sf8, Bw500kHz),
+            (Region::Us915 { .. }, 13) => (Sf7, Bw500kHz),
+            (Region::Eu868, 0) => (Sf12, Bw125kHz),
+            (Region::Eu868, 1) => (Sf11, Bw125kHz),
+            (Region::Eu868, 2) => (Sf10, Bw125kHz),
+            (Region::Eu868, 3) => (Sf9, Bw125kHz),
+            (Region::Eu868, 4) => (Sf8, Bw125kHz),
+            (Region::Eu868, 5) => (Sf7, Bw125kHz),
+            (Region::Eu868, 6) => (Sf7, Bw250kHz),
+            _ => return None,
+        };
+        Some(r)
+    }
+
+    // maximum payload at data rate dr (N in the regional parameters, with no MAC commands in
+    // FOpts), None if the region does not have it
+    pub fn max_payload(&self, dr: u8) -> Option<usize> {
+        let n = match (self, dr) {
+            (Region::Us915 { .. }, 0) => 11,
+            (Region::Us915 { .. }, 1) | (Region::Us915 { .. }, 8) => 53,
+            (Region::Us915 { .. }, 2) => 125,
+            (Region::Us915 { .. }, 9) => 129,
+            (Region::Us915 { .. }, 3..=4) | (Region::Us915 { .. }, 10..=13) => 242,
+            (Region::Eu868, 0..=2) => 51,
+            (Region::Eu868, 3) => 115,
+            (Region::Eu868, 4..=6) => 222,
+            _ => return None,
+        };
+        Some(n)
+    }
+
+    // channels before a join accept changes them, the sub-band in US915 and the 3 default EU868
+    // channels
+    pub fn plan(&self) -> ChannelPlan {
+        let mask = match self {
+            Region::Us915 { subband } => 0xFF << ((*subband as u32 - 1) * 8),
+            Region::Eu868 => 0b111,
+        };
+        ChannelPlan {
+            mask,
+            freqs: [0; 5],
+        }
+    }
+
+    // default RX2 data rate, DR8 (SF12 BW500) in US915 and DR0 (SF12 BW125) in EU868
+    pub fn rx2_dr(&self) -> u8 {
+        match self {
+            Region::Us915 { .. } => 8,
+            Region::Eu868 => 0,
+        }
+    }
+
+    // Apply a join accept CFList: CFListType 0 (EU868) adds channels 3 to 7 with the frequencies
+    // given (0 for none), CFListType 1 (US915) replaces the mask of 125kHz channels. The 500kHz
+    // channel mask is ignored, as uplinks are at 125kHz. A list for another region is ignored.
+    pub fn apply_cflist(&self, plan: &mut ChannelPlan, cflist: &[u8]) {
+        if cflist.len() != 16 {
+            return;
+        };
+        match (self, cflist[15]) {
+            (Region::Eu868, 0) => {
+                for (i, f) in cflist[..15].chunks(3).enumerate() {
+                    let freq = u32::from_le_bytes([f[0], f[1], f[2], 0]) * 100;
+                    plan.freqs[i] = freq;
+                    if freq == 0 {
+                        plan.mask &= !(1 << (3 + i));
+                    } else {
+                        plan.mask |= 1 << (3 + i);
+                    };
+                }
+            }
+            (Region::Us915 { .. }, 1) => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(&cflist[..8]);
+                let mask = u64::from_le_bytes(b);
+                // a mask without channels would leave nothing to send on
+                if mask != 0 {
+                    plan.mask = mask;
+                };
+            }
+            _ => (),
+        };
+    }
+
+    // uplink channel n of plan, at uplink_dr()
+    pub fn uplink(&self, plan: &ChannelPlan, n: u8) -> LoRaChannel {
+        let freq = match self {
+            Region::Us915 { .. } => 902_300_000 + 200_000 * (n % 64) as u32,
+            Region::Eu868 => match n % 8 {
+                n if n < 3 => 868_100_000 + 200_000 * n as u32,
+                n => plan.freqs[n as usize - 3],
+            },
+        };
+        self.lora(freq, self.uplink_dr())
+    }
+
+    // RX1 after an uplink on channel n, at the uplink data rate less rx1_dr_offset. In US915 the
+    // 125kHz uplink data rates DR0 to DR3 map to the 500kHz DR10 to DR13, so DR3 => DR13 (SF7 BW500).
+    pub fn rx1(&self, plan: &ChannelPlan, n: u8, rx1_dr_offset: u8) -> LoRaChannel {
+        match self {
+            Region::Us915 { .. } => {
+                let dr = (10 + self.uplink_dr() as i8 - rx1_dr_offset as i8).clamp(8, 13) as u8;
+                self.lora(923_300_000 + 600_000 * (n % 8) as u32, dr)
+            }
+            Region::Eu868 => {
+                let freq = self.uplink(plan, n).freq;
+                self.lora(freq, self.uplink_dr().saturating_sub(rx1_dr_offset))
+            }
+        }
+    }
+
+    pub fn rx2(&self, dr: u8) -> LoRaChannel {
+        let freq = match self {
+            Region::Us915 { .. } => 923_300_000,
+            Region::Eu868 => 869_525_000,
+        };
+        self.lora(freq, dr)
+    }
+
+    // data rates not in the region are not accepted from the network, so this uses the RX2
+    // default for them only to have a channel
+    fn lora(&self, freq: u32, dr: u8) -> LoRaChannel {
+        let (sf, bw) = self
+            .data_rate(dr)
+            .or_else(|| self.data_rate(self.rx2_dr()))
+            .unwrap(); // cannot fail, rx2_dr() is in the table
+        LoRaChannel {
+            freq,
+            bw,
+            sf,
+            cr: CodingRate::Cr4_5,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Class A device
+
+// LoRa settings of LoRaWAN frames, lora (eg CONFIG_LORA) with the public sync word, and inverted
+// IQ for downlinks
+pub fn modem(lora: &LoRaConfig, downlink: bool) -> ModemConfig {
+    ModemConfig::LoRaWan(LoRaConfig {
+        invert_iq: downlink,
+        ..*lora
+    })
+}
+
+// What a Class A device needs from the radio. Both directions use the settings of modem().
+pub trait LoRaWanRadio {
+    type Error;
+    // Transmit frame on channel and wait until it has been sent.
+    fn transmit(&mut self, channel: &LoRaChannel, frame: &[u8]) -> Result<(), Self::Error>;
+    // Wait ms, eg until a receive window opens.
+    fn delay(&mut self, ms: u32) -> Result<(), Self::Error>;
+    // Listen on channel with inverted IQ, as the network sends, for window_ms. Returns the length
+    // of the frame received into buff, if any. Timeouts and CRC errors are nothing received.
+    fn receive(
+        &mut self,
+        channel: &LoRaChannel,
+        window_ms: u32,
+        buff: &mut [u8],
+    ) -> Result<Option<usize>, Self::Error>;
+}
+
+pub struct Device {
+    region: Region,
+    credentials: Credentials,
+    session: Option<Session>,
+    channel: u8,       // last uplink channel, see next_channel()
+    ack_pending: bool, // acknowledge a confirmed downlink in the next uplink
+}
+
+impl Device {
+    pub fn new(region: Region, credentials: Credentials) -> Self {
+        let session = match credentials {
+            Credentials::Abp {
+                dev_addr,
+                nwk_skey,
+                app_skey,
+            } => Some(Session::abp(&region, dev_addr, nwk_skey, app_skey)),
+            Credentials::Otaa { .. } => None,
+        };
+        Device {
+            region,
+            credentials,
+            session,
+            channel: 63, // so the first uplink is on the lowest channel
+            ack_pending: false,
+        }
+    }
+
+    pub fn is_joined(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    // Send a join request (OTAA) and listen for the join accept. dev_nonce must not be reused.
+    // Returns Ok(true) once joined. ABP devices are always joined.
+    pub fn join<R: LoRaWanRadio>(
+        &mut self,
+        radio: &mut R,
+        dev_nonce: u16,
+    ) -> Result<bool, R::Error> {
+        let (dev_eui, app_eui, app_key) = match self.credentials {
+            Credentials::Otaa {
+                dev_eui,
+                app_eui,
+                app_key,
+            } => (dev_eui, app_eui, app_key),
+            Credentials::Abp { .. } => return Ok(true),
+        };
+
+        // the join accept comes on the region's default windows
+        let defaults = Session::abp(&self.region, [0; 4], [0; 16], [0; 16]);
+        let windows = Session {
+            rx1_delay_ms: JOIN_ACCEPT_DELAY1_MS,
+            ..defaults
+        };
+
+        let request = join_request(&dev_eui, &app_eui, dev_nonce, &app_key);
+        let mut buff = [0u8; 256];
+        let ch = self.next_channel(&windows.plan);
+        if let Some(n) = self.exchange(radio, &windows, ch, &request, &mut buff)? {
+            self.session = join_accept(&buff[..n], &app_key, dev_nonce, &self.region);
+        };
+        Ok(self.is_joined())
+    }
+
+    // Send an uplink on port and open the receive windows. Returns any downlink heard.
+    // For a confirmed uplink the downlink has ack set if the network received it. A payload too
+    // long for the uplink data rate is not sent.
+    pub fn send<R: LoRaWanRadio>(
+        &mut self,
+        radio: &mut R,
+        port: u8,
+        payload: &[u8],
+        confirmed: bool,
+    ) -> Result<Option<Downlink>, R::Error> {
+        let mut frame: Vec<u8, MAX_FRAME> = Vec::new();
+        let max = self.region.max_payload(self.region.uplink_dr());
+        if payload.len() > max.unwrap_or(0) {
+            return Ok(None);
+        };
+        let ack = self.ack_pending;
+        let mut session = match self.session.take() {
+            Some(s) => s,
+            None => return Ok(None), // not joined
+        };
+        if !uplink(&mut session, port, payload, confirmed, ack, &mut frame) {
+            self.session = Some(session);
+            return Ok(None);
+        };
+        self.ack_pending = false;
+
+        let mut buff = [0u8; 256];
+        let ch = self.next_channel(&session.plan);
+        let heard = self.exchange(radio, &session, ch, &frame, &mut buff);
+
+        let d = match heard {
+            Ok(Some(n)) => downlink(&mut session, &buff[..n]),
+            _ => None,
+        };
+        self.session = Some(session);
+        if let Some(d) = &d {
+            self.ack_pending = d.confirmed;
+        };
+        heard.map(|_| d)
+    }
+
+    // the next channel of plan after the last one used (plan.mask is never 0)
+    fn next_channel(&mut self, plan: &ChannelPlan) -> u8 {
+        let mut ch = self.channel;
+        loop {
+            ch = (ch + 1) % 64;
+            if plan.mask & (1 << ch) != 0 {
+                break;
+            };
+        }
+        self.channel = ch;
+        ch
+    }
+
+    // Transmit on uplink channel ch, then listen in RX1 (rx1_delay_ms after the end of the uplink)
+    // and RX2 (one second later), with the settings of session. Returns the length of the frame
+    // received in buff, if any.
+    fn exchange<R: LoRaWanRadio>(
+        &self,
+        radio: &mut R,
+        session: &Session,
+        ch: u8,
+        frame: &[u8],
+        buff: &mut [u8],
+    ) -> Result<Option<usize>, R::Error> {
+        let region = &self.region;
+        radio.transmit(&region.uplink(&session.plan, ch), frame)?;
+
+        // open the window a little early, the radio needs the preamble
+        radio.delay(session.rx1_delay_ms - RX_WINDOW_MS / 4)?;
+        let rx1 = region.rx1(&session.plan, ch, session.rx1_dr_offset);
+        if let Some(n) = radio.receive(&rx1, RX_WINDOW_MS, buff)? {
+            return Ok(Some(n));
+        };
+
+        // RX2 opens one second after RX1, which has used RX_WINDOW_MS of it
+        radio.delay(1000 - RX_WINDOW_MS)?;
+        radio.receive(&region.rx2(session.rx2_dr), RX_WINDOW_MS, buff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsk::{lora_sync_word, sx127x_lora_sync_word, LORA_SYNC_PUBLIC};
+    use aes::cipher::BlockDecrypt;
+    use Bandwidth::*;
+    use SpreadingFactor::*;
+
+    const APP_KEY: Key = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE,
+        0xFF,
+    ];
+    const DEV_EUI: Eui = [1, 2, 3, 4, 5, 6, 7, 8];
+    const APP_EUI: Eui = [8, 7, 6, 5, 4, 3, 2, 1];
+    const DEV_ADDR: [u8; 4] = [0x78, 0x56, 0x34, 0x12];
+    const APP_NONCE: [u8; 3] = [0x01, 0x02, 0x03];
+    const NET_ID: [u8; 3] = [0x13, 0x00, 0x00];
+
+    fn otaa() -> Credentials {
+        Credentials::Otaa {
+            dev_eui: DEV_EUI,
+            app_eui: APP_EUI,
+            app_key: APP_KEY,
+        }
+    }
+
+    fn sf(n: u8) -> SpreadingFactor {
+        match n {
+            7 => Sf7,
+            8 => Sf8,
+            9 => Sf9,
+            10 => Sf10,
+            11 => Sf11,
+            _ => Sf12,
+        }
+    }
+
+    fn is(ch: &LoRaChannel, freq: u32, sf: SpreadingFactor, bw: Bandwidth) -> bool {
+        ch.freq == freq && ch.sf == sf && ch.bw == bw && ch.cr == CodingRate::Cr4_5
+    }
+
+    // A stand-in network server. It answers a join request with a join accept, and each data
+    // uplink with a downlink when it has one to send (its own or an ack). The windows are worked
+    // out from its own tables, not those of Region, and downlinks go out at the RX1 delay it gave
+    // the device in the join accept (or one second later in RX2, with use_rx2).
+    struct StandIn {
+        region: Region,
+        dl_settings: u8,
+        rx_delay: u8,
+        cflist: Option<[u8; 16]>,
+        use_rx2: bool,
+        session: Option<Session>,
+        queued: Option<(u8, &'static [u8], bool)>, // port, payload, confirmed
+        received: Vec<(u8, Vec<u8, MAX_FRAME>, bool, bool), 8>, // port, payload, confirmed, ack
+    }
+
+    // a downlink on the air: when, frequency, spreading factor, bandwidth, frame
+    type Air = (u32, u32, SpreadingFactor, Bandwidth, Vec<u8, MAX_FRAME>);
+
+    impl StandIn {
+        fn new(region: Region) -> Self {
+            StandIn {
+                region,
+                dl_settings: match region {
+                    Region::Us915 { .. } => 8,
+                    Region::Eu868 => 0,
+                },
+                rx_delay: 1,
+                cflist: None,
+                use_rx2: false,
+                session: None,
+                queued: None,
+                received: Vec::new(),
+            }
+        }
+
+        fn rx1(&self, freq: u32, offset: u8) -> (u32, SpreadingFactor, Bandwidth) {
+            match self.region {
+                // uplinks at DR3, RX1 at DR13 (SF7 BW500) less the offset
+                Region::Us915 { .. } => {
+                    let ch = (freq - 902_300_000) / 200_000;
+                    (923_300_000 + 600_000 * (ch % 8), sf(7 + offset), Bw500kHz)
+                }
+                // uplinks at DR5, RX1 on the uplink frequency at DR5 less the offset
+                Region::Eu868 => (freq, sf(7 + offset), Bw125kHz),
+            }
+        }
+
+        fn rx2(&self, dr: u8) -> (u32, SpreadingFactor, Bandwidth) {
+            match self.region {
+                Region::Us915 { .. } => (923_300_000, sf(12 - (dr - 8)), Bw500kHz),
+                Region::Eu868 => (869_525_000, sf(12 - dr), Bw125kHz),
+            }
+        }
+
+        // the downlink for an uplink on freq that ended at end_ms, if any
+        fn uplink(&mut self, frame: &[u8], freq: u32, end_ms: u32) -> Option<Air> {
+            let (reply, rx1_delay_ms, offset, rx2_dr) = if frame[0] == MHDR_JOIN_REQUEST {
+                let reply = self.join(frame)?;
+                (
+                    reply,
+                    JOIN_ACCEPT_DELAY1_MS,
+                    0,
+                    StandIn::new(self.region).dl_settings,
+                )
+            } else {
+                let reply = self.data(frame)?;
+                let delay = self.rx_delay.max(1) as u32 * 1000;
+                (reply, delay, self.dl_settings >> 4, self.dl_settings & 0x0F)
+            };
+            let at = end_ms + rx1_delay_ms;
+            let (at, (f, s, b)) = if self.use_rx2 {
+                (at + 1000, self.rx2(rx2_dr))
+            } else {
+                (at, self.rx1(freq, offset))
+            };
+            Some((at, f, s, b, reply))
+        }
+
+        fn join(&mut self, frame: &[u8]) -> Option<Vec<u8, MAX_FRAME>> {
+            if frame.len() != 23 || mic(&APP_KEY, &[&frame[..19]]) != frame[19..] {
+                return None;
+            };
+            let dev_nonce = u16::from_le_bytes([frame[17], frame[18]]);
+
+            let mut body: Vec<u8, 32> = Vec::new();
+            body.extend_from_slice(&APP_NONCE).unwrap();
+            body.extend_from_slice(&NET_ID).unwrap();
+            body.extend_from_slice(&DEV_ADDR).unwrap();
+            body.push(self.dl_settings).unwrap();
+            body.push(self.rx_delay).unwrap();
+            if let Some(cflist) = self.cflist {
+                body.extend_from_slice(&cflist).unwrap();
+            };
+            let m = mic(&APP_KEY, &[&[MHDR_JOIN_ACCEPT], &body]);
+            body.extend_from_slice(&m).unwrap();
+
+            // encrypted with AES decrypt, so the device only needs AES encrypt
+            let cipher = Aes128::new(&APP_KEY.into());
+            for chunk in body.chunks_mut(16) {
+                let mut b = Block::default();
+                b.copy_from_slice(chunk);
+                cipher.decrypt_block(&mut b);
+                chunk.copy_from_slice(&b);
+            }
+
+            let derive = |kind: u8| {
+                let mut k = [0u8; 16];
+                k[0] = kind;
+                k[1..4].copy_from_slice(&APP_NONCE);
+                k[4..7].copy_from_slice(&NET_ID);
+                k[7..9].copy_from_slice(&dev_nonce.to_le_bytes());
+                aes_encrypt(&APP_KEY, &mut k);
+                k
+            };
+            self.session = Some(Session::abp(
+                &self.region,
+                DEV_ADDR,
+                derive(0x01),
+                derive(0x02),
+            ));
+
+            let mut out = Vec::new();
+            out.push(MHDR_JOIN_ACCEPT).unwrap();
+            out.extend_from_slice(&body).unwrap();
+            Some(out)
+        }
+
+        fn data(&mut self, frame: &[u8]) -> Option<Vec<u8, MAX_FRAME>> {
+            let session = self.session.as_mut()?;
+            let confirmed = frame[0] == MHDR_CONFIRMED_UP;
+            assert_eq!(&frame[1..5], &session.dev_addr);
+            let fcnt = u16::from_le_bytes([frame[6], frame[7]]) as u32;
+            let (msg, m) = frame.split_at(frame.len() - 4);
+            let b0 = block(0x49, 0, &session.dev_addr, fcnt, msg.len() as u8);
+            assert_eq!(mic(&session.nwk_skey, &[&b0, msg]), m);
+
+            let mut payload = Vec::new();
+            payload.extend_from_slice(&msg[9..]).unwrap();
+            crypt_payload(&session.app_skey, 0, &session.dev_addr, fcnt, &mut payload);
+            let ack = frame[5] & FCTRL_ACK != 0;
+            self.received
+                .push((msg[8], payload, confirmed, ack))
+                .unwrap();
+
+            let (port, payload, confirmed_down) = match self.queued.take() {
+                Some(q) => q,
+                None if confirmed => (1, &b""[..], false),
+                None => return None,
+            };
+            Some(downlink_frame(
+                session,
+                confirmed_down,
+                confirmed,
+                port,
+                payload,
+            ))
+        }
+    }
+
+    // the network side of uplink()
+    fn downlink_frame(
+        session: &mut Session,
+        confirmed: bool,
+        ack: bool,
+        port: u8,
+        payload: &[u8],
+    ) -> Vec<u8, MAX_FRAME> {
+        let fcnt = session.fcnt_down;
+        let mhdr = if confirmed {
+            MHDR_CONFIRMED_DOWN
+        } else {
+            MHDR_UNCONFIRMED_DOWN
+        };
+        let f = (fcnt as u16).to_le_bytes();
+        let mut out: Vec<u8, MAX_FRAME> = Vec::new();
+        out.push(mhdr).unwrap();
+        out.extend_from_slice(&session.dev_addr).unwrap();
+        let fctrl = if ack { FCTRL_ACK } else { 0 };
+        out.extend_from_slice(&[fctrl, f[0], f[1], port]).unwrap();
+        let start = out.len();
+        out.extend_from_slice(payload).unwrap();
+        crypt_payload(
+            &session.app_skey,
+            1,
+            &session.dev_addr,
+            fcnt,
+            &mut out[start..],
+        );
+        let b0 = block(0x49, 1, &session.dev_addr, fcnt, out.len() as u8);
+        let m = mic(&session.nwk_skey, &[&b0, &out[..]]);
+        out.extend_from_slice(&m).unwrap();
+        session.fcnt_down += 1;
+        out
+    }
+
+    // The radio between the device and the stand-in. Time passes in delays, transmissions and
+    // receive windows, and a downlink is only heard in a window open at its time on its channel.
+    struct Bridge {
+        server: StandIn,
+        now_ms: u32,
+        air: Option<Air>,
+        uplinks: Vec<u32, 16>, // frequencies
+    }
+
+    impl Bridge {
+        fn new(server: StandIn) -> Self {
+            Bridge {
+                server,
+                now_ms: 0,
+                air: None,
+                uplinks: Vec::new(),
+            }
+        }
+    }
+
+    impl LoRaWanRadio for Bridge {
+        type Error = ();
+
+        fn transmit(&mut self, channel: &LoRaChannel, frame: &[u8]) -> Result<(), ()> {
+            assert!(is(channel, channel.freq, Sf7, Bw125kHz));
+            self.uplinks.push(channel.freq).unwrap();
+            self.now_ms += 60; // time on air
+            self.air = self.server.uplink(frame, channel.freq, self.now_ms);
+            Ok(())
+        }
+
+        fn delay(&mut self, ms: u32) -> Result<(), ()> {
+            self.now_ms += ms;
+            Ok(())
+        }
+
+        fn receive(
+            &mut self,
+            channel: &LoRaChannel,
+            window_ms: u32,
+            buff: &mut [u8],
+        ) -> Result<Option<usize>, ()> {
+            let open = self.now_ms;
+            self.now_ms += window_ms;
+            match self.air.take() {
+                Some((at, freq, sf, bw, frame))
+                    if (open..open + window_ms).contains(&at) && is(channel, freq, sf, bw) =>
+                {
+                    buff[..frame.len()].copy_from_slice(&frame);
+                    Ok(Some(frame.len()))
+                }
+                air => {
+                    self.air = air;
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cmac_rfc4493() {
+        let key = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let m = [
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93,
+            0x17, 0x2A,
+        ];
+        assert_eq!(mic(&key, &[]), [0xBB, 0x1D, 0x69, 0x29]);
+        assert_eq!(mic(&key, &[&m[..5], &m[5..]]), [0x07, 0x0A, 0x16, 0xB4]);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex::<2>("0aFF", false), Some([0x0A, 0xFF]));
+        assert_eq!(parse_hex::<2>("0aFF", true), Some([0xFF, 0x0A]));
+        assert_eq!(parse_hex::<2>("0aF", false), None);
+        assert_eq!(parse_hex::<2>("0aFG", false), None);
+    }
+
+    #[test]
+    fn cayenne_lpp() {
+        // the example in the Cayenne LPP documentation
+        let mut out: Vec<u8, 16> = Vec::new();
+        assert!(lpp_gps(&mut out, 1, Position::new(42.3519, -87.9094), 10.0));
+        assert_eq!(
+            &out[..],
+            &[0x01, 0x88, 0x06, 0x76, 0x5F, 0xF2, 0x96, 0x0A, 0x00, 0x03, 0xE8]
+        );
+        out.clear();
+        assert!(lpp_analog(&mut out, 2, 3.7));
+        assert_eq!(&out[..], &[0x02, LPP_ANALOG_INPUT, 0x01, 0x72]);
+    }
+
+    #[test]
+    fn us915_windows() {
+        let region = Region::Us915 { subband: 2 };
+        let plan = region.plan();
+        assert_eq!(plan.mask, 0xFF00);
+        assert!(is(&region.uplink(&plan, 8), 903_900_000, Sf7, Bw125kHz));
+        assert!(is(&region.uplink(&plan, 15), 905_300_000, Sf7, Bw125kHz));
+        // DR3 uplinks, RX1 at DR13
+        assert!(is(&region.rx1(&plan, 8, 0), 923_300_000, Sf7, Bw500kHz));
+        assert!(is(&region.rx1(&plan, 15, 0), 927_500_000, Sf7, Bw500kHz));
+        assert!(is(&region.rx1(&plan, 8, 1), 923_300_000, Sf8, Bw500kHz));
+        assert!(is(&region.rx1(&plan, 8, 3), 923_300_000, Sf10, Bw500kHz));
+        assert!(is(
+            &region.rx2(region.rx2_dr()),
+            923_300_000,
+            Sf12,
+            Bw500kHz
+        ));
+        assert!(region.data_rate(5).is_none());
+    }
+
+    #[test]
+    fn eu868_windows() {
+        let region = Region::Eu868;
+        let mut plan = region.plan();
+        assert!(is(&region.uplink(&plan, 2), 868_500_000, Sf7, Bw125kHz));
+        assert!(is(&region.rx1(&plan, 2, 0), 868_500_000, Sf7, Bw125kHz));
+        assert!(is(&region.rx1(&plan, 2, 2), 868_500_000, Sf9, Bw125kHz));
+        assert!(is(
+            &region.rx2(region.rx2_dr()),
+            869_525_000,
+            Sf12,
+            Bw125kHz
+        ));
+
+        // 867.1 and 867.3 MHz, in 100Hz steps
+        let mut cflist = [0u8; 16];
+        cflist[..3].copy_from_slice(&8_671_000u32.to_le_bytes()[..3]);
+        cflist[3..6].copy_from_slice(&8_673_000u32.to_le_bytes()[..3]);
+        region.apply_cflist(&mut plan, &cflist);
+        assert_eq!(plan.mask, 0b11111);
+        assert!(is(&region.uplink(&plan, 4), 867_300_000, Sf7, Bw125kHz));
+
+        // a US915 channel mask is ignored
+        cflist[15] = 1;
+        region.apply_cflist(&mut plan, &cflist);
+        assert_eq!(plan.mask, 0b11111);
+    }
+
+    #[test]
+    fn otaa_join_and_uplinks_us915() {
+        let region = Region::Us915 { subband: 2 };
+        let mut server = StandIn::new(region);
+        server.dl_settings = 0x10 | 8; // RX1 offset 1, RX2 at DR8
+        server.rx_delay = 3;
+        // only channels 8 to 10
+        let mut cflist = [0u8; 16];
+        cflist[1] = 0x07;
+        cflist[15] = 1;
+        server.cflist = Some(cflist);
+        let mut radio = Bridge::new(server);
+
+        let mut device = Device::new(region, otaa());
+        assert!(!device.is_joined());
+        assert_eq!(device.send(&mut radio, 1, b"x", false), Ok(None));
+        assert_eq!(device.join(&mut radio, 0x1234), Ok(true));
+
+        let session = device.session().unwrap();
+        let network = radio.server.session.as_ref().unwrap();
+        assert_eq!(
+            (session.nwk_skey, session.app_skey),
+            (network.nwk_skey, network.app_skey)
+        );
+        assert_eq!(session.dev_addr, DEV_ADDR);
+        assert_eq!(session.rx1_delay_ms, 3000);
+        assert_eq!((session.rx1_dr_offset, session.rx2_dr), (1, 8));
+        assert_eq!(session.plan.mask, 0x0700);
+
+        for n in 0..4 {
+            assert_eq!(device.send(&mut radio, 1, &[n], false), Ok(None));
+        }
+        // the join request on channel 8, then rotating through 8 to 10
+        assert_eq!(
+            &radio.uplinks[..],
+            &[
+                903_900_000,
+                904_100_000,
+                904_300_000,
+                903_900_000,
+                904_100_000
+            ]
+        );
+        assert_eq!(radio.server.received.len(), 4);
+        assert_eq!(radio.server.received[3].1[..], [3]);
+
+        // the ack comes 3s after the uplink, in RX1 at SF8
+        radio.server.queued = Some((5, &b"set 60"[..], true));
+        let d = device.send(&mut radio, 2, b"alert", true).unwrap().unwrap();
+        assert!(d.ack && d.confirmed);
+        assert_eq!((d.port, &d.payload[..]), (Some(5), &b"set 60"[..]));
+
+        // the confirmed downlink is acknowledged in the next uplink
+        assert_eq!(device.send(&mut radio, 1, b"y", false), Ok(None));
+        let (port, payload, confirmed, ack) = radio.server.received.last().unwrap();
+        assert_eq!(
+            (*port, &payload[..], *confirmed, *ack),
+            (1, &b"y"[..], false, true)
+        );
+        assert_eq!(device.session().unwrap().fcnt_up, 6);
+    }
+
+    #[test]
+    fn abp_downlink_in_rx2_eu868() {
+        let region = Region::Eu868;
+        let session = Session::abp(&region, DEV_ADDR, [1; 16], [2; 16]);
+        let mut server = StandIn::new(region);
+        server.session = Some(session);
+        server.use_rx2 = true;
+        let mut radio = Bridge::new(server);
+
+        let credentials = Credentials::Abp {
+            dev_addr: DEV_ADDR,
+            nwk_skey: [1; 16],
+            app_skey: [2; 16],
+        };
+        let mut device = Device::new(region, credentials);
+        assert_eq!(device.join(&mut radio, 1), Ok(true));
+
+        let d = device.send(&mut radio, 2, b"alert", true).unwrap().unwrap();
+        assert!(d.ack && !d.confirmed);
+        assert_eq!(radio.uplinks[0], 868_100_000);
+    }
+
+    #[test]
+    fn eu868_join_settings() {
+        let region = Region::Eu868;
+        let mut server = StandIn::new(region);
+        server.dl_settings = 0x20 | 3; // RX1 offset 2, RX2 at DR3
+        server.rx_delay = 0; // 1s
+        let mut cflist = [0u8; 16];
+        cflist[..3].copy_from_slice(&8_671_000u32.to_le_bytes()[..3]);
+        server.cflist = Some(cflist);
+        let mut radio = Bridge::new(server);
+
+        let mut device = Device::new(region, otaa());
+        assert_eq!(device.join(&mut radio, 7), Ok(true));
+        let session = device.session().unwrap();
+        assert_eq!(session.rx1_delay_ms, 1000);
+        assert_eq!(session.plan.mask, 0b1111);
+
+        // RX1 at DR3 (SF9), then RX2 at DR3
+        let d = device.send(&mut radio, 1, b"a", true).unwrap().unwrap();
+        assert!(d.ack);
+        radio.server.use_rx2 = true;
+        let d = device.send(&mut radio, 1, b"b", true).unwrap().unwrap();
+        assert!(d.ack);
+        device.send(&mut radio, 1, b"c", false).unwrap();
+        assert_eq!(
+            &radio.uplinks[..],
+            &[868_100_000, 868_300_000, 868_500_000, 867_100_000]
+        );
+    }
+
+    #[test]
+    fn missed_windows() {
+        // a device listening at the default 1s misses a downlink sent at 2s
+        let region = Region::Eu868;
+        let mut server = StandIn::new(region);
+        server.session = Some(Session::abp(&region, DEV_ADDR, [1; 16], [2; 16]));
+        server.rx_delay = 2;
+        let mut radio = Bridge::new(server);
+        let credentials = Credentials::Abp {
+            dev_addr: DEV_ADDR,
+            nwk_skey: [1; 16],
+            app_skey: [2; 16],
+        };
+        let mut device = Device::new(region, credentials);
+        assert_eq!(device.send(&mut radio, 1, b"a", true), Ok(None));
+
+        // a join accept for another key is not accepted
+        let mut radio = Bridge::new(StandIn::new(region));
+        let mut device = Device::new(
+            region,
+            Credentials::Otaa {
+                dev_eui: DEV_EUI,
+                app_eui: APP_EUI,
+                app_key: [9; 16],
+            },
+        );
+        assert_eq!(device.join(&mut radio, 7), Ok(false));
+    }
+
+    #[test]
+    fn downlink_checks() {
+        let region = Region::Eu868;
+        let mut device = Session::abp(&region, DEV_ADDR, [1; 16], [2; 16]);
+        let mut network = device.clone();
+
+        let frame = downlink_frame(&mut network, false, false, 3, b"hello");
+        let d = downlink(&mut device, &frame).unwrap();
+        assert_eq!((d.port, &d.payload[..]), (Some(3), &b"hello"[..]));
+        assert_eq!(device.fcnt_down, 1);
+
+        // replayed
+        assert_eq!(downlink(&mut device, &frame), None);
+
+        let mut bad = downlink_frame(&mut network, false, false, 3, b"hello");
+        let n = bad.len();
+        bad[n - 1] ^= 1;
+        assert_eq!(downlink(&mut device, &bad), None);
+
+        let mut other = network.clone();
+        other.dev_addr = [1, 2, 3, 4];
+        let frame = downlink_frame(&mut other, false, false, 3, b"hello");
+        assert_eq!(downlink(&mut device, &frame), None);
+
+        // a skipped counter is fine
+        let frame = downlink_frame(&mut network, true, true, 4, b"");
+        let d = downlink(&mut device, &frame).unwrap();
+        assert!(d.confirmed && d.ack);
+        assert_eq!((d.port, device.fcnt_down), (Some(4), 3));
+    }
+
+    #[test]
+    fn uplink_limits() {
+        let region = Region::Eu868;
+        let mut session = Session::abp(&region, DEV_ADDR, [1; 16], [2; 16]);
+        let mut out = Vec::new();
+        assert!(!uplink(&mut session, 0, b"mac", false, false, &mut out));
+        assert!(!uplink(&mut session, 1, &[0; 243], false, false, &mut out));
+        assert_eq!(session.fcnt_up, 0);
+        assert!(uplink(&mut session, 1, &[0; 242], false, false, &mut out));
+        assert_eq!((out.len(), session.fcnt_up), (255, 1));
+    }
+
+    #[test]
+    fn public_sync_word() {
+        let lora = LoRaConfig::default();
+        let up = modem(&lora, false);
+        let down = modem(&lora, true);
+        assert_eq!(lora_sync_word(&up), LORA_SYNC_PUBLIC);
+        assert_eq!(lora_sync_word(&down), LORA_SYNC_PUBLIC);
+        assert_eq!(sx127x_lora_sync_word(lora_sync_word(&up)), 0x34);
+        assert_eq!(up, ModemConfig::LoRaWan(lora));
+        assert_eq!(
+            down,
+            ModemConfig::LoRaWan(LoRaConfig {
+                invert_iq: true,
+                ..lora
+            })
+        );
+    }
+
+    #[test]
+    fn payload_limits() {
+        let us915 = Region::Us915 { subband: 2 };
+        assert_eq!(us915.max_payload(0), Some(11));
+        assert_eq!(us915.max_payload(4), Some(242));
+        assert_eq!(us915.max_payload(5), None);
+        assert_eq!(us915.max_payload(8), Some(53));
+        assert_eq!(Region::Eu868.max_payload(2), Some(51));
+        assert_eq!(Region::Eu868.max_payload(3), Some(115));
+        assert_eq!(Region::Eu868.max_payload(7), None);
+        // every data rate of a region has a limit
+        for region in [us915, Region::Eu868].iter() {
+            for dr in 0..16 {
+                let has = region.data_rate(dr).is_some();
+                assert_eq!(region.max_payload(dr).is_some(), has);
+            }
+        }
+
+        // 222 bytes at the EU868 uplink data rate, DR5
+        let session = Session::abp(&Region::Eu868, DEV_ADDR, [1; 16], [2; 16]);
+        let mut server = StandIn::new(Region::Eu868);
+        server.session = Some(session);
+        let mut radio = Bridge::new(server);
+        let credentials = Credentials::Abp {
+            dev_addr: DEV_ADDR,
+            nwk_skey: [1; 16],
+            app_skey: [2; 16],
+        };
+        let mut device = Device::new(Region::Eu868, credentials);
+        assert_eq!(device.join(&mut radio, 1), Ok(true));
+        assert_eq!(device.send(&mut radio, 1, &[7; 223], false), Ok(None));
+        assert!(radio.uplinks.is_empty());
+        assert_eq!(device.session().unwrap().fcnt_up, 0);
+        assert_eq!(device.send(&mut radio, 1, &[7; 222], false), Ok(None));
+        assert_eq!(radio.server.received[0].1[..], [7; 222][..]);
+    }
+}
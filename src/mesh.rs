@@ -0,0 +1,476 @@
+//! Simple mesh routing towards a base station.
+//!  Each node periodically broadcasts a beacon advertising its address, its hop count to the base
+//!  and the quality of its route. Nodes keep a table of the neighbours they hear, with RSSI and SNR
+//!  from PacketInfo, and forward data packets to the neighbour with the best route to the base.
+//!
+//!  Beacon payload:  [addr, hops to base (NO_ROUTE if none), route quality]
+//!
+//!  Tracker packets (next is BROADCAST) heard by a mesh node are routed to the base as well.
+//!  The radio is only used through the MeshRadio trait, implemented in lora_spi_gps_usart.rs for
+//!  the radios setup() returns, so a mock radio works too.
+
+use heapless::Vec;
+
+use crate::packet::{decode, Header, Kind, BROADCAST, MAX_PACKET};
+use crate::relay::DuplicateFilter;
+
+pub const MAX_NEIGHBOURS: usize = 16;
+
+// hop count advertised by a node that has no route to the base
+pub const NO_ROUTE: u8 = 0xFF;
+
+// a neighbour not heard for this long is dropped from the table
+pub const NEIGHBOUR_TIMEOUT_MS: u32 = 120_000;
+
+pub const BEACON_INTERVAL_MS: u32 = 30_000;
+
+// signal of a received packet, from the radio's PacketInfo
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Link {
+    pub rssi: i16,
+    pub snr: Option<i16>,
+}
+
+// What the mesh needs from the radio, which is receiving between calls.
+pub trait MeshRadio {
+    type Error;
+    // Transmit buf, wait until it has been sent and start receiving again.
+    fn send(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+    // The length and signal of a packet received into buff, or None if there is none yet.
+    fn receive(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Link)>, Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Neighbour {
+    pub addr: u8,
+    pub rssi: i16,
+    pub snr: Option<i16>,
+    pub hops_to_base: u8,
+    pub route_quality: u8, // advertised by the neighbour
+    pub last_seen_ms: u32,
+}
+
+impl Neighbour {
+    // quality of the route through this neighbour, limited by the weaker of the link and its route
+    pub fn quality(&self) -> u8 {
+        link_quality(self.rssi, self.snr).min(self.route_quality)
+    }
+}
+
+// Link quality 0 (unusable) to 255 from RSSI, about -130dBm to -30dBm,
+// reduced when the SNR shows the signal is below the noise floor.
+pub fn link_quality(rssi: i16, snr: Option<i16>) -> u8 {
+    let mut q = (rssi as i32 + 130) * 255 / 100;
+    if let Some(snr) = snr {
+        if snr < 0 {
+            q += snr as i32 * 10;
+        };
+    };
+    q.clamp(0, 255) as u8
+}
+
+// what to do with a received packet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Nothing,
+    Deliver, // this node is the base
+    Forward, // transmit to the next hop
+}
+
+pub struct Mesh {
+    addr: u8,
+    base: u8,
+    neighbours: Vec<Neighbour, MAX_NEIGHBOURS>,
+    filter: DuplicateFilter,
+    seq: u16,
+    last_beacon_ms: Option<u32>,
+}
+
+impl Mesh {
+    pub fn new(addr: u8, base: u8) -> Self {
+        Mesh {
+            addr,
+            base,
+            neighbours: Vec::new(),
+            filter: DuplicateFilter::new(),
+            seq: 0,
+            last_beacon_ms: None,
+        }
+    }
+
+    pub fn is_base(&self) -> bool {
+        self.addr == self.base
+    }
+
+    pub fn neighbours(&self) -> &[Neighbour] {
+        &self.neighbours
+    }
+
+    // forget neighbours not heard from recently
+    pub fn expire(&mut self, now_ms: u32) {
+        self.neighbours
+            .retain(|n| now_ms.wrapping_sub(n.last_seen_ms) < NEIGHBOUR_TIMEOUT_MS);
+    }
+
+    // Neighbour to forward to: fewest hops to the base, then best route quality.
+    pub fn next_hop(&self) -> Option<&Neighbour> {
+        self.neighbours
+            .iter()
+            .filter(|n| n.hops_to_base != NO_ROUTE)
+            .min_by(|a, b| {
+                a.hops_to_base
+                    .cmp(&b.hops_to_base)
+                    .then(b.quality().cmp(&a.quality()))
+            })
+    }
+
+    // this node's hop count and route quality, as advertised in beacons
+    pub fn route(&self) -> (u8, u8) {
+        if self.is_base() {
+            return (0, 255);
+        };
+        match self.next_hop() {
+            Some(n) if n.hops_to_base < NO_ROUTE - 1 => (n.hops_to_base + 1, n.quality()),
+            _ => (NO_ROUTE, 0),
+        }
+    }
+
+    pub fn beacon(&mut self, out: &mut Vec<u8, MAX_PACKET>) -> bool {
+        let (hops, quality) = self.route();
+        let mut header = Header::new(Kind::Beacon, &[self.addr], self.next_seq());
+        header.hops = 0; // beacons are never forwarded
+        header.encode(&[self.addr, hops, quality], out)
+    }
+
+    fn next_seq(&mut self) -> u16 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
+    }
+
+    fn heard(&mut self, addr: u8, hops_to_base: u8, route_quality: u8, link: Link, now: u32) {
+        let n = Neighbour {
+            addr,
+            rssi: link.rssi,
+            snr: link.snr,
+            hops_to_base,
+            route_quality,
+            last_seen_ms: now,
+        };
+        match self.neighbours.iter_mut().find(|x| x.addr == addr) {
+            Some(x) => *x = n,
+            None => {
+                if self.neighbours.push(n).is_err() {
+                    // table full, replace the weakest neighbour if this one is better
+                    if let Some(w) = self.neighbours.iter_mut().min_by_key(|x| x.quality()) {
+                        if w.quality() < n.quality() {
+                            *w = n;
+                        };
+                    };
+                };
+            }
+        };
+    }
+
+    // Handle a received packet. Beacons update the neighbour table. Data and alert packets
+    // addressed to this node (or broadcast by trackers) are put in out, either as received to be
+    // delivered when this node is the base, or re-addressed to the next hop to be forwarded.
+    pub fn handle(
+        &mut self,
+        buf: &[u8],
+        link: Link,
+        now_ms: u32,
+        out: &mut Vec<u8, MAX_PACKET>,
+    ) -> Action {
+        let (mut header, payload) = match decode(buf) {
+            Some(v) => v,
+            None => return Action::Nothing,
+        };
+
+        if header.kind == Kind::Beacon {
+            if payload.len() >= 3 && payload[0] != self.addr {
+                self.heard(payload[0], payload[1], payload[2], link, now_ms);
+            };
+            return Action::Nothing;
+        };
+
+        // not for this node, or seen before
+        if header.next != self.addr && header.next != BROADCAST {
+            return Action::Nothing;
+        };
+        if !self.filter.is_new(&header.source, header.seq) {
+            return Action::Nothing;
+        };
+
+        if self.is_base() {
+            out.clear();
+            // cannot fail, buf came from a packet of at most MAX_PACKET bytes
+            out.extend_from_slice(&buf[..buf.len().min(MAX_PACKET)])
+                .unwrap();
+            return Action::Deliver;
+        };
+
+        if header.hops == 0 || header.path.contains(&self.addr) {
+            return Action::Nothing;
+        };
+        let next = match self.next_hop() {
+            Some(n) => n.addr,
+            None => return Action::Nothing, // no route to the base yet
+        };
+
+        header.hops -= 1;
+        header.next = next;
+        if header.path.push(self.addr).is_err() || !header.encode(payload, out) {
+            return Action::Nothing;
+        };
+        Action::Forward
+    }
+
+    // One pass of the mesh: send a beacon when due, check for a received packet and forward it.
+    // now_ms is a millisecond counter maintained by the caller. Returns true when (on the base) a
+    // packet has been delivered into delivered. Receive must have been started by the caller.
+    pub fn poll<T: MeshRadio>(
+        &mut self,
+        radio: &mut T,
+        now_ms: u32,
+        buff: &mut [u8],
+        delivered: &mut Vec<u8, MAX_PACKET>,
+    ) -> Result<bool, T::Error> {
+        let mut out: Vec<u8, MAX_PACKET> = Vec::new();
+
+        self.expire(now_ms);
+
+        let due = match self.last_beacon_ms {
+            None => true,
+            Some(t) => now_ms.wrapping_sub(t) >= BEACON_INTERVAL_MS,
+        };
+        if due {
+            self.last_beacon_ms = Some(now_ms);
+            if self.beacon(&mut out) {
+                radio.send(&out)?;
+            };
+        };
+
+        let (n, link) = match radio.receive(buff)? {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        match self.handle(&buff[..n], link, now_ms, &mut out) {
+            Action::Nothing => Ok(false),
+            Action::Deliver => {
+                delivered.clear();
+                // cannot fail, same capacity
+                delivered.extend_from_slice(&out).unwrap();
+                Ok(true)
+            }
+            Action::Forward => {
+                radio.send(&out)?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::DEFAULT_HOPS;
+    use heapless::Deque;
+
+    // Packets queued to be received, and a record of those sent.
+    struct MockRadio {
+        incoming: Deque<(Vec<u8, MAX_PACKET>, Link), 4>,
+        sent: Vec<Vec<u8, MAX_PACKET>, 4>,
+    }
+
+    impl MockRadio {
+        fn new() -> Self {
+            MockRadio {
+                incoming: Deque::new(),
+                sent: Vec::new(),
+            }
+        }
+
+        fn hear(&mut self, buf: &[u8], rssi: i16) {
+            let mut v = Vec::new();
+            v.extend_from_slice(buf).unwrap();
+            let link = Link { rssi, snr: Some(5) };
+            self.incoming.push_back((v, link)).unwrap();
+        }
+    }
+
+    impl MeshRadio for MockRadio {
+        type Error = ();
+
+        fn send(&mut self, buf: &[u8]) -> Result<(), ()> {
+            let mut v = Vec::new();
+            v.extend_from_slice(buf).unwrap();
+            self.sent.push(v).map_err(|_| ())
+        }
+
+        fn receive(&mut self, buff: &mut [u8]) -> Result<Option<(usize, Link)>, ()> {
+            Ok(self.incoming.pop_front().map(|(v, link)| {
+                buff[..v.len()].copy_from_slice(&v);
+                (v.len(), link)
+            }))
+        }
+    }
+
+    const BASE: u8 = 1;
+
+    // beacon from addr with its route, as another node would send it
+    fn beacon(addr: u8, hops: u8, quality: u8) -> Vec<u8, MAX_PACKET> {
+        let mut h = Header::new(Kind::Beacon, &[addr], 1);
+        h.hops = 0;
+        let mut out = Vec::new();
+        assert!(h.encode(&[addr, hops, quality], &mut out));
+        out
+    }
+
+    fn tracker(seq: u16) -> Vec<u8, MAX_PACKET> {
+        let mut out = Vec::new();
+        assert!(Header::new(Kind::Data, b"tracker", seq).encode(b"45.0,-75.0", &mut out));
+        out
+    }
+
+    // poll once, with nothing delivered
+    fn poll(mesh: &mut Mesh, radio: &mut MockRadio, now_ms: u32) {
+        let mut buff = [0u8; MAX_PACKET];
+        let mut delivered = Vec::new();
+        assert_eq!(
+            mesh.poll(radio, now_ms, &mut buff, &mut delivered),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn link_quality_range() {
+        assert_eq!(link_quality(-140, None), 0);
+        assert_eq!(link_quality(-30, None), 255);
+        assert_eq!(link_quality(-20, Some(10)), 255);
+        assert!(link_quality(-80, Some(-5)) < link_quality(-80, Some(5)));
+    }
+
+    #[test]
+    fn beacons() {
+        let mut radio = MockRadio::new();
+        let mut node = Mesh::new(5, BASE);
+
+        poll(&mut node, &mut radio, 0);
+        assert_eq!(radio.sent.len(), 1);
+        let (h, payload) = decode(&radio.sent[0]).unwrap();
+        assert_eq!((h.kind, h.hops, &h.source[..]), (Kind::Beacon, 0, &[5][..]));
+        assert_eq!(payload, &[5, NO_ROUTE, 0]);
+
+        // not again until the interval has passed
+        poll(&mut node, &mut radio, BEACON_INTERVAL_MS - 10);
+        assert_eq!(radio.sent.len(), 1);
+        poll(&mut node, &mut radio, BEACON_INTERVAL_MS);
+        assert_eq!(radio.sent.len(), 2);
+        assert_ne!(decode(&radio.sent[1]).unwrap().0.seq, h.seq);
+
+        let mut base = Mesh::new(BASE, BASE);
+        let mut out = Vec::new();
+        assert!(base.beacon(&mut out));
+        assert_eq!(decode(&out).unwrap().1, &[BASE, 0, 255]);
+    }
+
+    #[test]
+    fn neighbours() {
+        let mut radio = MockRadio::new();
+        let mut node = Mesh::new(5, BASE);
+        radio.hear(&beacon(BASE, 0, 255), -60);
+        radio.hear(&beacon(5, 0, 255), -60); // its own, relayed back
+        poll(&mut node, &mut radio, 100);
+        poll(&mut node, &mut radio, 200);
+
+        assert_eq!(node.neighbours().len(), 1);
+        let n = node.neighbours()[0];
+        assert_eq!((n.addr, n.rssi, n.snr), (BASE, -60, Some(5)));
+        assert_eq!(n.last_seen_ms, 100);
+        assert_eq!(node.route(), (1, link_quality(-60, Some(5))));
+
+        // heard again, updated in place
+        radio.hear(&beacon(BASE, 0, 255), -90);
+        poll(&mut node, &mut radio, 300);
+        assert_eq!(node.neighbours().len(), 1);
+        assert_eq!(node.neighbours()[0].rssi, -90);
+
+        node.expire(300 + NEIGHBOUR_TIMEOUT_MS);
+        assert!(node.neighbours().is_empty());
+        assert_eq!(node.route(), (NO_ROUTE, 0));
+    }
+
+    #[test]
+    fn next_hop_fewest_hops_then_quality() {
+        let mut radio = MockRadio::new();
+        let mut node = Mesh::new(5, BASE);
+        radio.hear(&beacon(7, 2, 255), -40); // strong but further
+        radio.hear(&beacon(8, 1, 255), -100);
+        radio.hear(&beacon(9, 1, 255), -70);
+        radio.hear(&beacon(10, NO_ROUTE, 0), -30);
+        for t in 0..4 {
+            poll(&mut node, &mut radio, t);
+        }
+        assert_eq!(node.next_hop().unwrap().addr, 9);
+        assert_eq!(node.route().0, 2);
+    }
+
+    #[test]
+    fn forwards_to_next_hop() {
+        let mut radio = MockRadio::new();
+        let mut node = Mesh::new(5, BASE);
+        poll(&mut node, &mut radio, 0); // first beacon
+        radio.sent.clear();
+
+        // no route yet, the packet is dropped
+        radio.hear(&tracker(1), -50);
+        poll(&mut node, &mut radio, 10);
+        assert!(radio.sent.is_empty());
+
+        radio.hear(&beacon(BASE, 0, 255), -60);
+        radio.hear(&tracker(2), -50);
+        radio.hear(&tracker(2), -50); // duplicate
+        for t in 20..23 {
+            poll(&mut node, &mut radio, t);
+        }
+        assert_eq!(radio.sent.len(), 1);
+        let (h, payload) = decode(&radio.sent[0]).unwrap();
+        assert_eq!((h.next, h.seq, h.hops), (BASE, 2, DEFAULT_HOPS - 1));
+        assert_eq!(&h.path[..], &[5]);
+        assert_eq!(payload, b"45.0,-75.0");
+
+        // addressed to another node
+        let mut other = Header::new(Kind::Data, b"tracker", 3);
+        other.next = 6;
+        let mut buf = Vec::new();
+        assert!(other.encode(b"x", &mut buf));
+        radio.hear(&buf, -50);
+        poll(&mut node, &mut radio, 30);
+        assert_eq!(radio.sent.len(), 1);
+    }
+
+    #[test]
+    fn base_delivers() {
+        let mut radio = MockRadio::new();
+        let mut base = Mesh::new(BASE, BASE);
+        assert!(base.is_base());
+        let mut buff = [0u8; MAX_PACKET];
+        let mut delivered = Vec::new();
+
+        radio.hear(&tracker(1), -50);
+        assert_eq!(
+            base.poll(&mut radio, 0, &mut buff, &mut delivered),
+            Ok(true)
+        );
+        assert_eq!(&delivered[..], &tracker(1)[..]);
+
+        // only once
+        radio.hear(&tracker(1), -50);
+        assert_eq!(
+            base.poll(&mut radio, 10, &mut buff, &mut delivered),
+            Ok(false)
+        );
+        assert_eq!(radio.sent.len(), 1); // the beacon
+    }
+}
@@ -0,0 +1,314 @@
+//! Minimal NMEA 0183 parsing for the GPS sentences used in this crate.
+//!  Also parses the position reports sent by send_gps,  eg  "4523.74241,N 07540.61255,W 03:17:37Z"
+
+use core::fmt::Write;
+
+use heapless::String;
+use libm::{fabs, round};
+
+use crate::geodesy::Position;
+
+fn to_str(x: &[u8]) -> Option<&str> {
+    core::str::from_utf8(x).ok()
+}
+
+// NMEA coordinate  ddmm.mmmm (lat) or dddmm.mmmm (lon) plus hemisphere N/S/E/W to decimal degrees.
+pub fn parse_coordinate(field: &[u8], hemisphere: u8) -> Option<f64> {
+    // minutes always have two digits before the decimal point
+    let dot = field.iter().position(|b| *b == b'.').unwrap_or(field.len());
+    if dot < 3 {
+        return None;
+    };
+
+    let degrees: f64 = to_str(&field[..dot - 2])?.parse().ok()?;
+    let minutes: f64 = to_str(&field[dot - 2..])?.parse().ok()?;
+    let value = degrees + minutes / 60.0;
+
+    match hemisphere {
+        b'N' | b'E' => Some(value),
+        b'S' | b'W' => Some(-value),
+        _ => None,
+    }
+}
+
+// true for a sentence of the given type (eg b"GGA") from any talker ($GPGGA, $GNGGA, ...)
+pub fn is_sentence(line: &[u8], kind: &[u8]) -> bool {
+    line.len() > 6 && line[0] == b'$' && &line[3..6] == kind
+}
+
+// true for an RMC sentence from any talker ($GPRMC, $GNRMC, ...)
+pub fn is_rmc(line: &[u8]) -> bool {
+    is_sentence(line, b"RMC")
+}
+
+// Fields of a sentence after the talker and type, with the checksum (and any \r) removed.
+fn fields(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let end = line.iter().position(|b| *b == b'*').unwrap_or(line.len());
+    line[..end]
+        .split(|b| *b == b',')
+        .skip(1)
+        .map(|f| f.strip_suffix(b"\r").unwrap_or(f))
+}
+
+fn parse_number<T: core::str::FromStr>(field: &[u8]) -> Option<T> {
+    to_str(field)?.parse().ok()
+}
+
+// UTC time of day in seconds from  hhmmss.ss  (fractions of a second are dropped).
+// GPS receivers usually have the time before they have a fix.
+pub fn parse_time(field: &[u8]) -> Option<u32> {
+    if field.len() < 6 {
+        return None;
+    };
+    let h: u32 = parse_number(&field[0..2])?;
+    let m: u32 = parse_number(&field[2..4])?;
+    let s: u32 = parse_number(&field[4..6])?;
+    if h > 23 || m > 59 || s > 60 {
+        return None;
+    };
+    Some(h * 3600 + m * 60 + s.min(59))
+}
+
+// Time of an RMC sentence, with or without a valid fix.
+pub fn parse_rmc_time(line: &[u8]) -> Option<u32> {
+    if !is_rmc(line) {
+        return None;
+    };
+    parse_time(fields(line).next()?)
+}
+
+// Fix data from a GGA sentence.
+//   $GPGGA,031737.00,4523.74241,N,07540.61255,W,1,08,1.01,75.2,M,-34.3,M,,*6B
+pub struct Gga {
+    pub time: Option<u32>,
+    pub quality: u8, // 0 no fix, 1 GPS, 2 DGPS, 4 and 5 RTK, 6 estimated (dead reckoning)
+    pub satellites: Option<u8>,
+    pub hdop: Option<f32>,
+}
+
+pub fn parse_gga(line: &[u8]) -> Option<Gga> {
+    if !is_sentence(line, b"GGA") {
+        return None;
+    };
+    let mut f = fields(line);
+    let time = parse_time(f.next()?);
+    let mut f = f.skip(4); // position, as in RMC
+    Some(Gga {
+        time,
+        quality: parse_number(f.next()?).unwrap_or(0),
+        satellites: parse_number(f.next()?),
+        hdop: parse_number(f.next()?),
+    })
+}
+
+// Fix type and dilution of precision from a GSA sentence.
+//   $GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39
+pub struct Gsa {
+    pub mode: u8, // 1 no fix, 2 2D, 3 3D
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+}
+
+pub fn parse_gsa(line: &[u8]) -> Option<Gsa> {
+    if !is_sentence(line, b"GSA") {
+        return None;
+    };
+    let mut f = fields(line).skip(1); // A (automatic) or M (manual) 2D/3D selection
+    let mode = parse_number(f.next()?).unwrap_or(1);
+    let mut f = f.skip(12); // satellites used
+    Some(Gsa {
+        mode,
+        pdop: parse_number(f.next()?),
+        hdop: parse_number(f.next()?),
+        vdop: f.next().and_then(parse_number),
+    })
+}
+
+// Position from an RMC sentence. None if it is not RMC or the status is not A (valid).
+//   $GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66
+pub fn parse_rmc_position(line: &[u8]) -> Option<Position> {
+    if !is_rmc(line) {
+        return None;
+    };
+
+    let mut fields = line.split(|b| *b == b',').skip(2);
+    let status = fields.next()?;
+    if status != b"A" {
+        return None;
+    };
+    let lat = fields.next()?;
+    let ns = *fields.next()?.first()?;
+    let lon = fields.next()?;
+    let ew = *fields.next()?.first()?;
+
+    Some(Position::new(
+        parse_coordinate(lat, ns)?,
+        parse_coordinate(lon, ew)?,
+    ))
+}
+
+// Position from  "lat,N lon,W"  as in the payload of a report from send_gps.
+pub fn parse_position(msg: &[u8]) -> Option<Position> {
+    let mut parts = msg.splitn(2, |b| *b == b' ');
+    let mut lat = parts.next()?.split(|b| *b == b',');
+    let mut lon = parts.next()?.split(|b| *b == b',');
+
+    let lat = parse_coordinate(lat.next()?, *lat.next()?.first()?)?;
+    let lon = parse_coordinate(lon.next()?, *lon.next()?.first()?)?;
+
+    Some(Position::new(lat, lon))
+}
+
+// Sender id and position from a plain text report  "id lat,N lon,W"  (without a packet header).
+// Returns None for anything else (eg raw NMEA lines forwarded when there is no fix, or alerts).
+// The id cannot contain spaces.
+pub fn parse_report(msg: &[u8]) -> Option<(&[u8], Position)> {
+    let mut parts = msg.splitn(2, |b| *b == b' ');
+    let id = parts.next()?;
+
+    Some((id, parse_position(parts.next()?)?))
+}
+
+// Position as in a report from send_gps,  "4523.74241,N 07540.61255,W"  (minutes to 5 decimals, about 2m).
+pub fn format_position(p: Position) -> String<32> {
+    // rounded in whole 1e-5 minutes, so 59.999999 minutes carries into the degrees
+    let part = |v: f64| {
+        let m = round(fabs(v) * 60.0 * 1e5) as u64;
+        (m / 6_000_000, m / 100_000 % 60, m % 100_000)
+    };
+    let (ld, lm, lf) = part(p.lat);
+    let (od, om, of) = part(p.lon);
+    let ns = if p.lat < 0.0 { 'S' } else { 'N' };
+    let ew = if p.lon < 0.0 { 'W' } else { 'E' };
+
+    let mut out = String::new();
+    // cannot overflow, at most 27 characters
+    let _ = write!(
+        out,
+        "{:02}{:02}.{:05},{} {:03}{:02}.{:05},{}",
+        ld, lm, lf, ns, od, om, of, ew
+    );
+    out
+}
+
+// UTC time of day as in reports from send_gps and status messages,  "03:17:37Z"
+pub fn format_time(t: u32) -> String<16> {
+    let mut out = String::new();
+    // cannot overflow, 9 characters
+    let _ = write!(
+        out,
+        "{:02}:{:02}:{:02}Z",
+        t / 3600 % 24,
+        t / 60 % 60,
+        t % 60
+    );
+    out
+}
+
+// Fix time (UTC seconds of day) in a report from send_gps, the first  hh:mm:ssZ  word, eg
+// "4523.74241,N 07540.61255,W 03:17:37Z 2D". None for reports from senders that do not add it.
+pub fn parse_report_time(msg: &[u8]) -> Option<u32> {
+    msg.split(|b| *b == b' ').find_map(|w| {
+        if w.len() != 9 || w[2] != b':' || w[5] != b':' || w[8] != b'Z' {
+            return None;
+        };
+        parse_time(&[w[0], w[1], w[3], w[4], w[6], w[7]])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u32 = 24 * 3600;
+    const GGA: &[u8] =
+        b"$GPGGA,031737.00,4523.74241,N,07540.61255,W,1,08,1.01,75.2,M,-34.3,M,,*6B\r";
+    const GSA: &[u8] = b"$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39\r";
+    const RMC: &[u8] = b"$GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66\r";
+
+    #[test]
+    fn gga() {
+        let g = parse_gga(GGA).unwrap();
+        assert_eq!(g.time, Some(3 * 3600 + 17 * 60 + 37));
+        assert_eq!(g.quality, 1);
+        assert_eq!(g.satellites, Some(8));
+        assert_eq!(g.hdop, Some(1.01));
+
+        assert!(parse_gga(RMC).is_none());
+        assert!(parse_gga(b"$GNGGA,031737.00").is_none());
+    }
+
+    #[test]
+    fn gga_without_fix() {
+        let g = parse_gga(b"$GNGGA,,,,,,0,00,99.99,,,,,,*56").unwrap();
+        assert_eq!((g.time, g.quality, g.satellites), (None, 0, Some(0)));
+        assert_eq!(g.hdop, Some(99.99));
+
+        let g = parse_gga(b"$GPGGA,031737.00,,,,,,,,,,,,,*48").unwrap();
+        assert_eq!((g.quality, g.satellites, g.hdop), (0, None, None));
+    }
+
+    #[test]
+    fn gsa() {
+        let g = parse_gsa(GSA).unwrap();
+        assert_eq!(g.mode, 3);
+        assert_eq!((g.pdop, g.hdop, g.vdop), (Some(2.5), Some(1.3), Some(2.1)));
+
+        // NMEA 4.10 adds the system id after VDOP
+        let g = parse_gsa(b"$GNGSA,A,2,04,05,09,,,,,,,,,,3.1,2.9,1.0,1*00").unwrap();
+        assert_eq!((g.mode, g.pdop, g.vdop), (2, Some(3.1), Some(1.0)));
+
+        let g = parse_gsa(b"$GPGSA,A,,,,,,,,,,,,,,,,*32").unwrap();
+        assert_eq!((g.mode, g.pdop, g.hdop, g.vdop), (1, None, None, None));
+
+        assert!(parse_gsa(GGA).is_none());
+        assert!(parse_gsa(b"$GPGSA,A,3,04,05").is_none());
+    }
+
+    #[test]
+    fn rmc() {
+        assert_eq!(parse_rmc_time(RMC), Some(3 * 3600 + 17 * 60 + 37));
+        let p = parse_rmc_position(RMC).unwrap();
+        assert!((p.lat - (45.0 + 23.74241 / 60.0)).abs() < 1e-9);
+        assert!((p.lon + (75.0 + 40.61255 / 60.0)).abs() < 1e-9);
+
+        let void = b"$GPRMC,031737.00,V,,,,,,,300321,,,N*7E";
+        assert_eq!(parse_rmc_time(void), Some(3 * 3600 + 17 * 60 + 37));
+        assert!(parse_rmc_position(void).is_none());
+        assert!(parse_rmc_time(GGA).is_none());
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(parse_time(b"235960"), Some(24 * 3600 - 1));
+        assert_eq!(parse_time(b"000000.00"), Some(0));
+        assert_eq!(parse_time(b"240000"), None);
+        assert_eq!(parse_time(b"12345"), None);
+        assert_eq!(parse_time(b"12ab00"), None);
+
+        assert_eq!(&format_time(3 * 3600 + 17 * 60 + 37)[..], "03:17:37Z");
+        assert_eq!(&format_time(DAY + 5)[..], "00:00:05Z");
+        assert_eq!(
+            parse_report_time(b"4523.74241,N 07540.61255,W 03:17:37Z 2D"),
+            Some(3 * 3600 + 17 * 60 + 37)
+        );
+        assert_eq!(parse_report_time(b"4523.74241,N 07540.61255,W"), None);
+    }
+
+    #[test]
+    fn position_round_trip() {
+        let p = parse_rmc_position(RMC).unwrap();
+        let text = format_position(p);
+        assert_eq!(&text[..], "4523.74241,N 07540.61255,W");
+        assert_eq!(parse_position(text.as_bytes()), Some(p));
+
+        let (id, q) = parse_report(b"tracker1 4523.74241,N 07540.61255,W 03:17:37Z").unwrap();
+        assert_eq!((id, q), (&b"tracker1"[..], p));
+        assert!(parse_report(b"$GPRMC,031737.00,V").is_none());
+
+        // 59.999999 minutes carries into the degrees
+        let text = format_position(Position::new(-(10.0 + 59.9999999 / 60.0), 0.0));
+        assert_eq!(&text[..], "1100.00000,S 00000.00000,E");
+    }
+}
@@ -0,0 +1,219 @@
+//! Packet header used by senders, relays and receivers.
+//!
+//!  [MAGIC, kind, hops, next, seq (2 bytes, big endian), n, source (n bytes), m, path (m bytes), payload ...]
+//!
+//!  hops is the number of further retransmissions allowed, decremented by each relay.
+//!  next is the mesh address of the node that should forward the packet, or BROADCAST.
+//!  path lists the address of each relay the packet has passed through, in order.
+//!  Packets that do not start with MAGIC are plain text messages from older senders (or send_spi).
+
+use heapless::Vec;
+
+// not ASCII, so cannot be the start of a plain text message
+pub const MAGIC: u8 = 0xA5;
+
+// Nov 2020 limit data.len() < 255 in radio_sx127x  .start_transmit
+pub const MAX_PACKET: usize = 254;
+
+pub const MAX_SOURCE: usize = 16;
+pub const MAX_PATH: usize = 4;
+
+// hop limit set by senders
+pub const DEFAULT_HOPS: u8 = 3;
+
+// next hop for packets any relay may forward
+pub const BROADCAST: u8 = 0xFF;
+
+// Relay and mesh node address from eg option_env!("RELAY_ADDR"). None unless 0 to 254, as
+// BROADCAST cannot be the address of a node.
+pub fn parse_addr(s: &str) -> Option<u8> {
+    match s.parse() {
+        Ok(addr) if addr != BROADCAST => Some(addr),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Data,
+    Alert,
+    Beacon,
+    Status, // eg no GPS fix, sent in place of a position
+}
+
+impl Kind {
+    fn to_u8(self) -> u8 {
+        match self {
+            Kind::Data => 0,
+            Kind::Alert => 1,
+            Kind::Beacon => 2,
+            Kind::Status => 3,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Kind::Data),
+            1 => Some(Kind::Alert),
+            2 => Some(Kind::Beacon),
+            3 => Some(Kind::Status),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub kind: Kind,
+    pub hops: u8,
+    pub next: u8,
+    pub seq: u16,
+    pub source: Vec<u8, MAX_SOURCE>,
+    pub path: Vec<u8, MAX_PATH>,
+}
+
+impl Header {
+    // header for a new packet. source is truncated to MAX_SOURCE bytes.
+    pub fn new(kind: Kind, source: &[u8], seq: u16) -> Self {
+        let mut s = Vec::new();
+        s.extend_from_slice(&source[..source.len().min(MAX_SOURCE)])
+            .unwrap(); // cannot fail, truncated to capacity
+        Header {
+            kind,
+            hops: DEFAULT_HOPS,
+            next: BROADCAST,
+            seq,
+            source: s,
+            path: Vec::new(),
+        }
+    }
+
+    // write header and payload into out. Returns false (and out is incomplete) if it does not fit.
+    pub fn encode(&self, payload: &[u8], out: &mut Vec<u8, MAX_PACKET>) -> bool {
+        out.clear();
+        let seq = self.seq.to_be_bytes();
+        let fixed = [
+            MAGIC,
+            self.kind.to_u8(),
+            self.hops,
+            self.next,
+            seq[0],
+            seq[1],
+        ];
+        out.extend_from_slice(&fixed).is_ok()
+            && out.push(self.source.len() as u8).is_ok()
+            && out.extend_from_slice(&self.source).is_ok()
+            && out.push(self.path.len() as u8).is_ok()
+            && out.extend_from_slice(&self.path).is_ok()
+            && out.extend_from_slice(payload).is_ok()
+    }
+}
+
+// Header and payload of a received packet. None for plain text or malformed packets.
+pub fn decode(buf: &[u8]) -> Option<(Header, &[u8])> {
+    if buf.len() < 8 || buf[0] != MAGIC {
+        return None;
+    };
+
+    let kind = Kind::from_u8(buf[1])?;
+    let hops = buf[2];
+    let next = buf[3];
+    let seq = u16::from_be_bytes([buf[4], buf[5]]);
+
+    let n = buf[6] as usize;
+    let source = buf.get(7..7 + n)?;
+    let m = *buf.get(7 + n)? as usize;
+    let path = buf.get(8 + n..8 + n + m)?;
+    let payload = &buf[8 + n + m..];
+
+    let mut header = Header {
+        kind,
+        hops,
+        next,
+        seq,
+        source: Vec::new(),
+        path: Vec::new(),
+    };
+    header.source.extend_from_slice(source).ok()?;
+    header.path.extend_from_slice(path).ok()?;
+
+    Some((header, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        let mut h = Header::new(Kind::Alert, b"tracker-1", 0x1234);
+        h.hops = 2;
+        h.next = 7;
+        h.path.extend_from_slice(&[3, 4]).unwrap();
+        h
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        assert!(header().encode(b"45.0,-75.0", &mut out));
+        assert_eq!(
+            &out[..11],
+            &[MAGIC, 1, 2, 7, 0x12, 0x34, 9, b't', b'r', b'a', b'c']
+        );
+
+        let (h, payload) = decode(&out).unwrap();
+        assert_eq!(h, header());
+        assert_eq!(payload, b"45.0,-75.0");
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let h = Header::new(Kind::Beacon, b"", 0);
+        let mut out = Vec::new();
+        assert!(h.encode(b"", &mut out));
+        assert_eq!(out.len(), 8);
+        assert_eq!(decode(&out), Some((h, &b""[..])));
+    }
+
+    #[test]
+    fn new_truncates_source() {
+        let h = Header::new(Kind::Data, &[b'x'; MAX_SOURCE + 5], 1);
+        assert_eq!(h.source.len(), MAX_SOURCE);
+        assert_eq!((h.hops, h.next), (DEFAULT_HOPS, BROADCAST));
+    }
+
+    #[test]
+    fn encode_too_long() {
+        let mut out = Vec::new();
+        assert!(!header().encode(&[0; MAX_PACKET], &mut out));
+    }
+
+    #[test]
+    fn decode_rejects() {
+        let mut good = Vec::new();
+        assert!(header().encode(b"payload", &mut good));
+
+        assert_eq!(decode(b"plain text message"), None);
+        assert_eq!(decode(&good[..7]), None); // too short
+        let mut bad_kind = good.clone();
+        bad_kind[1] = 9;
+        assert_eq!(decode(&bad_kind), None);
+        // source and path lengths past the end
+        assert_eq!(decode(&good[..12]), None);
+        assert_eq!(decode(&good[..18]), None);
+        // more than MAX_PATH, taking part of the payload
+        let mut long_path = good.clone();
+        long_path[16] = MAX_PATH as u8 + 1;
+        assert_eq!(decode(&long_path), None);
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(parse_addr("0"), Some(0));
+        assert_eq!(parse_addr("254"), Some(254));
+        assert_eq!(parse_addr("255"), None);
+        assert_eq!(parse_addr("256"), None);
+        assert_eq!(parse_addr("-1"), None);
+        assert_eq!(parse_addr("x"), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! PMTK commands for MediaTek based GPS modules (and Quectel L70, L76, L80, ... which are MTK based).
+//!
+//!  $PMTK<type>,<fields>*<checksum>\r\n
+//!
+//!  The checksum is the XOR of the characters between $ and *, as for NMEA sentences.
+//!  Each command is answered with  $PMTK001,<type>,<flag>*<checksum>  where the flag is
+//!  0 invalid, 1 unsupported, 2 valid but failed or 3 success.
+//!  Used to cut the NMEA output down to the sentences this crate uses, as at 9600 bps the default
+//!  output of some modules (with GSV for several constellations) fills most of each second.
+
+use core::fmt::Write as _;
+
+use heapless::{String, Vec};
+use nb::block;
+use old_e_h::serial::{Read, Write};
+
+use crate::gateway::write_all;
+
+// NMEA limit, $ to \r\n
+pub const MAX_COMMAND: usize = 82;
+
+// lines read while waiting for an acknowledgement, a few seconds of output
+pub const ACK_LINES: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ack {
+    Invalid,
+    Unsupported,
+    Failed,
+    Success,
+}
+
+// Sentences output, every n fixes (0 off, 1 to 5). Sentences not listed here are switched off.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Output {
+    pub gll: u8,
+    pub rmc: u8,
+    pub vtg: u8,
+    pub gga: u8,
+    pub gsa: u8,
+    pub gsv: u8,
+    pub zda: u8,
+}
+
+// RMC, GGA and GSA on every fix, as used by send_gps (see src/fix.rs)
+pub const FIX_OUTPUT: Output = Output {
+    gll: 0,
+    rmc: 1,
+    vtg: 0,
+    gga: 1,
+    gsa: 1,
+    gsv: 0,
+    zda: 0,
+};
+
+fn checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0, |c, b| c ^ b)
+}
+
+// Complete command from its body (between $ and *), eg "PMTK220,1000" to "$PMTK220,1000*1F\r\n".
+pub fn command(out: &mut String<MAX_COMMAND>, body: &str) -> bool {
+    out.clear();
+    write!(out, "${}*{:02X}\r\n", body, checksum(body.as_bytes())).is_ok()
+}
+
+// PMTK220, a fix (and NMEA output) every period_ms, 100 to 10000
+pub fn set_update_rate(out: &mut String<MAX_COMMAND>, period_ms: u16) -> bool {
+    let mut body: String<MAX_COMMAND> = String::new();
+    write!(body, "PMTK220,{}", period_ms).is_ok() && command(out, &body)
+}
+
+// PMTK314, the sentences to output
+pub fn set_output(out: &mut String<MAX_COMMAND>, o: &Output) -> bool {
+    let mut body: String<MAX_COMMAND> = String::new();
+    // 19 fields, 6 to 16 are unused or for other sentences (left off), 17 is ZDA and 18 MCHN
+    write!(
+        body,
+        "PMTK314,{},{},{},{},{},{},0,0,0,0,0,0,0,0,0,0,0,{},0",
+        o.gll, o.rmc, o.vtg, o.gga, o.gsa, o.gsv, o.zda
+    )
+    .is_ok()
+        && command(out, &body)
+}
+
+// PMTK251, usart baud rate. The module changes rate without an acknowledgement, so the usart must
+// then be changed to match (see lora_spi_gps_usart::set_gps_baud).
+pub fn set_baud(out: &mut String<MAX_COMMAND>, baud: u32) -> bool {
+    let mut body: String<MAX_COMMAND> = String::new();
+    write!(body, "PMTK251,{}", baud).is_ok() && command(out, &body)
+}
+
+// Quectel PQTXT, the $GPTXT sentences (eg antenna status) on or off, and whether to save the setting
+pub fn quectel_txt(out: &mut String<MAX_COMMAND>, enable: bool, save: bool) -> bool {
+    let mut body: String<MAX_COMMAND> = String::new();
+    write!(body, "PQTXT,W,{},{}", enable as u8, save as u8).is_ok() && command(out, &body)
+}
+
+// true if the line ends with the correct *checksum (any \r\n is ignored)
+pub fn checksum_ok(line: &[u8]) -> bool {
+    let star = match line.iter().position(|b| *b == b'*') {
+        Some(i) => i,
+        None => return false,
+    };
+    if line.first() != Some(&b'$') || line.len() < star + 3 {
+        return false;
+    };
+    let hex = match core::str::from_utf8(&line[star + 1..star + 3]) {
+        Ok(h) => h,
+        Err(_e) => return false,
+    };
+    u8::from_str_radix(hex, 16).ok() == Some(checksum(&line[1..star]))
+}
+
+// The command type and result from a  $PMTK001,<type>,<flag>*<checksum>  line, None for other lines.
+pub fn parse_ack(line: &[u8]) -> Option<(u16, Ack)> {
+    if !line.starts_with(b"$PMTK001,") || !checksum_ok(line) {
+        return None;
+    };
+    let end = line.iter().position(|b| *b == b'*')?;
+    let mut fields = line[9..end].split(|b| *b == b',');
+    let kind = core::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+    let ack = match fields.next()? {
+        b"0" => Ack::Invalid,
+        b"1" => Ack::Unsupported,
+        b"2" => Ack::Failed,
+        b"3" => Ack::Success,
+        _ => return None,
+    };
+    Some((kind, ack))
+}
+
+// Write cmd (from command() or a set_ function) and wait for its acknowledgement.
+// None if there was none within ACK_LINES lines (eg not an MTK module, or a Quectel PQ command,
+// which are answered differently). feed is called for each byte received, eg to feed the watchdog.
+pub fn send<T, R>(tx: &mut T, rx: &mut R, cmd: &str, feed: &mut impl FnMut()) -> Option<Ack>
+where
+    T: Write<u8>,
+    R: Read<u8>,
+{
+    write_all(tx, cmd.as_bytes()).ok()?;
+    let kind: u16 = cmd.get(5..8)?.parse().ok()?;
+
+    let mut line: Vec<u8, MAX_COMMAND> = Vec::new();
+    let mut lines = 0;
+    while lines < ACK_LINES {
+        let byte = match block!(rx.read()) {
+            Ok(b) => b,
+            Err(_e) => continue, // eg overrun, keep looking
+        };
+        feed();
+        if byte == b'$' {
+            line.clear();
+        };
+        if line.push(byte).is_err() || byte == b'\n' {
+            if let Some((k, ack)) = parse_ack(&line) {
+                if k == kind {
+                    return Some(ack);
+                };
+            };
+            line.clear();
+            lines += 1;
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let mut out = String::new();
+        assert!(set_update_rate(&mut out, 1000));
+        assert_eq!(&out[..], "$PMTK220,1000*1F\r\n");
+        assert!(set_output(&mut out, &FIX_OUTPUT));
+        assert_eq!(
+            &out[..],
+            "$PMTK314,0,1,0,1,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0*29\r\n"
+        );
+        assert!(set_baud(&mut out, 38400));
+        assert_eq!(&out[..], "$PMTK251,38400*27\r\n");
+        assert!(checksum_ok(out.as_bytes()));
+    }
+
+    #[test]
+    fn checksum_lines() {
+        assert!(checksum_ok(b"$PMTK001,220,3*30"));
+        assert!(checksum_ok(b"$PMTK001,220,3*30\r\n"));
+        assert!(checksum_ok(
+            b"$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39"
+        ));
+        assert!(checksum_ok(
+            b"$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39\r\n"
+        ));
+        assert!(!checksum_ok(b"$PMTK001,220,3*31"));
+        assert!(!checksum_ok(b"$PMTK001,220,2*30"));
+        assert!(!checksum_ok(b"$PMTK001,220,3*3"));
+        assert!(!checksum_ok(b"$PMTK001,220,3*zz"));
+        assert!(!checksum_ok(b"$PMTK001,220,3"));
+        assert!(!checksum_ok(b"PMTK001,220,3*30"));
+        assert!(!checksum_ok(b""));
+    }
+
+    #[test]
+    fn ack() {
+        assert_eq!(
+            parse_ack(b"$PMTK001,220,3*30\r\n"),
+            Some((220, Ack::Success))
+        );
+        assert_eq!(parse_ack(b"$PMTK001,314,0*35"), Some((314, Ack::Invalid)));
+        assert_eq!(
+            parse_ack(b"$PMTK001,314,1*34"),
+            Some((314, Ack::Unsupported))
+        );
+        assert_eq!(parse_ack(b"$PMTK001,314,2*37"), Some((314, Ack::Failed)));
+        // bad checksum, unknown flag, not an acknowledgement
+        assert_eq!(parse_ack(b"$PMTK001,220,3*31"), None);
+        assert_eq!(parse_ack(b"$PMTK001,220,4*37"), None);
+        assert_eq!(parse_ack(b"$PMTK001,220*2F"), None);
+        assert_eq!(parse_ack(b"$PMTK010,001*2E"), None);
+        assert_eq!(
+            parse_ack(b"$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39"),
+            None
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Small xorshift pseudo random number generator for transmit delays and backoff.
+//!  Not for anything that needs real randomness.
+
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    // a zero seed would only ever give zero, so it is replaced
+    pub fn new(seed: u32) -> Self {
+        XorShift32 {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    // stir more entropy (eg bytes of a received packet, an RSSI reading) into the state
+    pub fn mix(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state = (self.state ^ *b as u32).wrapping_mul(0x0100_0193);
+        }
+        if self.state == 0 {
+            self.state = 0x9E37_79B9;
+        };
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // uniform enough in  low..high  (high exclusive). Returns low if the range is empty.
+    pub fn between(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        };
+        low + self.next_u32() % (high - low)
+    }
+}
@@ -0,0 +1,163 @@
+//! Store-and-forward relay logic used by the relay_spi binary.
+//!  A packet is retransmitted once by each relay, provided it has hops left and has not been
+//!  seen before. The relay address is appended to the packet path.
+
+use heapless::{Deque, Vec};
+
+use crate::packet::{decode, BROADCAST, MAX_PACKET};
+
+// number of (source, sequence) pairs remembered for de-duplication
+pub const SEEN_LEN: usize = 32;
+
+// FNV-1a, to keep the seen list small
+fn hash(bytes: &[u8]) -> u32 {
+    let mut h: u32 = 0x811C_9DC5;
+    for b in bytes {
+        h = (h ^ *b as u32).wrapping_mul(0x0100_0193);
+    }
+    h
+}
+
+#[derive(Default)]
+pub struct DuplicateFilter {
+    seen: Deque<(u32, u16), SEEN_LEN>,
+}
+
+impl DuplicateFilter {
+    pub fn new() -> Self {
+        DuplicateFilter::default()
+    }
+
+    // true the first time (source, seq) is offered, false for repeats
+    pub fn is_new(&mut self, source: &[u8], seq: u16) -> bool {
+        let key = (hash(source), seq);
+        if self.seen.iter().any(|k| *k == key) {
+            return false;
+        };
+        if self.seen.is_full() {
+            self.seen.pop_front();
+        };
+        // cannot fail, there is room
+        self.seen.push_back(key).unwrap();
+        true
+    }
+}
+
+pub struct Relay {
+    addr: u8,
+    filter: DuplicateFilter,
+}
+
+impl Relay {
+    pub fn new(addr: u8) -> Self {
+        Relay {
+            addr,
+            filter: DuplicateFilter::new(),
+        }
+    }
+
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    // Put the packet to retransmit in out and return true, or return false if buf should be dropped:
+    // plain text or malformed, already seen, no hops left, already relayed here, or path full.
+    // Packets routed by the mesh (next is not BROADCAST) are left to the mesh nodes.
+    pub fn process(&mut self, buf: &[u8], out: &mut Vec<u8, MAX_PACKET>) -> bool {
+        let (mut header, payload) = match decode(buf) {
+            Some(v) => v,
+            None => return false,
+        };
+        if header.next != BROADCAST {
+            return false;
+        };
+
+        if !self.filter.is_new(&header.source, header.seq) {
+            return false;
+        };
+        if header.hops == 0 || header.path.contains(&self.addr) {
+            return false;
+        };
+
+        header.hops -= 1;
+        if header.path.push(self.addr).is_err() {
+            return false;
+        };
+
+        header.encode(payload, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Header, Kind, MAX_PATH};
+
+    #[test]
+    fn duplicates() {
+        let mut filter = DuplicateFilter::new();
+        assert!(filter.is_new(b"a", 1));
+        assert!(!filter.is_new(b"a", 1));
+        assert!(filter.is_new(b"a", 2));
+        assert!(filter.is_new(b"b", 1));
+        assert!(!filter.is_new(b"b", 1));
+    }
+
+    #[test]
+    fn oldest_forgotten() {
+        let mut filter = DuplicateFilter::new();
+        for seq in 0..SEEN_LEN as u16 {
+            assert!(filter.is_new(b"a", seq));
+        }
+        assert!(!filter.is_new(b"a", 0));
+        assert!(filter.is_new(b"a", SEEN_LEN as u16)); // pushes out seq 0
+        assert!(filter.is_new(b"a", 0));
+        assert!(!filter.is_new(b"a", SEEN_LEN as u16));
+    }
+
+    fn packet(h: &Header) -> Vec<u8, MAX_PACKET> {
+        let mut buf = Vec::new();
+        assert!(h.encode(b"payload", &mut buf));
+        buf
+    }
+
+    #[test]
+    fn relays_once() {
+        let mut relay = Relay::new(9);
+        assert_eq!(relay.addr(), 9);
+        let buf = packet(&Header::new(Kind::Data, b"t", 5));
+        let mut out = Vec::new();
+
+        assert!(relay.process(&buf, &mut out));
+        let (h, payload) = decode(&out).unwrap();
+        assert_eq!(h.hops, crate::packet::DEFAULT_HOPS - 1);
+        assert_eq!(&h.path[..], &[9]);
+        assert_eq!(payload, b"payload");
+
+        assert!(!relay.process(&buf, &mut out));
+    }
+
+    #[test]
+    fn drops() {
+        let mut relay = Relay::new(9);
+        let mut out = Vec::new();
+
+        assert!(!relay.process(b"plain text", &mut out));
+
+        let mut h = Header::new(Kind::Data, b"t", 1);
+        h.hops = 0;
+        assert!(!relay.process(&packet(&h), &mut out));
+
+        let mut h = Header::new(Kind::Data, b"t", 2);
+        h.path.push(9).unwrap();
+        assert!(!relay.process(&packet(&h), &mut out));
+
+        let mut h = Header::new(Kind::Data, b"t", 3);
+        h.path.extend_from_slice(&[1; MAX_PATH]).unwrap();
+        assert!(!relay.process(&packet(&h), &mut out));
+
+        let mut h = Header::new(Kind::Data, b"t", 4);
+        h.next = 3; // for the mesh
+        assert!(!relay.process(&packet(&h), &mut out));
+    }
+}
@@ -0,0 +1,592 @@
+//! Radio adapter for the STM32WL sub-GHz radio (an SX126x on an internal SPI), with feature stm32wlxx.
+//!
+//!  SubGhzRadio wraps the stm32wlxx-hal SubGhz driver and implements the same traits as Sx127x
+//!  (DelayMs, Transmit, Receive, radio::Channel, Reinit, SetModem for FSK, ChannelActivity and
+//!  Fhss, without hopping within a packet), so the binaries and the supervisor run
+//!  unchanged on single chip trackers. It is configured from the same constants as the SX127x
+//!  (CONFIG_CH, CONFIG_LORA and CONFIG_PA) and errors use the sx127x Error type, with SPI errors as
+//!  Comms, so Supervisor::check classifies them the same way.
+//!  The antenna is switched between receive and transmit with two pins (RfSwitch), as on the
+//!  Seeed LoRa-E5 (Wio-E5) module, which only connects the high power amplifier.
+
+use core::convert::Infallible;
+
+use embedded_hal::delay::blocking::DelayMs;
+use old_e_h::digital::v2::OutputPin;
+use radio::{Receive, Transmit};
+use radio_sx127x::device::fsk::FskChannel;
+use radio_sx127x::device::lora::{
+    Bandwidth, CodingRate as Sx127xCodingRate, LoRaChannel, LoRaConfig, PayloadCrc, PayloadLength,
+    SpreadingFactor as Sx127xSpreadingFactor,
+};
+use radio_sx127x::device::{Channel, PacketInfo};
+use radio_sx127x::Error as sx127xError;
+use radio_sx127x::Error::Comms;
+
+use stm32wlxx_hal::spi::Error as SpiError;
+use stm32wlxx_hal::subghz::{
+    AddrComp, CadParams, CalibrateImage, CfgIrq, CodingRate, CrcType, ExitMode, FskBandwidth,
+    FskBitrate, FskFdev, FskModParams, FskPulseShape, GenericPacketParams, HeaderType, Irq,
+    LoRaBandwidth, LoRaModParams, LoRaPacketParams, LoRaSyncWord, NbCadSymbol, Ocp, PaConfig,
+    PaSel, PacketType, PreambleDetection, RampTime, RegMode, RfFreq, SgMiso, SgMosi,
+    SpreadingFactor, StandbyClk, SubGhz, TcxoMode, TcxoTrim, Timeout, TxParams,
+};
+
+use crate::fsk::{rx_bandwidth, valid, FskConfig, ModemConfig, SetModem};
+use crate::hopping::{Fhss, HopConfig};
+use crate::lbt::{ChannelActivity, LbtConfig, Method, RSSI_SETTLE_MS};
+use crate::lora_spi_gps_usart::{CONFIG_CH, CONFIG_LORA, CONFIG_PA, CONFIG_RADIO, FREQUENCY};
+use crate::supervisor::Reinit;
+
+pub type SubGhzError = sx127xError<SpiError, Infallible, Infallible>;
+
+// the whole radio buffer is used for each direction, as the radio is half duplex
+const TX_BASE: u8 = 0;
+const RX_BASE: u8 = 0;
+
+// the irqs checked by check_transmit and check_receive
+const IRQ_MASK: u16 = Irq::TxDone.mask()
+    | Irq::RxDone.mask()
+    | Irq::Timeout.mask()
+    | Irq::HeaderErr.mask()
+    | Irq::Err.mask();
+
+// the irqs of channel activity detection
+const IRQ_CAD: u16 = Irq::CadDone.mask() | Irq::CadDetected.mask();
+
+// Antenna switch. Both pins low is off, which saves power between packets.
+pub struct RfSwitch<RX, TX> {
+    rx: RX,
+    tx: TX,
+}
+
+impl<RX: OutputPin, TX: OutputPin> RfSwitch<RX, TX> {
+    pub fn new(rx: RX, tx: TX) -> Self {
+        let mut sw = RfSwitch { rx, tx };
+        sw.off();
+        sw
+    }
+
+    // pin errors are ignored, the hal gpio is infallible
+    fn off(&mut self) {
+        self.rx.set_low().ok();
+        self.tx.set_low().ok();
+    }
+
+    fn receive(&mut self) {
+        self.tx.set_low().ok();
+        self.rx.set_high().ok();
+    }
+
+    fn transmit(&mut self) {
+        self.rx.set_low().ok();
+        self.tx.set_high().ok();
+    }
+}
+
+pub struct SubGhzRadio<RX, TX, D> {
+    sg: SubGhz<SgMiso, SgMosi>,
+    rf: RfSwitch<RX, TX>,
+    delay: D,
+    mod_params: LoRaModParams,
+    lora: LoRaConfig,       // CONFIG_LORA unless changed by set_modem
+    fsk: Option<FskConfig>, // None in LoRa
+}
+
+// the image calibration band containing FREQUENCY
+fn calibrate_image() -> CalibrateImage {
+    match FREQUENCY {
+        f if f >= 902_000_000 => CalibrateImage::ISM_902_928,
+        f if f >= 863_000_000 => CalibrateImage::ISM_863_870,
+        f if f >= 779_000_000 => CalibrateImage::ISM_779_787,
+        f if f >= 470_000_000 => CalibrateImage::ISM_470_510,
+        _ => CalibrateImage::ISM_430_440,
+    }
+}
+
+// SX127x channel settings as SubGhz modulation parameters. The SubGhz supports the same spreading
+// factors and coding rates, but only some of the bandwidths.
+fn mod_params(ch: &LoRaChannel) -> Result<LoRaModParams, SubGhzError> {
+    let bw = match ch.bw {
+        Bandwidth::Bw125kHz => LoRaBandwidth::Bw125,
+        Bandwidth::Bw250kHz => LoRaBandwidth::Bw250,
+        Bandwidth::Bw500kHz => LoRaBandwidth::Bw500,
+        _ => return Err(sx127xError::InvalidConfiguration),
+    };
+    let sf = match ch.sf {
+        Sx127xSpreadingFactor::Sf7 => SpreadingFactor::Sf7,
+        Sx127xSpreadingFactor::Sf8 => SpreadingFactor::Sf8,
+        Sx127xSpreadingFactor::Sf9 => SpreadingFactor::Sf9,
+        Sx127xSpreadingFactor::Sf10 => SpreadingFactor::Sf10,
+        Sx127xSpreadingFactor::Sf11 => SpreadingFactor::Sf11,
+        Sx127xSpreadingFactor::Sf12 => SpreadingFactor::Sf12,
+        _ => return Err(sx127xError::InvalidConfiguration),
+    };
+    let cr = match ch.cr {
+        Sx127xCodingRate::Cr4_5 => CodingRate::Cr45,
+        Sx127xCodingRate::Cr4_6 => CodingRate::Cr46,
+        Sx127xCodingRate::Cr4_7 => CodingRate::Cr47,
+        Sx127xCodingRate::Cr4_8 => CodingRate::Cr48,
+    };
+    // low data rate optimisation, needed when a symbol is longer than 16ms
+    let ldro = matches!(
+        (ch.sf, ch.bw),
+        (Sx127xSpreadingFactor::Sf11, Bandwidth::Bw125kHz)
+            | (Sx127xSpreadingFactor::Sf12, Bandwidth::Bw125kHz)
+            | (Sx127xSpreadingFactor::Sf12, Bandwidth::Bw250kHz)
+    );
+    Ok(LoRaModParams::new()
+        .set_sf(sf)
+        .set_bw(bw)
+        .set_cr(cr)
+        .set_ldro_en(ldro))
+}
+
+// LoRa packet parameters, with the payload length for transmit (ignored for receive with an
+// explicit header)
+fn packet_params(lora: &LoRaConfig, payload_len: u8) -> LoRaPacketParams {
+    let header = match lora.payload_len {
+        PayloadLength::Variable => HeaderType::Variable,
+        PayloadLength::Constant(_) => HeaderType::Fixed,
+    };
+    LoRaPacketParams::new()
+        .set_preamble_len(lora.preamble_len)
+        .set_header_type(header)
+        .set_payload_len(payload_len)
+        .set_crc_en(lora.payload_crc == PayloadCrc::Enabled)
+        .set_invert_iq(lora.invert_iq)
+}
+
+// SX127x FSK channel settings as SubGhz GFSK modulation parameters, with the narrowest receive
+// bandwidth at least as wide as the SX127x one and a Gaussian BT 0.5 filter.
+fn fsk_mod_params(ch: &FskChannel) -> Result<FskModParams, SubGhzError> {
+    let bw = match rx_bandwidth(ch.bw) {
+        hz if hz <= 4_800 => FskBandwidth::Bw4,
+        hz if hz <= 5_800 => FskBandwidth::Bw5,
+        hz if hz <= 7_300 => FskBandwidth::Bw7,
+        hz if hz <= 9_700 => FskBandwidth::Bw9,
+        hz if hz <= 11_700 => FskBandwidth::Bw11,
+        hz if hz <= 14_600 => FskBandwidth::Bw14,
+        hz if hz <= 19_500 => FskBandwidth::Bw19,
+        hz if hz <= 23_400 => FskBandwidth::Bw23,
+        hz if hz <= 29_300 => FskBandwidth::Bw29,
+        hz if hz <= 39_000 => FskBandwidth::Bw39,
+        hz if hz <= 46_900 => FskBandwidth::Bw46,
+        hz if hz <= 58_600 => FskBandwidth::Bw58,
+        hz if hz <= 78_200 => FskBandwidth::Bw78,
+        hz if hz <= 93_800 => FskBandwidth::Bw93,
+        hz if hz <= 117_300 => FskBandwidth::Bw117,
+        hz if hz <= 156_200 => FskBandwidth::Bw156,
+        hz if hz <= 187_200 => FskBandwidth::Bw187,
+        hz if hz <= 234_300 => FskBandwidth::Bw234,
+        hz if hz <= 312_000 => FskBandwidth::Bw312,
+        hz if hz <= 373_600 => FskBandwidth::Bw373,
+        hz if hz <= 467_000 => FskBandwidth::Bw467,
+        _ => return Err(sx127xError::InvalidConfiguration),
+    };
+    if ch.br < 600 || ch.br > 300_000 {
+        return Err(sx127xError::InvalidConfiguration);
+    };
+    Ok(FskModParams::new()
+        .set_bitrate(FskBitrate::from_bps(ch.br))
+        .set_pulse_shape(FskPulseShape::Bt05)
+        .set_bandwidth(bw)
+        .set_fdev(FskFdev::from_hertz(ch.fdev)))
+}
+
+// CAD only (back to standby) and no timeout
+fn cad_params(config: &LbtConfig) -> CadParams {
+    let symbols = match config.cad_symbols {
+        0 | 1 => NbCadSymbol::S1,
+        2 => NbCadSymbol::S2,
+        3 | 4 => NbCadSymbol::S4,
+        5..=8 => NbCadSymbol::S8,
+        _ => NbCadSymbol::S16,
+    };
+    CadParams::new()
+        .set_num_symbol(symbols)
+        .set_det_peak(config.cad_det_peak)
+        .set_det_min(config.cad_det_min)
+        .set_exit_mode(ExitMode::Standby)
+        .set_timeout(Timeout::DISABLED)
+}
+
+// FskConfig packet parameters, with the maximum payload length for receive
+fn fsk_packet_params(fsk: &FskConfig, payload_len: u8) -> GenericPacketParams {
+    // preamble detector 16 bits, or 8 with a short preamble
+    let detection = if fsk.preamble_len >= 2 {
+        PreambleDetection::Bit16
+    } else {
+        PreambleDetection::Bit8
+    };
+    // 2 byte inverted CRC, CCITT with the polynomial and seed set in set_modem
+    let crc = if fsk.crc {
+        CrcType::Byte2Inverted
+    } else {
+        CrcType::None
+    };
+    GenericPacketParams::new()
+        .set_preamble_len(fsk.preamble_len * 8)
+        .set_preamble_detection(detection)
+        .set_sync_word_len(fsk.sync_len * 8)
+        .set_addr_comp(AddrComp::Disabled)
+        .set_header_type(HeaderType::Variable)
+        .set_payload_len(payload_len)
+        .set_crc_type(crc)
+        .set_whitening_enable(fsk.whitening)
+}
+
+impl<RX, TX, D> SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    // Configure the radio, left in standby. Like Sx127x::spi(), an error means the radio
+    // is not responding.
+    pub fn new(
+        sg: SubGhz<SgMiso, SgMosi>,
+        rf: RfSwitch<RX, TX>,
+        delay: D,
+    ) -> Result<Self, SubGhzError> {
+        let mut radio = SubGhzRadio {
+            sg,
+            rf,
+            delay,
+            mod_params: mod_params(&CONFIG_CH)?,
+            lora: CONFIG_LORA,
+            fsk: None,
+        };
+        radio.configure().map_err(Comms)?;
+        Ok(radio)
+    }
+
+    fn configure(&mut self) -> Result<(), SpiError> {
+        self.rf.off();
+        self.sg.set_standby(StandbyClk::Rc)?;
+        // the LoRa-E5 has a TCXO powered from the radio, it needs to be running before calibration
+        self.sg.set_tcxo_mode(
+            &TcxoMode::new()
+                .set_txco_trim(TcxoTrim::Volts1pt7)
+                .set_timeout(Timeout::from_millis_sat(10)),
+        )?;
+        self.sg.set_regulator_mode(RegMode::Smps)?;
+        self.sg.set_buffer_base_address(TX_BASE, RX_BASE)?;
+        self.sg.set_pa_config(
+            &PaConfig::new()
+                .set_pa_duty_cycle(0x4)
+                .set_hp_max(0x7)
+                .set_pa(PaSel::Hp),
+        )?;
+        self.sg.set_pa_ocp(Ocp::Max140m)?;
+        self.sg.set_tx_params(
+            &TxParams::new()
+                .set_ramp_time(RampTime::Micros40)
+                .set_power(CONFIG_PA.power as u8),
+        )?;
+        self.sg.set_packet_type(PacketType::LoRa)?;
+        self.lora = CONFIG_LORA;
+        self.fsk = None;
+        self.sg.set_lora_sync_word(LoRaSyncWord::Private)?;
+        self.sg.set_lora_mod_params(&self.mod_params)?;
+        self.sg
+            .set_lora_packet_params(&packet_params(&self.lora, 0))?;
+        self.sg.calibrate_image(calibrate_image())?;
+        self.sg
+            .set_rf_frequency(&RfFreq::from_frequency(CONFIG_CH.freq))?;
+        self.sg.set_irq_cfg(
+            &CfgIrq::new()
+                .irq_enable_all(Irq::TxDone)
+                .irq_enable_all(Irq::RxDone)
+                .irq_enable_all(Irq::Timeout)
+                .irq_enable_all(Irq::HeaderErr)
+                .irq_enable_all(Irq::Err)
+                .irq_enable_all(Irq::CadDone)
+                .irq_enable_all(Irq::CadDetected),
+        )?;
+        Ok(())
+    }
+
+    fn irq_status(&mut self) -> Result<u16, SpiError> {
+        let (_status, irq) = self.sg.irq_status()?;
+        Ok(irq & IRQ_MASK)
+    }
+
+    // packet parameters for the current modem
+    fn set_packet_params(&mut self, payload_len: u8) -> Result<(), SpiError> {
+        match self.fsk {
+            None => self
+                .sg
+                .set_lora_packet_params(&packet_params(&self.lora, payload_len)),
+            Some(fsk) => self
+                .sg
+                .set_packet_params(&fsk_packet_params(&fsk, payload_len)),
+        }
+    }
+}
+
+impl<RX, TX, D> DelayMs<u32> for SubGhzRadio<RX, TX, D>
+where
+    D: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = Infallible;
+
+    fn delay_ms(&mut self, ms: u32) -> Result<(), Self::Error> {
+        self.delay.delay_ms(ms);
+        Ok(())
+    }
+}
+
+impl<RX, TX, D> Transmit for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    type Error = SubGhzError;
+
+    fn start_transmit(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        if data.len() > 255 {
+            return Err(sx127xError::InvalidConfiguration);
+        };
+        self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+        self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+        self.sg.write_buffer(TX_BASE, data).map_err(Comms)?;
+        self.set_packet_params(data.len() as u8).map_err(Comms)?;
+        self.rf.transmit();
+        self.sg.set_tx(Timeout::DISABLED).map_err(Comms)?;
+        Ok(())
+    }
+
+    fn check_transmit(&mut self) -> Result<bool, Self::Error> {
+        let irq = self.irq_status().map_err(Comms)?;
+        if irq & Irq::TxDone.mask() != 0 {
+            self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+            self.rf.off();
+            Ok(true)
+        } else if irq & Irq::Timeout.mask() != 0 {
+            self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+            self.rf.off();
+            Err(sx127xError::Timeout)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl<RX, TX, D> Receive for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    type Info = PacketInfo;
+    type Error = SubGhzError;
+
+    // continuous receive, as the SX127x. Timeout::MAX is continuous, DISABLED would be single.
+    fn start_receive(&mut self) -> Result<(), Self::Error> {
+        self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+        self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+        self.set_packet_params(0xFF).map_err(Comms)?;
+        self.rf.receive();
+        self.sg.set_rx(Timeout::MAX).map_err(Comms)?;
+        Ok(())
+    }
+
+    // A received packet stays pending (RxDone set) until get_received. With restart a bad packet
+    // is dropped and receive continues, otherwise it is reported as a Crc error.
+    fn check_receive(&mut self, restart: bool) -> Result<bool, Self::Error> {
+        let irq = self.irq_status().map_err(Comms)?;
+        let bad = irq & (Irq::HeaderErr.mask() | Irq::Err.mask()) != 0;
+        if bad {
+            self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+            if restart {
+                Ok(false)
+            } else {
+                Err(sx127xError::Crc)
+            }
+        } else if irq & Irq::RxDone.mask() != 0 {
+            Ok(true)
+        } else if irq & Irq::Timeout.mask() != 0 {
+            self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+            if restart {
+                self.sg.set_rx(Timeout::MAX).map_err(Comms)?;
+                Ok(false)
+            } else {
+                Err(sx127xError::Timeout)
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn get_received(
+        &mut self,
+        info: &mut Self::Info,
+        buff: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        let (_status, len, ptr) = self.sg.rx_buffer_status().map_err(Comms)?;
+        // a longer packet is truncated, as by the SX127x driver
+        let len = (len as usize).min(buff.len());
+        self.sg.read_buffer(ptr, &mut buff[..len]).map_err(Comms)?;
+
+        if self.fsk.is_none() {
+            let status = self.sg.lora_packet_status().map_err(Comms)?;
+            info.rssi = status.rssi_pkt().to_integer();
+            info.snr = Some(status.snr_pkt().to_integer());
+        } else {
+            let status = self.sg.fsk_packet_status().map_err(Comms)?;
+            info.rssi = status.rssi_sync().to_integer();
+            info.snr = None;
+        };
+
+        self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)?;
+        Ok(len)
+    }
+}
+
+impl<RX, TX, D> radio::Channel for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    type Channel = Channel;
+    type Error = SubGhzError;
+
+    // a channel for the current modem, see SetModem
+    fn set_channel(&mut self, channel: &Self::Channel) -> Result<(), Self::Error> {
+        let freq = match (channel, self.fsk) {
+            (Channel::LoRa(ch), None) => {
+                self.mod_params = mod_params(ch)?;
+                self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+                self.sg
+                    .set_lora_mod_params(&self.mod_params)
+                    .map_err(Comms)?;
+                ch.freq
+            }
+            (Channel::FskOok(ch), Some(_)) => {
+                let params = fsk_mod_params(ch)?;
+                self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+                self.sg.set_fsk_mod_params(&params).map_err(Comms)?;
+                ch.freq
+            }
+            _ => return Err(sx127xError::InvalidConfiguration),
+        };
+        self.sg
+            .set_rf_frequency(&RfFreq::from_frequency(freq))
+            .map_err(Comms)?;
+        Ok(())
+    }
+}
+
+impl<RX, TX, D> Reinit for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    type Error = SubGhzError;
+
+    // There is no reset pin, so the configuration is written again from standby, with CONFIG_CH.
+    fn reinit(&mut self) -> Result<(), Self::Error> {
+        self.mod_params = mod_params(&CONFIG_CH)?;
+        self.configure().map_err(Comms)?;
+        Ok(())
+    }
+}
+
+impl<RX, TX, D> SetModem for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+{
+    type Error = SubGhzError;
+
+    // The packet type is set first, as it selects the format of the modulation and packet parameters.
+    fn set_modem(&mut self, modem: &ModemConfig, channel: &Channel) -> Result<(), Self::Error> {
+        match (modem, channel) {
+            (ModemConfig::LoRa(lora), Channel::LoRa(_))
+            | (ModemConfig::LoRaWan(lora), Channel::LoRa(_)) => {
+                let sync = match modem {
+                    ModemConfig::LoRaWan(_) => LoRaSyncWord::Public,
+                    _ => LoRaSyncWord::Private,
+                };
+                self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+                self.sg.set_packet_type(PacketType::LoRa).map_err(Comms)?;
+                self.lora = *lora;
+                self.fsk = None;
+                self.sg.set_lora_sync_word(sync).map_err(Comms)?;
+            }
+            (ModemConfig::Fsk(fsk), Channel::FskOok(ch)) if valid(fsk) => {
+                // checked before the radio is changed
+                fsk_mod_params(ch)?;
+                self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+                self.sg.set_packet_type(PacketType::Fsk).map_err(Comms)?;
+                self.fsk = Some(*fsk);
+                self.sg.set_sync_word(&fsk.sync_word).map_err(Comms)?;
+                // CCITT CRC, as the SX127x
+                self.sg.set_initial_crc_polynomial(0x1D0F).map_err(Comms)?;
+                self.sg.set_crc_polynomial(0x1021).map_err(Comms)?;
+            }
+            _ => return Err(sx127xError::InvalidConfiguration),
+        };
+        self.set_channel(channel)?;
+        self.set_packet_params(0).map_err(Comms)?;
+        self.sg.clear_irq_status(IRQ_MASK).map_err(Comms)
+    }
+}
+
+impl<RX, TX, D> ChannelActivity for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+    D: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = SubGhzError;
+
+    fn channel_busy(&mut self, config: &LbtConfig) -> Result<bool, Self::Error> {
+        self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+        self.sg.clear_irq_status(IRQ_CAD).map_err(Comms)?;
+        self.rf.receive();
+
+        let busy = if self.fsk.is_none() && config.method == Method::Cad {
+            self.sg.set_cad_params(&cad_params(config)).map_err(Comms)?;
+            self.sg.set_cad().map_err(Comms)?;
+            let mut irq = 0;
+            for _ in 0..CONFIG_RADIO.timeout_ms {
+                let (_status, pending) = self.sg.irq_status().map_err(Comms)?;
+                irq = pending;
+                if irq & Irq::CadDone.mask() != 0 {
+                    break;
+                };
+                self.delay.delay_ms(1);
+            }
+            self.sg.clear_irq_status(IRQ_CAD).map_err(Comms)?;
+            if irq & Irq::CadDone.mask() == 0 {
+                self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+                self.rf.off();
+                return Err(sx127xError::Timeout);
+            };
+            irq & Irq::CadDetected.mask() != 0
+        } else {
+            self.sg.set_rx(Timeout::MAX).map_err(Comms)?;
+            self.delay.delay_ms(RSSI_SETTLE_MS);
+            let rssi = self.sg.rssi_inst().map_err(Comms)?;
+            rssi.to_integer() >= config.rssi_threshold
+        };
+
+        self.sg.set_standby(StandbyClk::Rc).map_err(Comms)?;
+        self.rf.off();
+        Ok(busy)
+    }
+}
+
+// The sub-GHz radio has no intra-packet hopping, packets stay on one channel
+impl<RX, TX, D> Fhss for SubGhzRadio<RX, TX, D>
+where
+    RX: OutputPin,
+    TX: OutputPin,
+    D: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = SubGhzError;
+
+    fn fhss_service(&mut self, _config: &HopConfig) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
@@ -0,0 +1,159 @@
+//! Recovery from radio errors, rather than panicking or only printing them.
+//!
+//!  Each radio result is passed through Supervisor::check. Errors are counted by class.
+//!  Transient errors (receive timeout or CRC, expected with weak signals) are just counted.
+//!  After REINIT_AFTER bus errors (SPI or pin) in a row the radio is re-initialised, which resets it
+//!  with its reset pin and loads CONFIG_RADIO again. If MAX_REINITS re-initialisations in a row do
+//!  not help, the MCU is reset as a last resort.
+
+use radio_sx127x::base::Base;
+use radio_sx127x::prelude::*; // Sx127x
+use radio_sx127x::Error as sx127xError;
+
+use crate::logln;
+use crate::lora_spi_gps_usart::CONFIG_RADIO;
+
+// consecutive bus (or other) errors before the radio is re-initialised
+pub const REINIT_AFTER: u32 = 3;
+
+// consecutive re-initialisations, without a success in between, before the MCU is reset
+pub const MAX_REINITS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorClass {
+    Transient, // timeout or CRC, nothing wrong with the radio
+    Bus,       // SPI or gpio, eg a loose wire or the radio in a bad state
+    Other,     // anything else reported by the driver
+}
+
+pub fn classify<C, P, D>(err: &sx127xError<C, P, D>) -> ErrorClass {
+    match err {
+        sx127xError::Timeout | sx127xError::Crc => ErrorClass::Transient,
+        sx127xError::Comms(_) | sx127xError::Pin(_) => ErrorClass::Bus,
+        _ => ErrorClass::Other,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+    pub transient: u32,
+    pub bus: u32,
+    pub other: u32,
+    pub reinits: u32,
+    pub failed_reinits: u32,
+}
+
+// Radios that can be reset and configured again, after setup().
+pub trait Reinit {
+    type Error;
+    fn reinit(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<B, C, P, D> Reinit for Sx127x<B>
+where
+    B: Base<C, P, D>,
+{
+    type Error = sx127xError<C, P, D>;
+
+    // configure() toggles the reset pin, checks the silicon version and writes the configuration
+    fn reinit(&mut self) -> Result<(), Self::Error> {
+        self.configure(&CONFIG_RADIO)
+    }
+}
+
+// what the caller should do after a failed radio operation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recovery {
+    Retry,         // try again (next time round the loop)
+    Reinitialised, // the radio was reset, so start_receive again if receiving
+}
+
+// core clock cycles last_resort() waits before resetting the MCU (about 2s at 64 MHz, 8s at 16 MHz),
+// so a radio that is missing or stays broken resets the MCU every few seconds rather than continuously.
+pub const RESET_BACKOFF_CYCLES: u32 = 128_000_000;
+
+// Reset the MCU, after RESET_BACKOFF_CYCLES. Used when the radio cannot be recovered, and in place of
+// panic!, which only halts in release builds. Nothing feeds the watchdog meanwhile, so with feature
+// watchdog it may reset the MCU first.
+pub fn last_resort() -> ! {
+    logln!("radio cannot be recovered, resetting");
+    crate::arch::delay_cycles(RESET_BACKOFF_CYCLES);
+    crate::arch::reset()
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    counters: Counters,
+    errors_in_row: u32,
+    reinits_in_row: u32,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::default()
+    }
+
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    // true while there are no bus (or other) errors since the last success, ie the radio is working.
+    // Transient errors do not count, a receive timeout is not a fault. Used to decide when to feed
+    // the watchdog.
+    pub fn healthy(&self) -> bool {
+        self.errors_in_row == 0 && self.reinits_in_row == 0
+    }
+
+    // The value of a successful result, or the error handled and what to do next.
+    pub fn check<T, R, C, P, D>(
+        &mut self,
+        radio: &mut R,
+        result: Result<T, sx127xError<C, P, D>>,
+    ) -> Result<T, Recovery>
+    where
+        R: Reinit,
+    {
+        let err = match result {
+            Ok(v) => {
+                self.errors_in_row = 0;
+                self.reinits_in_row = 0;
+                return Ok(v);
+            }
+            Err(e) => e,
+        };
+
+        let class = classify(&err);
+        match class {
+            ErrorClass::Transient => {
+                self.counters.transient += 1;
+                return Err(Recovery::Retry);
+            }
+            ErrorClass::Bus => self.counters.bus += 1,
+            ErrorClass::Other => self.counters.other += 1,
+        };
+        logln!("radio error {:?}, {:?}", class, self.counters);
+
+        self.errors_in_row += 1;
+        if self.errors_in_row < REINIT_AFTER {
+            return Err(Recovery::Retry);
+        };
+
+        self.errors_in_row = 0;
+        self.reinits_in_row += 1;
+        if self.reinits_in_row > MAX_REINITS {
+            last_resort();
+        };
+
+        self.counters.reinits += 1;
+        match radio.reinit() {
+            Ok(()) => {
+                logln!("radio re-initialised");
+                Err(Recovery::Reinitialised)
+            }
+            Err(_e) => {
+                self.counters.failed_reinits += 1;
+                Err(Recovery::Retry)
+            }
+        }
+    }
+}
@@ -0,0 +1,760 @@
+//! Driver for SX126x radios (SX1262, SX1268, LLCC68) on SPI, with feature sx126x.
+//!
+//!  Sx126x implements the same traits as Sx127x (DelayMs, Transmit, Receive, radio::Channel,
+//!  Reinit, SetModem for FSK, ChannelActivity and Fhss, without hopping within a packet) and is
+//!  configured from the same constants (CONFIG_CH, CONFIG_LORA and CONFIG_PA), plus CONFIG_SX126X
+//!  for what the SX127x does not have. setup() passes it the same pins, the SX127x DIO0 pin is the
+//!  SX126x BUSY pin and DIO1 is DIO1, so boards only need a different radio module.
+//!  Errors use the sx127x Error type, so Supervisor::check classifies them the same way.
+//!
+//!  The SX126x is driven by commands. Before each command the driver waits for BUSY to go low,
+//!  and all the irqs it uses are routed to DIO1, so check_transmit and check_receive only read the
+//!  irq status over SPI when DIO1 is high. With a TCXO (eg Heltec V3, Wio-E5 style modules) DIO3
+//!  supplies it, and DIO2 can switch the antenna between transmit and receive.
+
+use core::convert::Infallible;
+
+use embedded_hal::delay::blocking::DelayMs;
+use old_e_h::blocking::spi::{Transfer, Write};
+use old_e_h::digital::v2::{InputPin, OutputPin};
+use radio::{Receive, Transmit};
+use radio_sx127x::device::fsk::FskChannel;
+use radio_sx127x::device::lora::{
+    Bandwidth, CodingRate, LoRaChannel, LoRaConfig, PayloadCrc, PayloadLength, SpreadingFactor,
+};
+use radio_sx127x::device::{Channel, PacketInfo};
+use radio_sx127x::Error as sx127xError;
+use radio_sx127x::Error::{Comms, Pin};
+
+use crate::fsk::{
+    lora_sync_word, rx_bandwidth, valid, FskConfig, ModemConfig, SetModem, LORA_SYNC_PRIVATE,
+};
+use crate::hopping::{Fhss, HopConfig};
+use crate::lbt::{ChannelActivity, LbtConfig, Method, RSSI_SETTLE_MS};
+use crate::lora_spi_gps_usart::{CONFIG_CH, CONFIG_LORA, CONFIG_PA, CONFIG_RADIO, FREQUENCY};
+use crate::supervisor::Reinit;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Device {
+    Sx1262, // 150-960MHz
+    Sx1268, // 410-810MHz
+    Llcc68, // as the SX1262, but fewer spreading factors at each bandwidth
+}
+
+// DIO3 output voltage for the TCXO
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TcxoVoltage {
+    V1_6 = 0,
+    V1_7,
+    V1_8,
+    V2_2,
+    V2_4,
+    V2_7,
+    V3_0,
+    V3_3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tcxo {
+    pub voltage: TcxoVoltage,
+    pub startup_us: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub device: Device,
+    pub tcxo: Option<Tcxo>, // None for a crystal
+    pub dio2_rf_switch: bool,
+}
+
+// commands
+const SET_STANDBY: u8 = 0x80;
+const SET_PACKET_TYPE: u8 = 0x8A;
+const SET_RF_FREQUENCY: u8 = 0x86;
+const SET_PA_CONFIG: u8 = 0x95;
+const SET_TX_PARAMS: u8 = 0x8E;
+const SET_BUFFER_BASE_ADDRESS: u8 = 0x8F;
+const SET_MODULATION_PARAMS: u8 = 0x8B;
+const SET_PACKET_PARAMS: u8 = 0x8C;
+const SET_DIO_IRQ_PARAMS: u8 = 0x08;
+const SET_DIO3_AS_TCXO_CTRL: u8 = 0x97;
+const SET_DIO2_AS_RF_SWITCH_CTRL: u8 = 0x9D;
+const SET_REGULATOR_MODE: u8 = 0x96;
+const CALIBRATE: u8 = 0x89;
+const CALIBRATE_IMAGE: u8 = 0x98;
+const GET_STATUS: u8 = 0xC0;
+const GET_IRQ_STATUS: u8 = 0x12;
+const CLEAR_IRQ_STATUS: u8 = 0x02;
+const GET_RX_BUFFER_STATUS: u8 = 0x13;
+const GET_PACKET_STATUS: u8 = 0x14;
+const GET_RSSI_INST: u8 = 0x15;
+const SET_CAD_PARAMS: u8 = 0x88;
+const SET_CAD: u8 = 0xC5;
+const SET_TX: u8 = 0x83;
+const SET_RX: u8 = 0x82;
+const WRITE_BUFFER: u8 = 0x0E;
+const READ_BUFFER: u8 = 0x1E;
+const WRITE_REGISTER: u8 = 0x0D;
+
+const REG_LORA_SYNC_WORD: u16 = 0x0740;
+const REG_CRC_INIT: u16 = 0x06BC;
+const REG_CRC_POLYNOMIAL: u16 = 0x06BE;
+const REG_FSK_SYNC_WORD: u16 = 0x06C0;
+
+// SetPacketType
+const PACKET_TYPE_GFSK: u8 = 0x00;
+const PACKET_TYPE_LORA: u8 = 0x01;
+
+// irq bits
+const IRQ_TX_DONE: u16 = 1 << 0;
+const IRQ_RX_DONE: u16 = 1 << 1;
+const IRQ_HEADER_ERR: u16 = 1 << 5;
+const IRQ_CRC_ERR: u16 = 1 << 6;
+const IRQ_CAD_DONE: u16 = 1 << 7;
+const IRQ_CAD_DETECTED: u16 = 1 << 8;
+const IRQ_TIMEOUT: u16 = 1 << 9;
+const IRQ_MASK: u16 = IRQ_TX_DONE
+    | IRQ_RX_DONE
+    | IRQ_HEADER_ERR
+    | IRQ_CRC_ERR
+    | IRQ_CAD_DONE
+    | IRQ_CAD_DETECTED
+    | IRQ_TIMEOUT;
+
+// SetTx and SetRx timeout, no timeout for transmit and continuous for receive
+const NO_TIMEOUT: [u8; 3] = [0x00, 0x00, 0x00];
+const CONTINUOUS: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+const TX_BASE: u8 = 0;
+const RX_BASE: u8 = 0;
+
+// BUSY polls before waiting 1ms between polls. BUSY is normally high for a few microseconds after
+// a command, but for milliseconds after calibration or starting a TCXO.
+const BUSY_SPINS: u32 = 100;
+
+pub struct Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY> {
+    spi: SPI,
+    cs: CS,
+    busy: BUSY,
+    dio1: DIO1,
+    reset: RESET,
+    delay: DELAY,
+    config: Config,
+    lora: LoRaConfig,       // CONFIG_LORA unless changed by set_modem
+    fsk: Option<FskConfig>, // None in LoRa
+}
+
+// image calibration frequencies (in 4MHz steps) for the band containing FREQUENCY
+fn calibrate_image() -> [u8; 2] {
+    match FREQUENCY {
+        f if f >= 902_000_000 => [0xE1, 0xE9],
+        f if f >= 863_000_000 => [0xD7, 0xDB],
+        f if f >= 779_000_000 => [0xC1, 0xC5],
+        f if f >= 470_000_000 => [0x75, 0x81],
+        _ => [0x6B, 0x6F],
+    }
+}
+
+// PA settings for the lowest table entry (SX1262 datasheet 13.1.14) that reaches power
+fn pa_config(power: u8) -> [u8; 4] {
+    let (duty_cycle, hp_max) = match power {
+        p if p > 20 => (0x04, 0x07),
+        p if p > 17 => (0x03, 0x05),
+        p if p > 14 => (0x02, 0x03),
+        _ => (0x02, 0x02),
+    };
+    [duty_cycle, hp_max, 0x00, 0x01] // SX1262 (not SX1261) PA
+}
+
+// SX127x channel settings as SX126x modulation parameters
+fn modulation_params<E, P>(
+    ch: &LoRaChannel,
+    device: Device,
+) -> Result<[u8; 4], sx127xError<E, P, Infallible>> {
+    let (bw, khz) = match ch.bw {
+        Bandwidth::Bw125kHz => (0x04, 125),
+        Bandwidth::Bw250kHz => (0x05, 250),
+        Bandwidth::Bw500kHz => (0x06, 500),
+        _ => return Err(sx127xError::InvalidConfiguration),
+    };
+    let sf = match ch.sf {
+        SpreadingFactor::Sf7 => 7,
+        SpreadingFactor::Sf8 => 8,
+        SpreadingFactor::Sf9 => 9,
+        SpreadingFactor::Sf10 => 10,
+        SpreadingFactor::Sf11 => 11,
+        SpreadingFactor::Sf12 => 12,
+        _ => return Err(sx127xError::InvalidConfiguration),
+    };
+    let cr = match ch.cr {
+        CodingRate::Cr4_5 => 0x01,
+        CodingRate::Cr4_6 => 0x02,
+        CodingRate::Cr4_7 => 0x03,
+        CodingRate::Cr4_8 => 0x04,
+    };
+    // the LLCC68 has SF5-9 at 125kHz, SF5-10 at 250kHz and SF5-11 at 500kHz
+    let max_sf = match (device, khz) {
+        (Device::Llcc68, 125) => 9,
+        (Device::Llcc68, 250) => 10,
+        (Device::Llcc68, _) => 11,
+        _ => 12,
+    };
+    if sf > max_sf {
+        return Err(sx127xError::InvalidConfiguration);
+    };
+    // low data rate optimisation, needed when a symbol is longer than 16ms
+    let ldro = ((1u32 << sf) > 16 * khz) as u8;
+    Ok([sf, bw, cr, ldro])
+}
+
+// LoRa packet parameters, with the payload length for transmit (ignored for receive with an
+// explicit header)
+fn packet_params(lora: &LoRaConfig, payload_len: u8) -> [u8; 6] {
+    let implicit = match lora.payload_len {
+        PayloadLength::Variable => 0x00,
+        PayloadLength::Constant(_) => 0x01,
+    };
+    let [pre_hi, pre_lo] = lora.preamble_len.to_be_bytes();
+    [
+        pre_hi,
+        pre_lo,
+        implicit,
+        payload_len,
+        (lora.payload_crc == PayloadCrc::Enabled) as u8,
+        lora.invert_iq as u8,
+    ]
+}
+
+// GFSK receive bandwidths (both sides, in Hz) and their codes
+const FSK_RX_BW: [(u32, u8); 21] = [
+    (4_800, 0x1F),
+    (5_800, 0x17),
+    (7_300, 0x0F),
+    (9_700, 0x1E),
+    (11_700, 0x16),
+    (14_600, 0x0E),
+    (19_500, 0x1D),
+    (23_400, 0x15),
+    (29_300, 0x0D),
+    (39_000, 0x1C),
+    (46_900, 0x14),
+    (58_600, 0x0C),
+    (78_200, 0x1B),
+    (93_800, 0x13),
+    (117_300, 0x0B),
+    (156_200, 0x1A),
+    (187_200, 0x12),
+    (234_300, 0x0A),
+    (312_000, 0x19),
+    (373_600, 0x11),
+    (467_000, 0x09),
+];
+
+// SX127x FSK channel settings as SX126x GFSK modulation parameters, with the narrowest receive
+// bandwidth at least as wide as the SX127x one and a Gaussian BT 0.5 filter.
+fn fsk_modulation_params<E, P>(ch: &FskChannel) -> Result<[u8; 8], sx127xError<E, P, Infallible>> {
+    let hz = rx_bandwidth(ch.bw);
+    let bw = match FSK_RX_BW.iter().find(|(bw, _)| *bw >= hz) {
+        Some((_, code)) => *code,
+        None => return Err(sx127xError::InvalidConfiguration),
+    };
+    if ch.br < 600 || ch.br > 300_000 {
+        return Err(sx127xError::InvalidConfiguration);
+    };
+    let [_, br2, br1, br0] = (32 * 32_000_000 / ch.br).to_be_bytes();
+    let [_, fd2, fd1, fd0] = ((((ch.fdev as u64) << 25) / 32_000_000) as u32).to_be_bytes();
+    Ok([br2, br1, br0, 0x09, bw, fd2, fd1, fd0])
+}
+
+// FskConfig packet parameters, with the maximum payload length for receive
+fn fsk_packet_params(fsk: &FskConfig, payload_len: u8) -> [u8; 9] {
+    let [pre_hi, pre_lo] = (fsk.preamble_len * 8).to_be_bytes();
+    // preamble detector 16 bits, or 8 with a short preamble
+    let detector = if fsk.preamble_len >= 2 { 0x05 } else { 0x04 };
+    [
+        pre_hi,
+        pre_lo,
+        detector,
+        fsk.sync_len * 8,
+        0x00, // no address filtering
+        0x01, // variable length
+        payload_len,
+        if fsk.crc { 0x06 } else { 0x01 }, // 2 byte inverted CRC (CCITT with the registers set), or none
+        fsk.whitening as u8,
+    ]
+}
+
+// SetCadParams, CAD only (back to standby) and no timeout
+fn cad_params(config: &LbtConfig) -> [u8; 7] {
+    let symbols = match config.cad_symbols {
+        0 | 1 => 0x00,
+        2 => 0x01,
+        3 | 4 => 0x02,
+        5..=8 => 0x03,
+        _ => 0x04, // 16
+    };
+    [
+        symbols,
+        config.cad_det_peak,
+        config.cad_det_min,
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+    ]
+}
+
+// frequency register, in steps of 32MHz / 2^25
+fn frequency(freq: u32) -> [u8; 4] {
+    ((((freq as u64) << 25) / 32_000_000) as u32).to_be_bytes()
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    // Reset and configure the radio, left in standby. As Sx127x::spi(), an error means the radio
+    // is not responding, eg not connected.
+    pub fn spi(
+        spi: SPI,
+        cs: CS,
+        busy: BUSY,
+        dio1: DIO1,
+        reset: RESET,
+        delay: DELAY,
+        config: &Config,
+    ) -> Result<Self, sx127xError<E, P, Infallible>> {
+        let mut radio = Sx126x {
+            spi,
+            cs,
+            busy,
+            dio1,
+            reset,
+            delay,
+            config: *config,
+            lora: CONFIG_LORA,
+            fsk: None,
+        };
+        radio.configure()?;
+        Ok(radio)
+    }
+
+    fn configure(&mut self) -> Result<(), sx127xError<E, P, Infallible>> {
+        self.cs.set_high().map_err(Pin)?;
+        self.reset.set_low().map_err(Pin)?;
+        self.delay.delay_ms(1);
+        self.reset.set_high().map_err(Pin)?;
+        self.delay.delay_ms(5);
+
+        self.command(SET_STANDBY, &[0x00])?; // RC oscillator
+
+        // chip mode (bits 6:4) is 2 in STDBY_RC, a missing radio reads as 0x00 or 0xFF
+        let status = self.get_status()?;
+        if (status >> 4) & 0x07 != 2 {
+            return Err(sx127xError::InvalidDevice(status));
+        };
+
+        if let Some(tcxo) = self.config.tcxo {
+            // start up time in steps of 15.625us
+            let [_, t2, t1, t0] = (tcxo.startup_us * 64 / 1000).to_be_bytes();
+            self.command(SET_DIO3_AS_TCXO_CTRL, &[tcxo.voltage as u8, t2, t1, t0])?;
+            // the calibration at reset failed without the TCXO running, so calibrate everything again
+            self.command(CALIBRATE, &[0x7F])?;
+        };
+        if self.config.dio2_rf_switch {
+            self.command(SET_DIO2_AS_RF_SWITCH_CTRL, &[0x01])?;
+        };
+        self.command(SET_REGULATOR_MODE, &[0x01])?; // DC-DC
+        self.command(SET_PACKET_TYPE, &[PACKET_TYPE_LORA])?;
+        self.lora = CONFIG_LORA;
+        self.fsk = None;
+        self.command(CALIBRATE_IMAGE, &calibrate_image())?;
+        self.set_lora_channel(&CONFIG_CH)?;
+        let power = CONFIG_PA.power as u8;
+        self.command(SET_PA_CONFIG, &pa_config(power))?;
+        self.command(SET_TX_PARAMS, &[power, 0x02])?; // 40us ramp
+        self.command(SET_BUFFER_BASE_ADDRESS, &[TX_BASE, RX_BASE])?;
+        self.command(SET_PACKET_PARAMS, &packet_params(&self.lora, 0))?;
+        // private network sync word 0x12, as the SX127x default
+        self.write_register(REG_LORA_SYNC_WORD, &LORA_SYNC_PRIVATE)?;
+        let [m1, m0] = IRQ_MASK.to_be_bytes();
+        self.command(SET_DIO_IRQ_PARAMS, &[m1, m0, m1, m0, 0, 0, 0, 0])?; // all on DIO1
+        self.command(CLEAR_IRQ_STATUS, &[0xFF, 0xFF])
+    }
+
+    fn set_lora_channel(&mut self, ch: &LoRaChannel) -> Result<(), sx127xError<E, P, Infallible>> {
+        let params = modulation_params(ch, self.config.device)?;
+        self.command(SET_STANDBY, &[0x00])?;
+        self.command(SET_MODULATION_PARAMS, &params)?;
+        self.command(SET_RF_FREQUENCY, &frequency(ch.freq))
+    }
+
+    fn set_fsk_channel(&mut self, ch: &FskChannel) -> Result<(), sx127xError<E, P, Infallible>> {
+        let params = fsk_modulation_params(ch)?;
+        self.command(SET_STANDBY, &[0x00])?;
+        self.command(SET_MODULATION_PARAMS, &params)?;
+        self.command(SET_RF_FREQUENCY, &frequency(ch.freq))
+    }
+
+    // packet parameters for the current modem
+    fn set_packet_params(&mut self, payload_len: u8) -> Result<(), sx127xError<E, P, Infallible>> {
+        match self.fsk {
+            None => self.command(SET_PACKET_PARAMS, &packet_params(&self.lora, payload_len)),
+            Some(fsk) => self.command(SET_PACKET_PARAMS, &fsk_packet_params(&fsk, payload_len)),
+        }
+    }
+
+    // Wait for BUSY low, which the SX126x needs before each command. A radio stuck busy is
+    // reported as Aborted, and re-initialised by the supervisor.
+    fn wait_busy(&mut self) -> Result<(), sx127xError<E, P, Infallible>> {
+        for n in 0..BUSY_SPINS + CONFIG_RADIO.timeout_ms {
+            if self.busy.is_low().map_err(Pin)? {
+                return Ok(());
+            };
+            if n >= BUSY_SPINS {
+                self.delay.delay_ms(1);
+            };
+        }
+        Err(sx127xError::Aborted)
+    }
+
+    // Select the radio for one SPI transaction, deselecting it also after an SPI error.
+    fn transaction<F>(&mut self, f: F) -> Result<(), sx127xError<E, P, Infallible>>
+    where
+        F: FnOnce(&mut SPI) -> Result<(), E>,
+    {
+        self.wait_busy()?;
+        self.cs.set_low().map_err(Pin)?;
+        let result = f(&mut self.spi);
+        self.cs.set_high().map_err(Pin)?;
+        result.map_err(Comms)
+    }
+
+    fn command(&mut self, op: u8, params: &[u8]) -> Result<(), sx127xError<E, P, Infallible>> {
+        self.transaction(|spi| {
+            spi.write(&[op])?;
+            spi.write(params)
+        })
+    }
+
+    // GetStatus, the status byte is clocked out with the byte after the opcode (rather than
+    // before any data, as for the other commands that return something)
+    fn get_status(&mut self) -> Result<u8, sx127xError<E, P, Infallible>> {
+        let mut status = [0u8; 1];
+        self.transaction(|spi| {
+            spi.write(&[GET_STATUS])?;
+            spi.transfer(&mut status).map(|_| ())
+        })?;
+        Ok(status[0])
+    }
+
+    // a command returning data, after the status byte
+    fn read(
+        &mut self,
+        op: u8,
+        params: &[u8],
+        data: &mut [u8],
+    ) -> Result<(), sx127xError<E, P, Infallible>> {
+        self.transaction(|spi| {
+            spi.write(&[op])?;
+            spi.write(params)?;
+            spi.write(&[0x00])?; // status
+            spi.transfer(data).map(|_| ())
+        })
+    }
+
+    fn write_register(
+        &mut self,
+        addr: u16,
+        data: &[u8],
+    ) -> Result<(), sx127xError<E, P, Infallible>> {
+        let [hi, lo] = addr.to_be_bytes();
+        self.transaction(|spi| {
+            spi.write(&[WRITE_REGISTER, hi, lo])?;
+            spi.write(data)
+        })
+    }
+
+    // Pending irqs, without SPI if DIO1 is low.
+    fn irq_status(&mut self) -> Result<u16, sx127xError<E, P, Infallible>> {
+        if self.dio1.is_low().map_err(Pin)? {
+            return Ok(0);
+        };
+        let mut irq = [0u8; 2];
+        self.read(GET_IRQ_STATUS, &[], &mut irq)?;
+        Ok(u16::from_be_bytes(irq) & IRQ_MASK)
+    }
+
+    fn clear_irqs(&mut self) -> Result<(), sx127xError<E, P, Infallible>> {
+        self.command(CLEAR_IRQ_STATUS, &IRQ_MASK.to_be_bytes())
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY> DelayMs<u32> for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = Infallible;
+
+    fn delay_ms(&mut self, ms: u32) -> Result<(), Self::Error> {
+        self.delay.delay_ms(ms);
+        Ok(())
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> Transmit for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = sx127xError<E, P, Infallible>;
+
+    fn start_transmit(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        if data.len() > 255 {
+            return Err(sx127xError::InvalidConfiguration);
+        };
+        self.command(SET_STANDBY, &[0x00])?;
+        self.clear_irqs()?;
+        self.transaction(|spi| {
+            spi.write(&[WRITE_BUFFER, TX_BASE])?;
+            spi.write(data)
+        })?;
+        self.set_packet_params(data.len() as u8)?;
+        self.command(SET_TX, &NO_TIMEOUT)
+    }
+
+    fn check_transmit(&mut self) -> Result<bool, Self::Error> {
+        let irq = self.irq_status()?;
+        if irq & IRQ_TX_DONE != 0 {
+            self.clear_irqs()?;
+            Ok(true)
+        } else if irq & IRQ_TIMEOUT != 0 {
+            self.clear_irqs()?;
+            Err(sx127xError::Timeout)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> Receive for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Info = PacketInfo;
+    type Error = sx127xError<E, P, Infallible>;
+
+    // continuous receive, as the SX127x
+    fn start_receive(&mut self) -> Result<(), Self::Error> {
+        self.command(SET_STANDBY, &[0x00])?;
+        self.clear_irqs()?;
+        self.set_packet_params(0xFF)?;
+        self.command(SET_RX, &CONTINUOUS)
+    }
+
+    // A received packet stays pending (RxDone set) until get_received. With restart a bad packet
+    // is dropped and receive continues, otherwise it is reported as a Crc error.
+    fn check_receive(&mut self, restart: bool) -> Result<bool, Self::Error> {
+        let irq = self.irq_status()?;
+        if irq & (IRQ_HEADER_ERR | IRQ_CRC_ERR) != 0 {
+            self.clear_irqs()?;
+            if restart {
+                Ok(false)
+            } else {
+                Err(sx127xError::Crc)
+            }
+        } else if irq & IRQ_RX_DONE != 0 {
+            Ok(true)
+        } else if irq & IRQ_TIMEOUT != 0 {
+            self.clear_irqs()?;
+            if restart {
+                self.command(SET_RX, &CONTINUOUS)?;
+                Ok(false)
+            } else {
+                Err(sx127xError::Timeout)
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn get_received(
+        &mut self,
+        info: &mut Self::Info,
+        buff: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        let mut status = [0u8; 2];
+        self.read(GET_RX_BUFFER_STATUS, &[], &mut status)?;
+        let [len, start] = status;
+        // a longer packet is truncated, as by the SX127x driver
+        let len = (len as usize).min(buff.len());
+        self.read(READ_BUFFER, &[start], &mut buff[..len])?;
+
+        // LoRa: rssi, snr and signal rssi. GFSK: rx status, rssi at sync and average rssi.
+        let mut pkt = [0u8; 3];
+        self.read(GET_PACKET_STATUS, &[], &mut pkt)?;
+        if self.fsk.is_none() {
+            info.rssi = -(pkt[0] as i16) / 2;
+            info.snr = Some((pkt[1] as i8) as i16 / 4);
+        } else {
+            info.rssi = -(pkt[1] as i16) / 2;
+            info.snr = None;
+        };
+
+        self.clear_irqs()?;
+        Ok(len)
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> radio::Channel
+    for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Channel = Channel;
+    type Error = sx127xError<E, P, Infallible>;
+
+    // a channel for the current modem, see SetModem
+    fn set_channel(&mut self, channel: &Self::Channel) -> Result<(), Self::Error> {
+        match (channel, self.fsk) {
+            (Channel::LoRa(ch), None) => self.set_lora_channel(ch),
+            (Channel::FskOok(ch), Some(_)) => self.set_fsk_channel(ch),
+            _ => Err(sx127xError::InvalidConfiguration),
+        }
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> Reinit for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = sx127xError<E, P, Infallible>;
+
+    // as for the SX127x, the reset pin is toggled and the configuration written again
+    fn reinit(&mut self) -> Result<(), Self::Error> {
+        self.configure()
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> SetModem for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = sx127xError<E, P, Infallible>;
+
+    // The packet type is set first, as it selects the format of the modulation and packet parameters.
+    fn set_modem(&mut self, modem: &ModemConfig, channel: &Channel) -> Result<(), Self::Error> {
+        match (modem, channel) {
+            (ModemConfig::LoRa(lora), Channel::LoRa(ch))
+            | (ModemConfig::LoRaWan(lora), Channel::LoRa(ch)) => {
+                self.command(SET_STANDBY, &[0x00])?;
+                self.command(SET_PACKET_TYPE, &[PACKET_TYPE_LORA])?;
+                self.lora = *lora;
+                self.fsk = None;
+                self.set_lora_channel(ch)?;
+                self.write_register(REG_LORA_SYNC_WORD, &lora_sync_word(modem))?;
+            }
+            (ModemConfig::Fsk(fsk), Channel::FskOok(ch)) if valid(fsk) => {
+                // checked before the radio is changed
+                fsk_modulation_params::<E, P>(ch)?;
+                self.command(SET_STANDBY, &[0x00])?;
+                self.command(SET_PACKET_TYPE, &[PACKET_TYPE_GFSK])?;
+                self.fsk = Some(*fsk);
+                self.set_fsk_channel(ch)?;
+                self.write_register(REG_FSK_SYNC_WORD, &fsk.sync_word)?;
+                // CCITT CRC, as the SX127x
+                self.write_register(REG_CRC_INIT, &[0x1D, 0x0F])?;
+                self.write_register(REG_CRC_POLYNOMIAL, &[0x10, 0x21])?;
+            }
+            _ => return Err(sx127xError::InvalidConfiguration),
+        };
+        self.set_packet_params(0)?;
+        self.clear_irqs()
+    }
+}
+
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> ChannelActivity
+    for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = sx127xError<E, P, Infallible>;
+
+    fn channel_busy(&mut self, config: &LbtConfig) -> Result<bool, Self::Error> {
+        self.command(SET_STANDBY, &[0x00])?;
+        self.clear_irqs()?;
+
+        if self.fsk.is_none() && config.method == Method::Cad {
+            self.command(SET_CAD_PARAMS, &cad_params(config))?;
+            self.command(SET_CAD, &[])?;
+            for _ in 0..CONFIG_RADIO.timeout_ms {
+                let irq = self.irq_status()?;
+                if irq & IRQ_CAD_DONE != 0 {
+                    self.clear_irqs()?;
+                    return Ok(irq & IRQ_CAD_DETECTED != 0);
+                };
+                self.delay.delay_ms(1);
+            }
+            self.command(SET_STANDBY, &[0x00])?;
+            return Err(sx127xError::Timeout);
+        };
+
+        self.command(SET_RX, &CONTINUOUS)?;
+        self.delay.delay_ms(RSSI_SETTLE_MS);
+        let mut rssi = [0u8; 1];
+        self.read(GET_RSSI_INST, &[], &mut rssi)?;
+        self.command(SET_STANDBY, &[0x00])?;
+        Ok(-(rssi[0] as i16) / 2 >= config.rssi_threshold)
+    }
+}
+
+// The SX126x has no intra-packet hopping, packets stay on one channel
+impl<SPI, CS, BUSY, DIO1, RESET, DELAY, E, P> Fhss for Sx126x<SPI, CS, BUSY, DIO1, RESET, DELAY>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin<Error = P>,
+    BUSY: InputPin<Error = P>,
+    DIO1: InputPin<Error = P>,
+    RESET: OutputPin<Error = P>,
+    DELAY: old_e_h::blocking::delay::DelayMs<u32>,
+{
+    type Error = sx127xError<E, P, Infallible>;
+
+    fn fhss_service(&mut self, _config: &HopConfig) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
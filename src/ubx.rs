@@ -0,0 +1,447 @@
+//! u-blox UBX binary protocol, for configuring u-blox GPS modules and reading NAV-PVT.
+//!
+//!  [0xB5, 0x62, class, id, length (2 bytes, little endian), payload ..., CK_A, CK_B]
+//!
+//!  The checksum is an 8 bit Fletcher checksum over class, id, length and payload.
+//!  Configuration uses the CFG-PRT, CFG-RATE, CFG-MSG, CFG-NAV5 and CFG-RXM messages, which are
+//!  supported by u-blox 6, 7 and 8 modules (eg NEO-6M, NEO-M8N). Each is acknowledged with
+//!  ACK-ACK, or ACK-NAK if the module does not accept it.
+//!  NAV-PVT (u-blox 8 and later) gives time, position, fix type and accuracy estimates in a single
+//!  message, rather than needing RMC, GGA and GSA.
+
+use heapless::Vec;
+use nb::block;
+use old_e_h::serial::{Read, Write};
+
+use crate::gateway::write_all;
+use crate::geodesy::Position;
+
+pub const SYNC: [u8; 2] = [0xB5, 0x62];
+
+// largest payload kept by the Decoder, NAV-PVT is 92 bytes. Longer messages are skipped.
+pub const MAX_PAYLOAD: usize = 100;
+pub const MAX_FRAME: usize = MAX_PAYLOAD + 8;
+
+// message class and id
+pub const NAV_PVT: (u8, u8) = (0x01, 0x07);
+pub const ACK_NAK: (u8, u8) = (0x05, 0x00);
+pub const ACK_ACK: (u8, u8) = (0x05, 0x01);
+pub const CFG_PRT: (u8, u8) = (0x06, 0x00);
+pub const CFG_MSG: (u8, u8) = (0x06, 0x01);
+pub const CFG_RATE: (u8, u8) = (0x06, 0x08);
+pub const CFG_RXM: (u8, u8) = (0x06, 0x11);
+pub const CFG_NAV5: (u8, u8) = (0x06, 0x24);
+
+// NMEA sentences, class 0xF0, that can be switched off with CFG-MSG
+pub const NMEA_GGA: (u8, u8) = (0xF0, 0x00);
+pub const NMEA_GLL: (u8, u8) = (0xF0, 0x01);
+pub const NMEA_GSA: (u8, u8) = (0xF0, 0x02);
+pub const NMEA_GSV: (u8, u8) = (0xF0, 0x03);
+pub const NMEA_RMC: (u8, u8) = (0xF0, 0x04);
+pub const NMEA_VTG: (u8, u8) = (0xF0, 0x05);
+
+// protocol masks for CFG-PRT
+pub const PROTO_UBX: u16 = 0x01;
+pub const PROTO_NMEA: u16 = 0x02;
+
+// bytes read while waiting for an acknowledgement, at least 1.04s at 9600 bps (960 bytes/s) and
+// longer when the GPS pauses between messages
+pub const ACK_BYTES: usize = 1000;
+
+// dynamic platform model (CFG-NAV5), limits the motion the receiver expects
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DynModel {
+    Portable = 0,
+    Stationary = 2,
+    Pedestrian = 3,
+    Automotive = 4,
+    Sea = 5,
+    Airborne1g = 6,
+    Airborne2g = 7,
+    Airborne4g = 8,
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 2] {
+    let (mut a, mut b) = (0u8, 0u8);
+    for v in bytes {
+        a = a.wrapping_add(*v);
+        b = b.wrapping_add(a);
+    }
+    [a, b]
+}
+
+// Write a complete message into out. Returns false (and out is incomplete) if it does not fit.
+pub fn encode<const N: usize>(out: &mut Vec<u8, N>, msg: (u8, u8), payload: &[u8]) -> bool {
+    out.clear();
+    let len = (payload.len() as u16).to_le_bytes();
+    let ok = out.extend_from_slice(&SYNC).is_ok()
+        && out
+            .extend_from_slice(&[msg.0, msg.1, len[0], len[1]])
+            .is_ok()
+        && out.extend_from_slice(payload).is_ok();
+    ok && out.extend_from_slice(&checksum(&out[2..])).is_ok()
+}
+
+// CFG-PRT for UART1, 8N1 at baud, with the protocols accepted and sent.
+// The module switches baud rate after acknowledging, so the usart must then be changed to match.
+pub fn cfg_prt_uart<const N: usize>(
+    out: &mut Vec<u8, N>,
+    baud: u32,
+    in_proto: u16,
+    out_proto: u16,
+) -> bool {
+    let mut p = [0u8; 20];
+    p[0] = 1; // UART1
+    p[4..8].copy_from_slice(&0x0000_08D0u32.to_le_bytes()); // 8 bits, no parity, 1 stop bit
+    p[8..12].copy_from_slice(&baud.to_le_bytes());
+    p[12..14].copy_from_slice(&in_proto.to_le_bytes());
+    p[14..16].copy_from_slice(&out_proto.to_le_bytes());
+    encode(out, CFG_PRT, &p)
+}
+
+// CFG-RATE, a navigation solution (and output) every period_ms
+pub fn cfg_rate<const N: usize>(out: &mut Vec<u8, N>, period_ms: u16) -> bool {
+    let mut p = [0u8; 6];
+    p[0..2].copy_from_slice(&period_ms.to_le_bytes());
+    p[2..4].copy_from_slice(&1u16.to_le_bytes()); // every measurement
+    p[4..6].copy_from_slice(&1u16.to_le_bytes()); // aligned to GPS time
+    encode(out, CFG_RATE, &p)
+}
+
+// CFG-MSG, send msg on the current port every rate solutions, 0 to switch it off
+pub fn cfg_msg<const N: usize>(out: &mut Vec<u8, N>, msg: (u8, u8), rate: u8) -> bool {
+    encode(out, CFG_MSG, &[msg.0, msg.1, rate])
+}
+
+// CFG-NAV5, setting only the dynamic platform model
+pub fn cfg_nav5<const N: usize>(out: &mut Vec<u8, N>, model: DynModel) -> bool {
+    let mut p = [0u8; 36];
+    p[0..2].copy_from_slice(&0x0001u16.to_le_bytes()); // mask, apply dynModel only
+    p[2] = model as u8;
+    encode(out, CFG_NAV5, &p)
+}
+
+// CFG-RXM, power save mode (cyclic tracking) or continuous (max performance)
+pub fn cfg_rxm<const N: usize>(out: &mut Vec<u8, N>, power_save: bool) -> bool {
+    encode(out, CFG_RXM, &[8, power_save as u8])
+}
+
+// A message received, payload borrowed from the Decoder
+pub struct Frame<'a> {
+    pub msg: (u8, u8),
+    pub payload: &'a [u8],
+}
+
+// Finds UBX messages in the bytes from the GPS, which may be mixed with NMEA lines.
+#[derive(Default)]
+pub struct Decoder {
+    buf: Vec<u8, MAX_FRAME>,
+    expect: usize,
+    done: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    // Add a byte. Returns a message when it is complete and the checksum is correct.
+    pub fn push(&mut self, byte: u8) -> Option<Frame<'_>> {
+        if self.done {
+            self.buf.clear();
+            self.done = false;
+        };
+
+        match self.buf.len() {
+            0 if byte != SYNC[0] => return None,
+            1 if byte != SYNC[1] => {
+                self.buf.clear();
+                if byte == SYNC[0] {
+                    let _ = self.buf.push(byte);
+                };
+                return None;
+            }
+            _ => (),
+        };
+        let _ = self.buf.push(byte); // cannot fail, length is checked below
+
+        if self.buf.len() == 6 {
+            let len = u16::from_le_bytes([self.buf[4], self.buf[5]]) as usize;
+            if len > MAX_PAYLOAD {
+                self.buf.clear(); // skip it, the sync bytes will be found again after it
+                return None;
+            };
+            self.expect = 6 + len + 2;
+        };
+
+        if self.buf.len() < 6 || self.buf.len() < self.expect {
+            return None;
+        };
+
+        self.done = true;
+        let n = self.expect;
+        if checksum(&self.buf[2..n - 2]) != self.buf[n - 2..n] {
+            return None;
+        };
+        Some(Frame {
+            msg: (self.buf[2], self.buf[3]),
+            payload: &self.buf[6..n - 2],
+        })
+    }
+}
+
+// Some(true) for ACK-ACK of msg, Some(false) for ACK-NAK, None for anything else.
+pub fn parse_ack(frame: &Frame, msg: (u8, u8)) -> Option<bool> {
+    if frame.payload != [msg.0, msg.1] {
+        return None;
+    };
+    match frame.msg {
+        ACK_ACK => Some(true),
+        ACK_NAK => Some(false),
+        _ => None,
+    }
+}
+
+// Write msg (a complete message from encode() or a cfg_ function) and wait for the acknowledgement.
+// Returns Some(true) if acknowledged, Some(false) if rejected and None if there was no answer
+// within ACK_BYTES bytes (eg not a u-blox module, or a different baud rate). feed is called for
+// each byte received, eg to feed the watchdog while the GPS is answering.
+pub fn command<T, R>(tx: &mut T, rx: &mut R, msg: &[u8], feed: &mut impl FnMut()) -> Option<bool>
+where
+    T: Write<u8>,
+    R: Read<u8>,
+{
+    write_all(tx, msg).ok()?;
+    wait_ack(rx, (*msg.get(2)?, *msg.get(3)?), feed)
+}
+
+// Wait for the acknowledgement of msg (class and id), as in command(). For CFG-PRT changing the
+// baud rate, which the module acknowledges at the new rate once it has switched.
+pub fn wait_ack<R>(rx: &mut R, msg: (u8, u8), feed: &mut impl FnMut()) -> Option<bool>
+where
+    R: Read<u8>,
+{
+    let mut decoder = Decoder::new();
+    for _ in 0..ACK_BYTES {
+        let byte = match block!(rx.read()) {
+            Ok(b) => b,
+            Err(_e) => continue, // eg overrun, keep looking
+        };
+        feed();
+        if let Some(ack) = decoder.push(byte).and_then(|f| parse_ack(&f, msg)) {
+            return Some(ack);
+        };
+    }
+    None
+}
+
+// Navigation solution from NAV-PVT. Units as sent, mm and 1e-7 degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NavPvt {
+    pub hour: u8,
+    pub min: u8,
+    pub sec: u8,
+    pub time_valid: bool,
+    pub fix_type: u8, // 0 no fix, 1 dead reckoning, 2 2D, 3 3D, 4 GNSS and dead reckoning, 5 time only
+    pub fix_ok: bool, // within the limits set (eg by CFG-NAV5), position not to be used otherwise
+    pub differential: bool,
+    pub satellites: u8,
+    pub lon: i32,
+    pub lat: i32,
+    pub height_msl: i32, // mm above mean sea level
+    pub h_acc: u32,      // mm, horizontal accuracy estimate
+    pub v_acc: u32,      // mm, vertical accuracy estimate
+    pub speed: i32,      // mm/s, ground speed
+    pub heading: i32,    // 1e-5 degrees, heading of motion
+    pub pdop: u16,       // 0.01
+}
+
+fn u32_at(p: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]])
+}
+
+fn i32_at(p: &[u8], i: usize) -> i32 {
+    u32_at(p, i) as i32
+}
+
+impl NavPvt {
+    pub fn parse(frame: &Frame) -> Option<Self> {
+        let p = frame.payload;
+        if frame.msg != NAV_PVT || p.len() < 92 {
+            return None;
+        };
+        Some(NavPvt {
+            hour: p[8],
+            min: p[9],
+            sec: p[10],
+            time_valid: p[11] & 0x02 != 0,
+            fix_type: p[20],
+            fix_ok: p[21] & 0x01 != 0,
+            differential: p[21] & 0x02 != 0,
+            satellites: p[23],
+            lon: i32_at(p, 24),
+            lat: i32_at(p, 28),
+            height_msl: i32_at(p, 36),
+            h_acc: u32_at(p, 40),
+            v_acc: u32_at(p, 44),
+            speed: i32_at(p, 60),
+            heading: i32_at(p, 64),
+            pdop: u16::from_le_bytes([p[76], p[77]]),
+        })
+    }
+
+    // UTC time of day in seconds, if the module has it
+    pub fn time(&self) -> Option<u32> {
+        if !self.time_valid {
+            return None;
+        };
+        Some(self.hour as u32 * 3600 + self.min as u32 * 60 + self.sec.min(59) as u32)
+    }
+
+    // position, if there is a usable fix (including dead reckoning)
+    pub fn position(&self) -> Option<Position> {
+        if !self.fix_ok || !(1..=4).contains(&self.fix_type) {
+            return None;
+        };
+        Some(Position::new(
+            self.lat as f64 * 1e-7,
+            self.lon as f64 * 1e-7,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CFG-RATE 1000ms and the ACK-ACK for CFG-PRT, from u-center
+    const RATE: [u8; 14] = [
+        0xB5, 0x62, 0x06, 0x08, 0x06, 0x00, 0xE8, 0x03, 0x01, 0x00, 0x01, 0x00, 0x01, 0x39,
+    ];
+    const ACK_PRT: [u8; 10] = [0xB5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x00, 0x0E, 0x37];
+
+    fn nav_pvt() -> Vec<u8, MAX_FRAME> {
+        let mut p = [0u8; 92];
+        p[8..11].copy_from_slice(&[3, 17, 37]);
+        p[11] = 0x07; // date, time and fully resolved
+        p[20] = 3;
+        p[21] = 0x03; // fix ok, differential
+        p[23] = 11;
+        p[24..28].copy_from_slice(&(-756_768_758i32).to_le_bytes());
+        p[28..32].copy_from_slice(&453_957_068i32.to_le_bytes());
+        p[36..40].copy_from_slice(&75_200i32.to_le_bytes());
+        p[40..44].copy_from_slice(&2_500u32.to_le_bytes());
+        p[76..78].copy_from_slice(&134u16.to_le_bytes());
+        let mut out = Vec::new();
+        assert!(encode(&mut out, NAV_PVT, &p));
+        out
+    }
+
+    #[test]
+    fn checksum_and_encode() {
+        assert_eq!(checksum(&RATE[2..12]), [0x01, 0x39]);
+        assert_eq!(checksum(&ACK_PRT[2..8]), [0x0E, 0x37]);
+        assert_eq!(checksum(&[]), [0, 0]);
+        // wraps rather than overflows
+        assert_eq!(checksum(&[0xFF; 3]), [0xFD, 0xFA]);
+
+        let mut out: Vec<u8, MAX_FRAME> = Vec::new();
+        assert!(cfg_rate(&mut out, 1000));
+        assert_eq!(&out[..], &RATE[..]);
+
+        let mut short: Vec<u8, 10> = Vec::new();
+        assert!(!cfg_rate(&mut short, 1000));
+    }
+
+    #[test]
+    fn decoder() {
+        let mut d = Decoder::new();
+        // NMEA and a stray sync byte before the message
+        let mut input: Vec<u8, 64> = Vec::new();
+        input.extend_from_slice(b"$GPRMC,,V*33\r\n").unwrap();
+        input.extend_from_slice(&[0xB5, 0xB5]).unwrap();
+        input.extend_from_slice(&ACK_PRT[1..]).unwrap();
+        let mut found = 0;
+        for b in input {
+            if let Some(f) = d.push(b) {
+                assert_eq!(parse_ack(&f, CFG_PRT), Some(true));
+                assert_eq!(parse_ack(&f, CFG_RATE), None);
+                found += 1;
+            };
+        }
+        assert_eq!(found, 1);
+
+        // a bad checksum is dropped, the next message is still found
+        let mut bad = ACK_PRT;
+        bad[9] ^= 1;
+        for b in bad.iter().chain(RATE.iter()) {
+            if let Some(f) = d.push(*b) {
+                assert_eq!((f.msg, f.payload), (CFG_RATE, &RATE[6..12]));
+                found += 1;
+            };
+        }
+        assert_eq!(found, 2);
+    }
+
+    #[test]
+    fn decoder_skips_long_messages() {
+        let mut d = Decoder::new();
+        let len = (MAX_PAYLOAD as u16 + 1).to_le_bytes();
+        for b in [0xB5, 0x62, 0x01, 0x35, len[0], len[1]] {
+            assert!(d.push(b).is_none());
+        }
+        let frame = nav_pvt();
+        let n = frame.len();
+        let mut got = None;
+        for b in frame {
+            got = d.push(b).map(|f| (f.msg, f.payload.len()));
+        }
+        assert_eq!(got, Some((NAV_PVT, n - 8)));
+    }
+
+    #[test]
+    fn nav_pvt_parse() {
+        let frame = nav_pvt();
+        let mut d = Decoder::new();
+        let mut pvt = None;
+        for b in frame {
+            if let Some(f) = d.push(b) {
+                pvt = NavPvt::parse(&f);
+            };
+        }
+        let pvt = pvt.unwrap();
+        assert_eq!(
+            (pvt.fix_type, pvt.fix_ok, pvt.differential),
+            (3, true, true)
+        );
+        assert_eq!(
+            (pvt.satellites, pvt.height_msl, pvt.h_acc),
+            (11, 75_200, 2_500)
+        );
+        assert_eq!(pvt.pdop, 134);
+        assert_eq!(pvt.time(), Some(3 * 3600 + 17 * 60 + 37));
+        let p = pvt.position().unwrap();
+        assert!((p.lat - 45.395_706_8).abs() < 1e-9);
+        assert!((p.lon + 75.676_875_8).abs() < 1e-9);
+
+        let no_fix = NavPvt {
+            fix_type: 0,
+            time_valid: false,
+            ..pvt
+        };
+        assert_eq!((no_fix.position(), no_fix.time()), (None, None));
+        let time_only = NavPvt { fix_type: 5, ..pvt };
+        assert_eq!(time_only.position(), None);
+
+        let ack = Frame {
+            msg: ACK_ACK,
+            payload: &[0; 92],
+        };
+        assert!(NavPvt::parse(&ack).is_none());
+        let short = Frame {
+            msg: NAV_PVT,
+            payload: &[0; 84],
+        };
+        assert!(NavPvt::parse(&short).is_none());
+    }
+}
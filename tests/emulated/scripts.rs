@@ -0,0 +1,48 @@
+// Scenarios played by the emulated board (src/emulated.rs), included there with feature lm3s6965.
+// They are the test data of the QEMU runs in CI (.github/workflows/CI.yml), so they live here
+// rather than in the firmware. EXPECTED_TX follows from the GEOFENCES in src/bin/send_gps.rs and
+// has to be changed with them.
+
+// GPS output played to send_gps: no fix, a good fix inside both geofences of send_gps, a good fix
+// outside them, a fix with too few satellites, the fix lost briefly and then for longer.
+pub const NMEA_SCRIPT: &[u8] = b"\
+$GPRMC,031700.00,V,,,,,,,300321,,,N*7B\r\n\
+$GPGGA,031737.00,4523.74241,N,07540.61255,W,1,08,0.90,70.1,M,-34.0,M,,*55\r\n\
+$GPGSA,A,3,04,05,09,12,17,20,25,29,,,,,1.50,0.90,1.20*0F\r\n\
+$GPRMC,031737.00,A,4523.74241,N,07540.61255,W,0.551,,300321,,,A*66\r\n\
+$GPGGA,031742.00,4530.00000,N,07540.00000,W,1,08,0.90,70.1,M,-34.0,M,,*54\r\n\
+$GPRMC,031742.00,A,4530.00000,N,07540.00000,W,0.551,,300321,,,A*67\r\n\
+$GPGGA,031747.00,4530.00000,N,07540.00000,W,1,03,0.90,70.1,M,-34.0,M,,*5A\r\n\
+$GPRMC,031747.00,A,4530.00000,N,07540.00000,W,0.551,,300321,,,A*62\r\n\
+$GPRMC,031752.00,V,,,,,,,300321,,,N*7C\r\n\
+$GPRMC,031900.00,V,,,,,,,300321,,,N*75\r\n";
+
+// Packets send_gps should transmit for NMEA_SCRIPT, in order (kind and payload, the header is not
+// otherwise checked). Alerts are repeated ALERT_REPEATS (3) times.
+pub const EXPECTED_TX: &[(Kind, &[u8])] = &[
+    (Kind::Status, b"no fix since start"),
+    (Kind::Data, b"4523.74241,N 07540.61255,W 03:17:37Z"),
+    (Kind::Alert, b"EXIT 00 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Alert, b"EXIT 00 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Alert, b"EXIT 00 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Alert, b"EXIT 01 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Alert, b"EXIT 01 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Alert, b"EXIT 01 4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Data, b"4530.00000,N 07540.00000,W 03:17:42Z"),
+    (Kind::Data, b"4530.00000,N 07540.00000,W 03:17:47Z SATS"),
+    (Kind::Status, b"no fix since 03:17:47Z"),
+];
+
+// Played to receive_spi. The bus errors make the supervisor re-initialise the radio, after which
+// the receiver must start_receive again to get the last packet.
+pub const RX_SCRIPT: &[RxEvent] = &[
+    RxEvent::Nothing,
+    RxEvent::Packet(Kind::Data, b"4523.74241,N 07540.61255,W 03:17:37Z"),
+    RxEvent::Crc,
+    RxEvent::Packet(Kind::Alert, b"EXIT 00 4530.00000,N 07540.00000,W 03:17:42Z"),
+    RxEvent::Text(b"hello from send_spi"),
+    RxEvent::Bus,
+    RxEvent::Bus,
+    RxEvent::Bus,
+    RxEvent::Packet(Kind::Status, b"no fix since 03:17:47Z"),
+];